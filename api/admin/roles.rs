@@ -0,0 +1,169 @@
+use rust_on_vercel_template::{
+    auth::{extract_bearer_token, verify_access_token},
+    email_verification::require_verified_email,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    rbac::{require_permission, RoleRepository, ROLES_MANAGE},
+    ApiError, MessageResponse, UserRoleRequest,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "admin/roles", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST and DELETE requests
+    if req.method() != "POST" && req.method() != "DELETE" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Require a verified email
+    if let Err(error) = require_verified_email(&claims) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Check permissions
+    if let Err(error) = require_permission(&claims, ROLES_MANAGE) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+    // Parse request body
+    let body = req.body();
+    let role_request: UserRoleRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    let user_id = match Uuid::parse_str(&role_request.user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let role_repo = RoleRepository::new(pool);
+
+    // Grant or revoke the role; it shows up in the user's tokens after their next refresh
+    let result = if req.method() == "POST" {
+        role_repo.assign_role(&user_id, &role_request.role).await
+    } else {
+        role_repo.remove_role(&user_id, &role_request.role).await
+    };
+
+    match result {
+        Ok(_) => {
+            let response = MessageResponse {
+                message: if req.method() == "POST" {
+                    "Role granted".to_string()
+                } else {
+                    "Role revoked".to_string()
+                },
+            };
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&response)?.into())?)
+        }
+        Err(e) => {
+            let error = ApiError {
+                message: e.to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
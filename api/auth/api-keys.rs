@@ -0,0 +1,257 @@
+use rust_on_vercel_template::{
+    api_keys::{validate_api_key_request, ApiKeyRepository},
+    auth::{extract_bearer_token, verify_access_token},
+    email_verification::require_verified_email,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, CreateApiKeyRequest, CreateApiKeyResponse, MessageResponse, RevokeApiKeyRequest,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/api-keys", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET, POST and DELETE requests
+    if req.method() != "GET" && req.method() != "POST" && req.method() != "DELETE" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Require a verified email
+    if let Err(error) = require_verified_email(&claims) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID in token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let api_key_repo = ApiKeyRepository::new(pool);
+
+    if req.method() == "GET" {
+        // List the user's keys
+        return match api_key_repo.list_api_keys(&user_id).await {
+            Ok(api_keys) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&api_keys)?.into())?),
+            Err(_) => {
+                let error = ApiError {
+                    message: "Database error".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+        };
+    }
+
+    if req.method() == "DELETE" {
+        // Parse request body
+        let body = req.body();
+        let revoke_request: RevokeApiKeyRequest = match serde_json::from_slice(body) {
+            Ok(req) => req,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid request body".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        let api_key_id = match Uuid::parse_str(&revoke_request.api_key_id) {
+            Ok(id) => id,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid API key ID".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        // Revoke the key so it stops working right away
+        return match api_key_repo.revoke_api_key(&user_id, &api_key_id).await {
+            Ok(true) => {
+                let response = MessageResponse {
+                    message: "API key revoked".to_string(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&response)?.into())?)
+            }
+            Ok(false) => {
+                let error = ApiError {
+                    message: "API key not found".to_string(),
+                    code: StatusCode::NOT_FOUND.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+            Err(_) => {
+                let error = ApiError {
+                    message: "Failed to revoke API key".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+        };
+    }
+
+    // Parse request body
+    let body = req.body();
+    let create_request: CreateApiKeyRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input; keys get at most the permissions of the token creating them
+    if let Err(message) = validate_api_key_request(&create_request, &claims.permissions) {
+        let error = ApiError {
+            message,
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // The key works in the organization that is active now
+    let organization_id = claims
+        .org
+        .as_deref()
+        .and_then(|id| Uuid::parse_str(id).ok());
+
+    // Create the key
+    match api_key_repo
+        .create_api_key(&user_id, organization_id.as_ref(), &create_request)
+        .await
+    {
+        Ok((api_key, key)) => {
+            let response = CreateApiKeyResponse { api_key, key };
+            Ok(Response::builder()
+                .status(StatusCode::CREATED)
+                .header("content-type", "application/json")
+                .header("cache-control", "no-store")
+                .body(serde_json::to_string(&response)?.into())?)
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to create API key".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
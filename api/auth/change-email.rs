@@ -0,0 +1,299 @@
+use rust_on_vercel_template::{
+    auth::{extract_bearer_token, is_valid_email, verify_access_token},
+    client_ip,
+    email_change::{send_email_change_confirmation, EmailChangeRepository},
+    login_attempts::{LoginAttemptRepository, LoginBlock, PasswordCheck},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, ChangeEmailRequest, MessageResponse, UserRepository,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/change-email", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Parse request body
+    let body = req.body();
+    let change_request: ChangeEmailRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    let new_email = change_request.new_email.trim().to_string();
+    if new_email.is_empty() || change_request.current_password.is_empty() {
+        let error = ApiError {
+            message: "New email and current password are required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    if !is_valid_email(&new_email) {
+        let error = ApiError {
+            message: "Invalid email address".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID in token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let attempt_repo = LoginAttemptRepository::new(pool.clone());
+    let email_change_repo = EmailChangeRepository::new(pool.clone());
+    let user_repo = UserRepository::new(pool);
+
+    // Get user from database
+    let user = match user_repo.get_user_by_id(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let error = ApiError {
+                message: "User not found".to_string(),
+                code: StatusCode::NOT_FOUND.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    if new_email == user.email {
+        let error = ApiError {
+            message: "This is already your email address".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Check the current password, under the same lockout as signin
+    match attempt_repo
+        .confirm_password(
+            &user.email,
+            &change_request.current_password,
+            &client_ip(&req),
+        )
+        .await
+    {
+        Ok(PasswordCheck::Confirmed) => {}
+        Ok(PasswordCheck::Incorrect) => {
+            let error = ApiError {
+                message: "Current password is incorrect".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Ok(PasswordCheck::Blocked(LoginBlock::AccountLocked {
+            retry_after_seconds,
+        })) => {
+            let error = ApiError {
+                message: "Account temporarily locked after too many failed attempts".to_string(),
+                code: StatusCode::LOCKED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::LOCKED)
+                .header("content-type", "application/json")
+                .header("retry-after", retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Ok(PasswordCheck::Blocked(LoginBlock::TooManyAttempts {
+            retry_after_seconds,
+        })) => {
+            let error = ApiError {
+                message: "Too many failed signin attempts, try again later".to_string(),
+                code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("content-type", "application/json")
+                .header("retry-after", retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    }
+
+    // The new address must not belong to another account
+    match user_repo.get_user_by_email(&new_email).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            let error = ApiError {
+                message: "Email address is already in use".to_string(),
+                code: StatusCode::CONFLICT.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::CONFLICT)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    }
+
+    // Send a confirmation link to the new address; nothing changes until it is used
+    let sent = match email_change_repo
+        .request_email_change(&user, &new_email)
+        .await
+    {
+        Ok(confirmation_token) => {
+            send_email_change_confirmation(&user, &new_email, &confirmation_token).await
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = sent {
+        eprintln!("Failed to send email change confirmation: {}", e);
+        let error = ApiError {
+            message: "Failed to send confirmation email".to_string(),
+            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let response = MessageResponse {
+        message: "Check your new email address for a confirmation link".to_string(),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&response)?.into())?)
+}
@@ -0,0 +1,121 @@
+use rust_on_vercel_template::{
+    audit::{AuditRepository, EMAIL_CHANGED},
+    email_change::{send_email_change_notice, EmailChangeRepository},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, ConfirmEmailChangeRequest, MessageResponse, UserRepository,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| {
+        rate_limited(
+            req,
+            "auth/change-email/confirm",
+            RateLimitPolicy::AUTH,
+            handler,
+        )
+    })
+    .await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let confirm_request: ConfirmEmailChangeRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if confirm_request.token.is_empty() {
+        let error = ApiError {
+            message: "Token is required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let email_change_repo = EmailChangeRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool.clone());
+    let user_repo = UserRepository::new(pool);
+
+    // Consume the confirmation token and switch the address
+    let change = match email_change_repo
+        .confirm_email_change(&confirm_request.token)
+        .await
+    {
+        Ok(change) => change,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired confirmation token".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    if let Err(e) = audit_repo
+        .record(
+            &change.user_id,
+            EMAIL_CHANGED,
+            &req,
+            json!({ "old_email": change.old_email, "new_email": change.new_email }),
+        )
+        .await
+    {
+        eprintln!("Failed to record audit event: {}", e);
+    }
+
+    // Let the old address know, with a way to reverse the change
+    let sent = match user_repo.get_user_by_id(&change.user_id).await {
+        Ok(Some(user)) => send_email_change_notice(&user.username, &change).await,
+        Ok(None) => Ok(()),
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = sent {
+        eprintln!("Failed to send email change notice: {}", e);
+    }
+
+    let response = MessageResponse {
+        message: "Email address updated. Sign in again to continue".to_string(),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&response)?.into())?)
+}
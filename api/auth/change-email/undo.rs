@@ -0,0 +1,120 @@
+use rust_on_vercel_template::{
+    audit::{AuditRepository, EMAIL_CHANGE_UNDONE},
+    email_change::EmailChangeRepository,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    revocation::TokenRevocationRepository,
+    ApiError, MessageResponse, UndoEmailChangeRequest,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| {
+        rate_limited(
+            req,
+            "auth/change-email/undo",
+            RateLimitPolicy::AUTH,
+            handler,
+        )
+    })
+    .await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let undo_request: UndoEmailChangeRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if undo_request.token.is_empty() {
+        let error = ApiError {
+            message: "Token is required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let email_change_repo = EmailChangeRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool.clone());
+    let revocation_repo = TokenRevocationRepository::new(pool);
+
+    // Switch back to the old address
+    let user_id = match email_change_repo
+        .undo_email_change(&undo_request.token)
+        .await
+    {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired undo token".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    if let Err(e) = audit_repo
+        .record(&user_id, EMAIL_CHANGE_UNDONE, &req, json!({}))
+        .await
+    {
+        eprintln!("Failed to record audit event: {}", e);
+    }
+
+    // Whoever made the change may still be signed in, so sign out every session
+    match revocation_repo.revoke_all_user_tokens(&user_id).await {
+        Ok(()) => {
+            let response = MessageResponse {
+                message: "Email change undone and all sessions signed out. Reset your password if you didn't make this change".to_string(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&response)?.into())?)
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to sign out sessions".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
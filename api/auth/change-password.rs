@@ -0,0 +1,303 @@
+use rust_on_vercel_template::{
+    audit::{AuditRepository, PASSWORD_CHANGED},
+    auth::{extract_bearer_token, issue_auth_response_in_organization, verify_access_token},
+    client_ip,
+    login_attempts::{LoginAttemptRepository, LoginBlock, PasswordCheck},
+    password_policy::PasswordPolicy,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, ChangePasswordRequest, PasswordPolicyError, UserRepository,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/change-password", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Parse request body
+    let body = req.body();
+    let change_request: ChangePasswordRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if change_request.current_password.is_empty() || change_request.new_password.is_empty() {
+        let error = ApiError {
+            message: "Current and new password are required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    if change_request.current_password == change_request.new_password {
+        let error = ApiError {
+            message: "New password must be different from the current one".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID in token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let attempt_repo = LoginAttemptRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool.clone());
+    let user_repo = UserRepository::new(pool.clone());
+
+    // Get user from database
+    let user = match user_repo.get_user_by_id(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let error = ApiError {
+                message: "User not found".to_string(),
+                code: StatusCode::NOT_FOUND.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Check the current password, under the same lockout as signin
+    match attempt_repo
+        .confirm_password(
+            &user.email,
+            &change_request.current_password,
+            &client_ip(&req),
+        )
+        .await
+    {
+        Ok(PasswordCheck::Confirmed) => {}
+        Ok(PasswordCheck::Incorrect) => {
+            let error = ApiError {
+                message: "Current password is incorrect".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Ok(PasswordCheck::Blocked(LoginBlock::AccountLocked {
+            retry_after_seconds,
+        })) => {
+            let error = ApiError {
+                message: "Account temporarily locked after too many failed attempts".to_string(),
+                code: StatusCode::LOCKED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::LOCKED)
+                .header("content-type", "application/json")
+                .header("retry-after", retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Ok(PasswordCheck::Blocked(LoginBlock::TooManyAttempts {
+            retry_after_seconds,
+        })) => {
+            let error = ApiError {
+                message: "Too many failed signin attempts, try again later".to_string(),
+                code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("content-type", "application/json")
+                .header("retry-after", retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    }
+
+    // Check the new password against the policy
+    let violations = PasswordPolicy::from_env().validate(
+        &change_request.new_password,
+        &user.email,
+        &user.username,
+    );
+    if !violations.is_empty() {
+        let error = PasswordPolicyError {
+            message: "Password does not meet the requirements".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+            violations,
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Update password, sign out every existing session and revoke API keys
+    if user_repo
+        .update_password(&user_id, &change_request.new_password)
+        .await
+        .is_err()
+    {
+        let error = ApiError {
+            message: "Failed to change password".to_string(),
+            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    if let Err(e) = audit_repo
+        .record(&user_id, PASSWORD_CHANGED, &req, json!({}))
+        .await
+    {
+        eprintln!("Failed to record audit event: {}", e);
+    }
+
+    // Keep the caller signed in with a fresh pair for the same app and organization
+    let organization_id = claims
+        .org
+        .as_deref()
+        .and_then(|org| Uuid::parse_str(org).ok());
+    match issue_auth_response_in_organization(
+        &pool,
+        &req,
+        user,
+        &claims.aud,
+        organization_id.as_ref(),
+    )
+    .await
+    {
+        Ok(response) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?),
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to generate token".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use rust_on_vercel_template::{
+    app_url,
+    mailer::{mailer_from_env, EmailMessage},
+    password_resets::PasswordResetRepository,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, ForgotPasswordRequest, MessageResponse, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/forgot-password", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let forgot_request: ForgotPasswordRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if forgot_request.email.is_empty() {
+        let error = ApiError {
+            message: "Email is required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let user_repo = UserRepository::new(pool.clone());
+    let reset_repo = PasswordResetRepository::new(pool);
+
+    // The response is the same whether or not the account exists, so this
+    // endpoint can't be used to discover registered email addresses.
+    let response = MessageResponse {
+        message: "If an account exists for that email, a password reset link has been sent"
+            .to_string(),
+    };
+
+    let user = match user_repo.get_user_by_email(&forgot_request.email).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Ok(Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&response)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Create reset token
+    let reset_token = match reset_repo.create_reset_token(&user.id).await {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to create reset token".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Send reset email
+    let message = EmailMessage {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nUse the link below to choose a new password. It expires soon and can only be used once.\n\n{}/reset-password?token={}\n\nIf you didn't ask for this, you can ignore this email.",
+            user.username,
+            app_url(),
+            reset_token
+        ),
+    };
+
+    let sent = match mailer_from_env() {
+        Ok(mailer) => mailer.send(&message).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = sent {
+        eprintln!("Failed to send password reset email: {}", e);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&response)?.into())?)
+}
@@ -0,0 +1,189 @@
+use rust_on_vercel_template::{
+    app_url,
+    auth::{generate_token, is_valid_email, resolve_audience},
+    magic_links::MagicLinkRepository,
+    mailer::{mailer_from_env, EmailMessage},
+    rate_limit::{email_rate_limit_subject, rate_limited, RateLimitPolicy, RateLimiter},
+    ApiError, MagicLinkRequest, MagicLinkResponse, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/magic-link", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let link_request: MagicLinkRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if link_request.email.is_empty() {
+        let error = ApiError {
+            message: "Email is required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    if !is_valid_email(&link_request.email) {
+        let error = ApiError {
+            message: "Invalid email address".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Resolve which app the tokens are for
+    let audience = match resolve_audience(link_request.audience.as_deref()) {
+        Ok(audience) => audience,
+        Err(_) => {
+            let error = ApiError {
+                message: "Unknown audience".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Hold back repeated requests for one address, since each new link cancels the last.
+    // The limit applies whether or not the account exists, so it gives nothing away.
+    match RateLimiter::new(pool.clone())
+        .check(
+            &RateLimitPolicy::EMAIL,
+            &email_rate_limit_subject(&link_request.email),
+        )
+        .await
+    {
+        Ok(Some(decision)) if !decision.allowed => {
+            let error = ApiError {
+                message: "Too many sign-in links requested for this email, try again later"
+                    .to_string(),
+                code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("content-type", "application/json")
+                .header("retry-after", decision.retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to check email rate limit: {}", e),
+    }
+
+    let user_repo = UserRepository::new(pool.clone());
+    let link_repo = MagicLinkRepository::new(pool);
+
+    // The response looks the same whether or not the account exists, down to the device
+    // nonce, and either way an email is sent, so neither the body nor the time it takes
+    // tells whether an email address is registered
+    let (email, device_nonce) = match user_repo.get_user_by_email(&link_request.email).await {
+        Ok(Some(user)) => {
+            // Create the sign-in link
+            let pending = match link_repo.create_magic_link(&user, &audience).await {
+                Ok(pending) => pending,
+                Err(_) => {
+                    let error = ApiError {
+                        message: "Failed to create sign-in link".to_string(),
+                        code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    };
+                    return Ok(Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .header("content-type", "application/json")
+                        .body(serde_json::to_string(&error)?.into())?);
+                }
+            };
+
+            let email = EmailMessage {
+                to: user.email.clone(),
+                subject: "Your sign-in link".to_string(),
+                body: format!(
+                    "Hi {},\n\nUse the link below to sign in. It expires soon, can only be used once, and only works in the browser you asked for it from.\n\n{}/magic-link?token={}\n\nIf you didn't ask for this, you can ignore this email.",
+                    user.username,
+                    app_url(),
+                    pending.token
+                ),
+            };
+            (email, pending.device_nonce)
+        }
+        Ok(None) => {
+            let email = EmailMessage {
+                to: link_request.email.clone(),
+                subject: "Your sign-in link".to_string(),
+                body: format!(
+                    "Hi,\n\nSomeone asked for a link to sign in with this email address, but there is no account for it. You can create one at {}.\n\nIf you didn't ask for this, you can ignore this email.",
+                    app_url()
+                ),
+            };
+            (email, generate_token())
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Send the email
+    let sent = match mailer_from_env() {
+        Ok(mailer) => mailer.send(&email).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = sent {
+        eprintln!("Failed to send sign-in link email: {}", e);
+    }
+
+    let response = MagicLinkResponse {
+        message: "If an account exists for that email, a sign-in link has been sent".to_string(),
+        device_nonce,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&response)?.into())?)
+}
@@ -0,0 +1,176 @@
+use rust_on_vercel_template::{
+    account::record_signin,
+    auth::{create_mfa_challenge_token, issue_auth_response, mfa_challenge_ttl},
+    magic_links::MagicLinkRepository,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, MagicLinkVerifyRequest, MfaChallengeResponse, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| {
+        rate_limited(
+            req,
+            "auth/magic-link/verify",
+            RateLimitPolicy::AUTH,
+            handler,
+        )
+    })
+    .await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let verify_request: MagicLinkVerifyRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if verify_request.token.is_empty() || verify_request.device_nonce.is_empty() {
+        let error = ApiError {
+            message: "Token and device nonce are required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Use up the link, which only works from the device that asked for it
+    let (user_id, audience) = match MagicLinkRepository::new(pool.clone())
+        .consume_magic_link(&verify_request.token, &verify_request.device_nonce)
+        .await
+    {
+        Ok(link) => link,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired sign-in link".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let user_repo = UserRepository::new(pool.clone());
+
+    // Get user from database
+    let user = match user_repo.get_user_by_id(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let error = ApiError {
+                message: "User not found".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // The link stands in for the password only; users with MFA enabled must still
+    // exchange a challenge token at /api/auth/mfa/verify or /api/auth/passkeys/authenticate
+    let mfa_methods = match user_repo.mfa_methods(&user.id).await {
+        Ok(methods) => methods,
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    if !mfa_methods.is_empty() {
+        let mfa_token = match create_mfa_challenge_token(&user.id, &audience) {
+            Ok(token) => token,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Failed to generate token".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        let response = MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+            expires_in: mfa_challenge_ttl().num_seconds() as usize,
+            methods: mfa_methods,
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?);
+    }
+
+    // Signing in cancels a pending account deletion
+    record_signin(&pool, &user.id, &req, &audience).await;
+
+    // Issue access and refresh tokens
+    match issue_auth_response(&pool, &req, user, &audience).await {
+        Ok(response) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?),
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to generate token".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
@@ -1,22 +1,29 @@
 use rust_on_vercel_template::{
-    auth::{extract_bearer_token, verify_jwt},
-    create_pool, ApiError, UserRepository,
+    api_keys::extract_api_key,
+    audit::{AuditRepository, ACCOUNT_DELETION_REQUESTED},
+    auth::{authenticate_request, require_recent_auth},
+    client_ip,
+    login_attempts::{LoginAttemptRepository, LoginBlock, PasswordCheck},
+    profile::validate_profile_update,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, DeleteAccountRequest, DeleteAccountResponse, UpdateProfileRequest, UserRepository,
 };
-use serde_json;
+use serde_json::json;
+use sqlx::PgPool;
 use uuid::Uuid;
 use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    run(handler).await
+    run(|req| rate_limited(req, "auth/me", RateLimitPolicy::DEFAULT, handler)).await
 }
 
-pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
     // Load environment variables
     dotenvy::dotenv().ok();
 
-    // Only allow GET requests
-    if req.method() != "GET" {
+    // Only allow GET, PATCH and DELETE requests
+    if req.method() != "GET" && req.method() != "PATCH" && req.method() != "DELETE" {
         let error = ApiError {
             message: "Method not allowed".to_string(),
             code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
@@ -27,26 +34,10 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
             .body(serde_json::to_string(&error)?.into())?);
     }
 
-    // Get authorization header
-    let auth_header = match req.headers().get("authorization") {
-        Some(header) => match header.to_str() {
-            Ok(h) => h,
-            Err(_) => {
-                let error = ApiError {
-                    message: "Invalid authorization header".to_string(),
-                    code: StatusCode::UNAUTHORIZED.as_u16(),
-                };
-                return Ok(Response::builder()
-                    .status(StatusCode::UNAUTHORIZED)
-                    .header("content-type", "application/json")
-                    .body(serde_json::to_string(&error)?.into())?);
-            }
-        },
-        None => {
-            let error = ApiError {
-                message: "Authorization header required".to_string(),
-                code: StatusCode::UNAUTHORIZED.as_u16(),
-            };
+    // Authenticate with an access token or API key
+    let claims = match authenticate_request(&pool, &req).await {
+        Ok(claims) => claims,
+        Err(error) => {
             return Ok(Response::builder()
                 .status(StatusCode::UNAUTHORIZED)
                 .header("content-type", "application/json")
@@ -54,12 +45,14 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         }
     };
 
-    // Extract token from Bearer header
-    let token = match extract_bearer_token(auth_header) {
-        Ok(token) => token,
+    let user_repo = UserRepository::new(pool.clone());
+
+    // Get user from database
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
         Err(_) => {
             let error = ApiError {
-                message: "Invalid authorization header format".to_string(),
+                message: "Invalid user ID in token".to_string(),
                 code: StatusCode::UNAUTHORIZED.as_u16(),
             };
             return Ok(Response::builder()
@@ -69,54 +62,255 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         }
     };
 
-    // Verify JWT token
-    let claims = match verify_jwt(token) {
-        Ok(claims) => claims,
-        Err(_) => {
-            let error = ApiError {
-                message: "Invalid or expired token".to_string(),
-                code: StatusCode::UNAUTHORIZED.as_u16(),
-            };
-            return Ok(Response::builder()
-                .status(StatusCode::UNAUTHORIZED)
+    if req.method() == "GET" {
+        return match user_repo.get_user_by_id(&user_id).await {
+            Ok(Some(user)) => Ok(Response::builder()
+                .status(StatusCode::OK)
                 .header("content-type", "application/json")
-                .body(serde_json::to_string(&error)?.into())?);
+                .body(serde_json::to_string(&user)?.into())?),
+            Ok(None) => {
+                let error = ApiError {
+                    message: "User not found".to_string(),
+                    code: StatusCode::NOT_FOUND.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+            Err(_) => {
+                let error = ApiError {
+                    message: "Database error".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+        };
+    }
+
+    // API keys can read the account but not change it
+    if extract_api_key(&req).is_some() {
+        let error = ApiError {
+            message: "API keys cannot change the account".to_string(),
+            code: StatusCode::FORBIDDEN.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Deleting the account needs the password or a fresh sign-in, and is undone by signing in
+    // before the purge
+    if req.method() == "DELETE" {
+        // Parse request body
+        let delete_request: DeleteAccountRequest = match serde_json::from_slice(req.body()) {
+            Ok(req) => req,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid request body".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        let audit_repo = AuditRepository::new(pool.clone());
+        let attempt_repo = LoginAttemptRepository::new(pool);
+
+        // Confirm it's the user with the password, under the same lockout as signin, or
+        // with a sign-in made moments ago for accounts that have no password
+        match delete_request.password.as_deref() {
+            Some(password) => {
+                let email = match user_repo.get_user_by_id(&user_id).await {
+                    Ok(Some(user)) => user.email,
+                    Ok(None) => {
+                        let error = ApiError {
+                            message: "User not found".to_string(),
+                            code: StatusCode::NOT_FOUND.as_u16(),
+                        };
+                        return Ok(Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .header("content-type", "application/json")
+                            .body(serde_json::to_string(&error)?.into())?);
+                    }
+                    Err(_) => {
+                        let error = ApiError {
+                            message: "Database error".to_string(),
+                            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        };
+                        return Ok(Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .header("content-type", "application/json")
+                            .body(serde_json::to_string(&error)?.into())?);
+                    }
+                };
+                match attempt_repo
+                    .confirm_password(&email, password, &client_ip(&req))
+                    .await
+                {
+                    Ok(PasswordCheck::Confirmed) => {}
+                    Ok(PasswordCheck::Incorrect) => {
+                        let error = ApiError {
+                            message: "Password is incorrect".to_string(),
+                            code: StatusCode::UNAUTHORIZED.as_u16(),
+                        };
+                        return Ok(Response::builder()
+                            .status(StatusCode::UNAUTHORIZED)
+                            .header("content-type", "application/json")
+                            .body(serde_json::to_string(&error)?.into())?);
+                    }
+                    Ok(PasswordCheck::Blocked(LoginBlock::AccountLocked {
+                        retry_after_seconds,
+                    })) => {
+                        let error = ApiError {
+                            message: "Account temporarily locked after too many failed attempts"
+                                .to_string(),
+                            code: StatusCode::LOCKED.as_u16(),
+                        };
+                        return Ok(Response::builder()
+                            .status(StatusCode::LOCKED)
+                            .header("content-type", "application/json")
+                            .header("retry-after", retry_after_seconds.to_string())
+                            .body(serde_json::to_string(&error)?.into())?);
+                    }
+                    Ok(PasswordCheck::Blocked(LoginBlock::TooManyAttempts {
+                        retry_after_seconds,
+                    })) => {
+                        let error = ApiError {
+                            message: "Too many failed signin attempts, try again later".to_string(),
+                            code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                        };
+                        return Ok(Response::builder()
+                            .status(StatusCode::TOO_MANY_REQUESTS)
+                            .header("content-type", "application/json")
+                            .header("retry-after", retry_after_seconds.to_string())
+                            .body(serde_json::to_string(&error)?.into())?);
+                    }
+                    Err(_) => {
+                        let error = ApiError {
+                            message: "Database error".to_string(),
+                            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        };
+                        return Ok(Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .header("content-type", "application/json")
+                            .body(serde_json::to_string(&error)?.into())?);
+                    }
+                }
+            }
+            None => {
+                if let Err(error) = require_recent_auth(&claims) {
+                    return Ok(Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .header("content-type", "application/json")
+                        .body(serde_json::to_string(&error)?.into())?);
+                }
+            }
         }
-    };
 
-    // Connect to database
-    let pool = match create_pool().await {
-        Ok(pool) => pool,
-        Err(_) => {
-            let error = ApiError {
-                message: "Database connection failed".to_string(),
-                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-            };
-            return Ok(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .header("content-type", "application/json")
-                .body(serde_json::to_string(&error)?.into())?);
+        // Schedule the deletion and sign out every session
+        let purge_after = match user_repo.schedule_account_deletion(&user_id).await {
+            Ok(purge_after) => purge_after,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Failed to delete account".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        if let Err(e) = audit_repo
+            .record(
+                &user_id,
+                ACCOUNT_DELETION_REQUESTED,
+                &req,
+                json!({ "purge_after": purge_after }),
+            )
+            .await
+        {
+            eprintln!("Failed to record audit event: {}", e);
         }
-    };
 
-    let user_repo = UserRepository::new(pool);
+        let response = DeleteAccountResponse {
+            message: "Account scheduled for deletion. Sign in again before it is purged to keep it"
+                .to_string(),
+            purge_after,
+        };
 
-    // Get user from database
-    let user_id = match Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
+        return Ok(Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let update_request: UpdateProfileRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
         Err(_) => {
             let error = ApiError {
-                message: "Invalid user ID in token".to_string(),
-                code: StatusCode::UNAUTHORIZED.as_u16(),
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
             };
             return Ok(Response::builder()
-                .status(StatusCode::UNAUTHORIZED)
+                .status(StatusCode::BAD_REQUEST)
                 .header("content-type", "application/json")
                 .body(serde_json::to_string(&error)?.into())?);
         }
     };
 
-    match user_repo.get_user_by_id(&user_id).await {
+    // Validate input
+    if let Err(message) = validate_profile_update(&update_request) {
+        let error = ApiError {
+            message,
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Usernames are unique
+    if let Some(username) = update_request.username.as_deref().map(str::trim) {
+        match user_repo.get_user_by_username(username).await {
+            Ok(Some(other)) if other.id != user_id => {
+                let error = ApiError {
+                    message: "Username is already taken".to_string(),
+                    code: StatusCode::CONFLICT.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::CONFLICT)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+            Ok(_) => {}
+            Err(_) => {
+                let error = ApiError {
+                    message: "Database error".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        }
+    }
+
+    // Update profile
+    match user_repo.update_profile(&user_id, &update_request).await {
         Ok(Some(user)) => Ok(Response::builder()
             .status(StatusCode::OK)
             .header("content-type", "application/json")
@@ -133,7 +327,7 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         }
         Err(_) => {
             let error = ApiError {
-                message: "Database error".to_string(),
+                message: "Failed to update profile".to_string(),
                 code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
             };
             Ok(Response::builder()
@@ -0,0 +1,153 @@
+use rust_on_vercel_template::{
+    audit::{AuditRepository, DATA_EXPORTED},
+    auth::{extract_bearer_token, verify_access_token},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, UserRepository,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/me/export", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET requests
+    if req.method() != "GET" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID in token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let audit_repo = AuditRepository::new(pool.clone());
+    let user_repo = UserRepository::new(pool);
+
+    // Record the export first, so it shows up in the archive itself
+    if let Err(e) = audit_repo
+        .record(&user_id, DATA_EXPORTED, &req, json!({}))
+        .await
+    {
+        eprintln!("Failed to record audit event: {}", e);
+    }
+
+    // Collect everything stored about the user
+    match user_repo.export_account(&user_id).await {
+        Ok(Some(export)) => {
+            let filename = format!(
+                "account-export-{}.json",
+                export.exported_at.format("%Y-%m-%d")
+            );
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .header(
+                    "content-disposition",
+                    format!("attachment; filename=\"{}\"", filename),
+                )
+                .header("cache-control", "no-store")
+                .body(serde_json::to_string_pretty(&export)?.into())?)
+        }
+        Ok(None) => {
+            let error = ApiError {
+                message: "User not found".to_string(),
+                code: StatusCode::NOT_FOUND.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to export account data".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use rust_on_vercel_template::{
+    account::record_signin,
+    auth::{consume_mfa_challenge_token, issue_auth_response, verify_mfa_challenge_token},
+    login_attempts::{LoginAttemptRepository, LoginBlock},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, MfaVerifyRequest, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/mfa/verify", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let verify_request: MfaVerifyRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if verify_request.mfa_token.is_empty() || verify_request.code.is_empty() {
+        let error = ApiError {
+            message: "MFA token and code are required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Verify the challenge token issued by signin
+    let (user_id, audience) = match verify_mfa_challenge_token(&verify_request.mfa_token) {
+        Ok(challenge) => challenge,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired MFA token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+    let user_repo = UserRepository::new(pool.clone());
+    let attempt_repo = LoginAttemptRepository::new(pool.clone());
+
+    // Refuse early while too many wrong codes have been entered for this user
+    match attempt_repo.check_mfa(&user_id).await {
+        Ok(None) => {}
+        Ok(Some(LoginBlock::AccountLocked {
+            retry_after_seconds,
+        }))
+        | Ok(Some(LoginBlock::TooManyAttempts {
+            retry_after_seconds,
+        })) => {
+            let error = ApiError {
+                message:
+                    "Two-factor authentication temporarily locked after too many failed attempts"
+                        .to_string(),
+                code: StatusCode::LOCKED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::LOCKED)
+                .header("content-type", "application/json")
+                .header("retry-after", retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    }
+
+    // Check the TOTP or recovery code
+    if user_repo
+        .verify_mfa_code(&user_id, &verify_request.code)
+        .await
+        .is_err()
+    {
+        if let Err(e) = attempt_repo.record_mfa_failure(&user_id).await {
+            eprintln!("Failed to record MFA failure: {}", e);
+        }
+
+        let error = ApiError {
+            message: "Invalid code".to_string(),
+            code: StatusCode::UNAUTHORIZED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Use up the challenge so it cannot finish another signin
+    if consume_mfa_challenge_token(&pool, &verify_request.mfa_token)
+        .await
+        .is_err()
+    {
+        let error = ApiError {
+            message: "Invalid or expired MFA token".to_string(),
+            code: StatusCode::UNAUTHORIZED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    if let Err(e) = attempt_repo.record_mfa_success(&user_id).await {
+        eprintln!("Failed to clear MFA failures: {}", e);
+    }
+
+    // Get user from database
+    let user = match user_repo.get_user_by_id(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let error = ApiError {
+                message: "User not found".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // The second factor completes the signin, so earlier password failures no longer count
+    if let Err(e) = attempt_repo.record_success(&user.email).await {
+        eprintln!("Failed to reset login attempts: {}", e);
+    }
+
+    // Signing in cancels a pending account deletion
+    record_signin(&pool, &user.id, &req, &audience).await;
+
+    // Issue access and refresh tokens
+    match issue_auth_response(&pool, &req, user, &audience).await {
+        Ok(response) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?),
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to generate token".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
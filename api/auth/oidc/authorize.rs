@@ -0,0 +1,138 @@
+use rust_on_vercel_template::{
+    auth::resolve_audience,
+    oidc::{oidc_redirect_uri, OidcProvider, OidcRepository},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, OidcAuthorizeRequest, OidcAuthorizeResponse,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/oidc/authorize", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let authorize_request: OidcAuthorizeRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    let provider = match OidcProvider::from_env(&authorize_request.provider) {
+        Ok(provider) => provider,
+        Err(_) => {
+            let error = ApiError {
+                message: "Unknown identity provider".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let audience = match resolve_audience(authorize_request.audience.as_deref()) {
+        Ok(audience) => audience,
+        Err(_) => {
+            let error = ApiError {
+                message: "Unknown audience".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Look up the provider's endpoints
+    let metadata = match provider.discover().await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            eprintln!("OIDC discovery for {} failed: {}", provider.name, e);
+            let error = ApiError {
+                message: "Identity provider unavailable".to_string(),
+                code: StatusCode::BAD_GATEWAY.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let oidc_repo = OidcRepository::new(pool);
+    let redirect_uri = oidc_redirect_uri();
+
+    // Remember the state, nonce and PKCE verifier for the callback
+    let pending = match oidc_repo
+        .create_login_state(&provider.name, &audience, &redirect_uri)
+        .await
+    {
+        Ok(pending) => pending,
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to start sign in".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    match provider.authorization_url(
+        &metadata,
+        &redirect_uri,
+        &pending.state,
+        &pending.nonce,
+        &pending.code_verifier,
+    ) {
+        Ok(authorization_url) => {
+            let response = OidcAuthorizeResponse { authorization_url };
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&response)?.into())?)
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Identity provider unavailable".to_string(),
+                code: StatusCode::BAD_GATEWAY.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
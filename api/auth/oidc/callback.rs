@@ -0,0 +1,267 @@
+use rust_on_vercel_template::{
+    account::record_signin,
+    audit::{AuditRepository, ACCOUNT_CREATED},
+    auth::{create_mfa_challenge_token, issue_auth_response, mfa_challenge_ttl},
+    email_verification::UnverifiedSigninPolicy,
+    oidc::{OidcProvider, OidcRepository},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, MfaChallengeResponse, OidcCallbackRequest, UserRepository,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/oidc/callback", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let callback_request: OidcCallbackRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if callback_request.state.is_empty() || callback_request.code.is_empty() {
+        let error = ApiError {
+            message: "State and code are required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let oidc_repo = OidcRepository::new(pool.clone());
+    let user_repo = UserRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool.clone());
+
+    // The state ties the callback to a sign-in started here, and can only be used once
+    let login_state = match oidc_repo.take_login_state(&callback_request.state).await {
+        Ok(Some(login_state)) => login_state,
+        Ok(None) => {
+            let error = ApiError {
+                message: "Invalid or expired sign in, please start again".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Redeem the code and verify the ID token it returns
+    let provider = match OidcProvider::from_env(&login_state.provider) {
+        Ok(provider) => provider,
+        Err(_) => {
+            let error = ApiError {
+                message: "Unknown identity provider".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let id_token_claims = match async {
+        let metadata = provider.discover().await?;
+        let id_token = provider
+            .exchange_code(
+                &metadata,
+                &callback_request.code,
+                &login_state.code_verifier,
+                &login_state.redirect_uri,
+            )
+            .await?;
+        provider
+            .verify_id_token(&metadata, &id_token, &login_state.nonce)
+            .await
+    }
+    .await
+    {
+        Ok(claims) => claims,
+        Err(e) => {
+            eprintln!("OIDC sign in with {} failed: {}", provider.name, e);
+            let error = ApiError {
+                message: "Sign in with the identity provider failed".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Find the linked user, linking or creating an account on first sign in
+    let resolved = match oidc_repo
+        .find_or_create_user(&provider.name, &id_token_claims)
+        .await
+    {
+        Ok(Some(resolved)) => resolved,
+        Ok(None) if !id_token_claims.email_verified => {
+            let error = ApiError {
+                message: "The identity provider has not verified your email address".to_string(),
+                code: StatusCode::FORBIDDEN.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Ok(None) => {
+            let error = ApiError {
+                message: "An account with this email already exists. Sign in with your password"
+                    .to_string(),
+                code: StatusCode::CONFLICT.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::CONFLICT)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to sign in".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+    let user = resolved.user;
+    let audience = login_state.audience;
+
+    if resolved.created {
+        if let Err(e) = audit_repo
+            .record(
+                &user.id,
+                ACCOUNT_CREATED,
+                &req,
+                json!({ "provider": provider.name }),
+            )
+            .await
+        {
+            eprintln!("Failed to record audit event: {}", e);
+        }
+    }
+
+    // Enforce the unverified email policy
+    if !UnverifiedSigninPolicy::from_env().allows_signin(&user) {
+        let error = ApiError {
+            message: "Email address not verified".to_string(),
+            code: StatusCode::FORBIDDEN.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Users with MFA enabled must exchange a challenge token at /api/auth/mfa/verify,
+    // or /api/auth/passkeys/authenticate with a passkey
+    let mfa_methods = match user_repo.mfa_methods(&user.id).await {
+        Ok(methods) => methods,
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    if !mfa_methods.is_empty() {
+        let mfa_token = match create_mfa_challenge_token(&user.id, &audience) {
+            Ok(token) => token,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Failed to generate token".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        let response = MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+            expires_in: mfa_challenge_ttl().num_seconds() as usize,
+            methods: mfa_methods,
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?);
+    }
+
+    // Signing in cancels a pending account deletion
+    record_signin(&pool, &user.id, &req, &audience).await;
+
+    // Issue access and refresh tokens
+    match issue_auth_response(&pool, &req, user, &audience).await {
+        Ok(response) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?),
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to generate token".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use rust_on_vercel_template::{
+    audit::{AuditRepository, PASSKEY_ADDED, PASSKEY_REMOVED},
+    auth::{extract_bearer_token, require_recent_auth, verify_access_token},
+    passkeys::{validate_passkey_name, PasskeyRepository},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, MessageResponse, RegisterPasskeyRequest, RemovePasskeyRequest,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/passkeys", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET, POST and DELETE requests
+    if req.method() != "GET" && req.method() != "POST" && req.method() != "DELETE" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID in token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let passkey_repo = PasskeyRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool);
+
+    if req.method() == "GET" {
+        // List the user's passkeys
+        return match passkey_repo.list_passkeys(&user_id).await {
+            Ok(passkeys) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&passkeys)?.into())?),
+            Err(_) => {
+                let error = ApiError {
+                    message: "Database error".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+        };
+    }
+
+    // Adding or removing a passkey changes how the account signs in, so it needs a recent sign-in
+    if let Err(error) = require_recent_auth(&claims) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    if req.method() == "DELETE" {
+        // Parse request body
+        let body = req.body();
+        let remove_request: RemovePasskeyRequest = match serde_json::from_slice(body) {
+            Ok(req) => req,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid request body".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        let passkey_id = match Uuid::parse_str(&remove_request.passkey_id) {
+            Ok(id) => id,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid passkey ID".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        // Remove the passkey so it can no longer sign in
+        return match passkey_repo.remove_passkey(&user_id, &passkey_id).await {
+            Ok(true) => {
+                if let Err(e) = audit_repo
+                    .record(
+                        &user_id,
+                        PASSKEY_REMOVED,
+                        &req,
+                        json!({ "passkey_id": passkey_id }),
+                    )
+                    .await
+                {
+                    eprintln!("Failed to record audit event: {}", e);
+                }
+
+                let response = MessageResponse {
+                    message: "Passkey removed".to_string(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&response)?.into())?)
+            }
+            Ok(false) => {
+                let error = ApiError {
+                    message: "Passkey not found".to_string(),
+                    code: StatusCode::NOT_FOUND.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+            Err(_) => {
+                let error = ApiError {
+                    message: "Failed to remove passkey".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+        };
+    }
+
+    // Parse request body
+    let body = req.body();
+    let register_request: RegisterPasskeyRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if let Err(message) = validate_passkey_name(register_request.name.as_deref()) {
+        let error = ApiError {
+            message,
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Verify the new credential against the challenge from registration-options and store it
+    match passkey_repo
+        .register_passkey(
+            &user_id,
+            register_request.name.as_deref(),
+            &register_request.credential,
+        )
+        .await
+    {
+        Ok(passkey) => {
+            if let Err(e) = audit_repo
+                .record(
+                    &user_id,
+                    PASSKEY_ADDED,
+                    &req,
+                    json!({ "passkey_id": passkey.id, "name": passkey.name }),
+                )
+                .await
+            {
+                eprintln!("Failed to record audit event: {}", e);
+            }
+
+            Ok(Response::builder()
+                .status(StatusCode::CREATED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&passkey)?.into())?)
+        }
+        Err(e) => {
+            eprintln!("Failed to register passkey: {}", e);
+            let error = ApiError {
+                message: "Passkey could not be registered".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
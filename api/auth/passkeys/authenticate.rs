@@ -0,0 +1,148 @@
+use rust_on_vercel_template::{
+    account::record_signin,
+    auth::issue_auth_response,
+    email_verification::UnverifiedSigninPolicy,
+    login_attempts::LoginAttemptRepository,
+    passkeys::PasskeyRepository,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, PasskeyAuthenticationRequest, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| {
+        rate_limited(
+            req,
+            "auth/passkeys/authenticate",
+            RateLimitPolicy::AUTH,
+            handler,
+        )
+    })
+    .await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let authentication_request: PasskeyAuthenticationRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Check the assertion against the challenge from authentication-options. A passkey that
+    // verified the user counts as both factors, so no MFA challenge follows.
+    let signin = match PasskeyRepository::new(pool.clone())
+        .authenticate(&authentication_request.credential)
+        .await
+    {
+        Ok(signin) => signin,
+        Err(e) => {
+            eprintln!("Passkey sign in failed: {}", e);
+            let error = ApiError {
+                message: "Invalid passkey".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Get user from database
+    let user = match UserRepository::new(pool.clone())
+        .get_user_by_id(&signin.user_id)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let error = ApiError {
+                message: "User not found".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Enforce the unverified email policy; as a second factor signin already did
+    if !signin.second_factor && !UnverifiedSigninPolicy::from_env().allows_signin(&user) {
+        let error = ApiError {
+            message: "Email address not verified".to_string(),
+            code: StatusCode::FORBIDDEN.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // As a second factor the passkey completes a password signin, whose failures no longer count
+    if signin.second_factor {
+        if let Err(e) = LoginAttemptRepository::new(pool.clone())
+            .record_success(&user.email)
+            .await
+        {
+            eprintln!("Failed to reset login attempts: {}", e);
+        }
+    }
+
+    // Signing in cancels a pending account deletion
+    record_signin(&pool, &user.id, &req, &signin.audience).await;
+
+    // Issue access and refresh tokens
+    match issue_auth_response(&pool, &req, user, &signin.audience).await {
+        Ok(response) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&response)?.into())?),
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to generate token".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
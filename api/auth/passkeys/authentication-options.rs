@@ -0,0 +1,108 @@
+use rust_on_vercel_template::{
+    auth::{resolve_audience, verify_mfa_challenge_token},
+    passkeys::PasskeyRepository,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, PasskeyAuthenticationOptionsRequest,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| {
+        rate_limited(
+            req,
+            "auth/passkeys/authentication-options",
+            RateLimitPolicy::AUTH,
+            handler,
+        )
+    })
+    .await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let options_request: PasskeyAuthenticationOptionsRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // As a second factor, the challenge token from signin names the user and the audience
+    let (mfa_user_id, audience) = match options_request.mfa_token.as_deref() {
+        Some(mfa_token) => match verify_mfa_challenge_token(mfa_token) {
+            Ok((user_id, audience)) => (Some(user_id), audience),
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid or expired MFA token".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        // Resolve which app the tokens are for
+        None => match resolve_audience(options_request.audience.as_deref()) {
+            Ok(audience) => (None, audience),
+            Err(_) => {
+                let error = ApiError {
+                    message: "Unknown audience".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+    };
+
+    // Issue a challenge for navigator.credentials.get(), answered at passkeys/authenticate
+    match PasskeyRepository::new(pool)
+        .authentication_options(mfa_user_id.as_ref(), &audience)
+        .await
+    {
+        Ok(options) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .header("cache-control", "no-store")
+            .body(serde_json::to_string(&options)?.into())?),
+        Err(e) => {
+            eprintln!("Failed to create passkey authentication options: {}", e);
+            let error = ApiError {
+                message: "Failed to start passkey sign in".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
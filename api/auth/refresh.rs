@@ -0,0 +1,185 @@
+use rust_on_vercel_template::{
+    auth::{access_token_ttl, create_jwt, resolve_audience},
+    email_verification::UnverifiedSigninPolicy,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    rbac::RoleRepository,
+    refresh_tokens::RefreshTokenRepository,
+    ApiError, AuthResponse, RefreshRequest, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/refresh", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let refresh_request: RefreshRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if refresh_request.refresh_token.is_empty() {
+        let error = ApiError {
+            message: "Refresh token is required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let refresh_repo = RefreshTokenRepository::new(pool.clone());
+    let user_repo = UserRepository::new(pool.clone());
+    let role_repo = RoleRepository::new(pool);
+
+    // Rotate the refresh token; a reused token revokes its whole family
+    let rotated = match refresh_repo
+        .rotate_refresh_token(&refresh_request.refresh_token)
+        .await
+    {
+        Ok(rotated) => rotated,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired refresh token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Get user from database
+    let user = match user_repo.get_user_by_id(&rotated.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let error = ApiError {
+                message: "User not found".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Enforce the unverified email policy
+    if !UnverifiedSigninPolicy::from_env().allows_signin(&user) {
+        let error = ApiError {
+            message: "Email address not verified".to_string(),
+            code: StatusCode::FORBIDDEN.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Keep the audience of the original sign-in; older families get the default
+    let audience = match resolve_audience(rotated.audience.as_deref()) {
+        Ok(audience) => audience,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired refresh token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Load current roles so role and membership changes take effect on refresh
+    let access = match role_repo
+        .get_user_access(&user.id, rotated.organization_id.as_ref())
+        .await
+    {
+        Ok(access) => access,
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Generate JWT token
+    let token = match create_jwt(
+        &user,
+        &audience,
+        &access,
+        Some(&rotated.session_id),
+        rotated.auth_time,
+    ) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to generate token".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let response = AuthResponse {
+        user,
+        token,
+        refresh_token: rotated.refresh_token,
+        expires_in: access_token_ttl().num_seconds() as usize,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&response)?.into())?)
+}
@@ -0,0 +1,111 @@
+use rust_on_vercel_template::{
+    email_verification::{send_verification_email, EmailVerificationRepository},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, MessageResponse, ResendVerificationRequest, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| {
+        rate_limited(
+            req,
+            "auth/resend-verification",
+            RateLimitPolicy::AUTH,
+            handler,
+        )
+    })
+    .await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let resend_request: ResendVerificationRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if resend_request.email.is_empty() {
+        let error = ApiError {
+            message: "Email is required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let user_repo = UserRepository::new(pool.clone());
+    let verification_repo = EmailVerificationRepository::new(pool);
+
+    // The response is the same whether or not the account exists or is already
+    // verified, so this endpoint can't be used to discover registered addresses.
+    let response = MessageResponse {
+        message:
+            "If an unverified account exists for that email, a verification link has been sent"
+                .to_string(),
+    };
+
+    let user = match user_repo.get_user_by_email(&resend_request.email).await {
+        Ok(Some(user)) if user.email_verified_at.is_none() => user,
+        Ok(_) => {
+            return Ok(Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&response)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Send a fresh verification email
+    let sent = match verification_repo.create_verification_token(&user.id).await {
+        Ok(verification_token) => send_verification_email(&user, &verification_token).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = sent {
+        eprintln!("Failed to send verification email: {}", e);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&response)?.into())?)
+}
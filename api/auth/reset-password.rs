@@ -0,0 +1,146 @@
+use rust_on_vercel_template::{
+    audit::{AuditRepository, PASSWORD_RESET},
+    password_policy::PasswordPolicy,
+    password_resets::PasswordResetRepository,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, MessageResponse, PasswordPolicyError, ResetPasswordRequest, UserRepository,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/reset-password", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let reset_request: ResetPasswordRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if reset_request.token.is_empty() {
+        let error = ApiError {
+            message: "Token is required".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let reset_repo = PasswordResetRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool.clone());
+    let user_repo = UserRepository::new(pool);
+
+    // Look up the account the token belongs to
+    let user = match reset_repo.find_reset_token_user(&reset_request.token).await {
+        Ok(user_id) => user_repo.get_user_by_id(&user_id).await.ok().flatten(),
+        Err(_) => None,
+    };
+    let user = match user {
+        Some(user) => user,
+        None => {
+            let error = ApiError {
+                message: "Invalid or expired reset token".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Check the new password against the policy before the token is used up
+    let violations = PasswordPolicy::from_env().validate(
+        &reset_request.new_password,
+        &user.email,
+        &user.username,
+    );
+    if !violations.is_empty() {
+        let error = PasswordPolicyError {
+            message: "Password does not meet the requirements".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+            violations,
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Use up the token, update the password, then sign out everywhere and revoke API keys
+    let result = reset_repo
+        .reset_password(&reset_request.token, &reset_request.new_password)
+        .await;
+
+    match result {
+        Ok(Some(user_id)) => {
+            if let Err(e) = audit_repo
+                .record(&user_id, PASSWORD_RESET, &req, json!({}))
+                .await
+            {
+                eprintln!("Failed to record audit event: {}", e);
+            }
+
+            let response = MessageResponse {
+                message: "Password has been reset".to_string(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&response)?.into())?)
+        }
+        Ok(None) => {
+            let error = ApiError {
+                message: "Invalid or expired reset token".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+        Err(e) => {
+            eprintln!("Failed to reset password: {}", e);
+            let error = ApiError {
+                message: "Failed to reset password".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
@@ -1,15 +1,21 @@
 use rust_on_vercel_template::{
-    auth::create_jwt, create_pool, ApiError, AuthResponse, LoginRequest, UserRepository,
+    account::record_signin,
+    auth::{create_mfa_challenge_token, issue_auth_response, mfa_challenge_ttl, resolve_audience},
+    client_ip,
+    email_verification::UnverifiedSigninPolicy,
+    login_attempts::{LoginAttemptRepository, LoginBlock},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, LoginRequest, MfaChallengeResponse, UserRepository,
 };
-use serde_json;
+use sqlx::PgPool;
 use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    run(handler).await
+    run(|req| rate_limited(req, "auth/signin", RateLimitPolicy::AUTH, handler)).await
 }
 
-pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
     // Load environment variables
     dotenvy::dotenv().ok();
 
@@ -53,22 +59,65 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
             .body(serde_json::to_string(&error)?.into())?);
     }
 
-    // Connect to database
-    let pool = match create_pool().await {
-        Ok(pool) => pool,
+    // Resolve which app the tokens are for
+    let audience = match resolve_audience(login_request.audience.as_deref()) {
+        Ok(audience) => audience,
         Err(_) => {
             let error = ApiError {
-                message: "Database connection failed".to_string(),
-                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                message: "Unknown audience".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
             };
             return Ok(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .status(StatusCode::BAD_REQUEST)
                 .header("content-type", "application/json")
                 .body(serde_json::to_string(&error)?.into())?);
         }
     };
 
-    let user_repo = UserRepository::new(pool);
+    let user_repo = UserRepository::new(pool.clone());
+    let attempt_repo = LoginAttemptRepository::new(pool.clone());
+    let ip = client_ip(&req);
+
+    // Refuse locked accounts and throttled IP addresses before checking the password
+    match attempt_repo.check(&login_request.email, &ip).await {
+        Ok(None) => {}
+        Ok(Some(LoginBlock::AccountLocked {
+            retry_after_seconds,
+        })) => {
+            let error = ApiError {
+                message: "Account temporarily locked after too many failed attempts".to_string(),
+                code: StatusCode::LOCKED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::LOCKED)
+                .header("content-type", "application/json")
+                .header("retry-after", retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Ok(Some(LoginBlock::TooManyAttempts {
+            retry_after_seconds,
+        })) => {
+            let error = ApiError {
+                message: "Too many failed signin attempts, try again later".to_string(),
+                code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("content-type", "application/json")
+                .header("retry-after", retry_after_seconds.to_string())
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    }
 
     // Authenticate user
     match user_repo
@@ -76,12 +125,25 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         .await
     {
         Ok(user) => {
-            // Generate JWT token
-            let token = match create_jwt(&user.id, &user.email) {
-                Ok(token) => token,
+            // Enforce the unverified email policy
+            if !UnverifiedSigninPolicy::from_env().allows_signin(&user) {
+                let error = ApiError {
+                    message: "Email address not verified".to_string(),
+                    code: StatusCode::FORBIDDEN.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+
+            // Users with MFA enabled must exchange a challenge token at /api/auth/mfa/verify,
+            // or /api/auth/passkeys/authenticate with a passkey
+            let mfa_methods = match user_repo.mfa_methods(&user.id).await {
+                Ok(methods) => methods,
                 Err(_) => {
                     let error = ApiError {
-                        message: "Failed to generate token".to_string(),
+                        message: "Database error".to_string(),
                         code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
                     };
                     return Ok(Response::builder()
@@ -91,7 +153,55 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
                 }
             };
 
-            let response = AuthResponse { user, token };
+            if !mfa_methods.is_empty() {
+                let mfa_token = match create_mfa_challenge_token(&user.id, &audience) {
+                    Ok(token) => token,
+                    Err(_) => {
+                        let error = ApiError {
+                            message: "Failed to generate token".to_string(),
+                            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        };
+                        return Ok(Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .header("content-type", "application/json")
+                            .body(serde_json::to_string(&error)?.into())?);
+                    }
+                };
+
+                let response = MfaChallengeResponse {
+                    mfa_required: true,
+                    mfa_token,
+                    expires_in: mfa_challenge_ttl().num_seconds() as usize,
+                    methods: mfa_methods,
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&response)?.into())?);
+            }
+
+            // The signin is complete, so earlier failures no longer count
+            if let Err(e) = attempt_repo.record_success(&login_request.email).await {
+                eprintln!("Failed to reset login attempts: {}", e);
+            }
+
+            // Signing in cancels a pending account deletion
+            record_signin(&pool, &user.id, &req, &audience).await;
+
+            // Issue access and refresh tokens
+            let response = match issue_auth_response(&pool, &req, user, &audience).await {
+                Ok(response) => response,
+                Err(_) => {
+                    let error = ApiError {
+                        message: "Failed to generate token".to_string(),
+                        code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    };
+                    return Ok(Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .header("content-type", "application/json")
+                        .body(serde_json::to_string(&error)?.into())?);
+                }
+            };
 
             Ok(Response::builder()
                 .status(StatusCode::OK)
@@ -99,6 +209,10 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
                 .body(serde_json::to_string(&response)?.into())?)
         }
         Err(_) => {
+            if let Err(e) = attempt_repo.record_failure(&login_request.email, &ip).await {
+                eprintln!("Failed to record login attempt: {}", e);
+            }
+
             let error = ApiError {
                 message: "Invalid credentials".to_string(),
                 code: StatusCode::UNAUTHORIZED.as_u16(),
@@ -0,0 +1,172 @@
+use rust_on_vercel_template::{
+    auth::{extract_bearer_token, verify_access_token},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    refresh_tokens::RefreshTokenRepository,
+    revocation::TokenRevocationRepository,
+    sessions::SessionRepository,
+    ApiError, SignoutRequest,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "auth/signout", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Parse request body; an empty body only signs out the current token
+    let body = req.body();
+    let signout_request: SignoutRequest = if body.is_empty() {
+        SignoutRequest {
+            refresh_token: None,
+            all_sessions: None,
+        }
+    } else {
+        match serde_json::from_slice(body) {
+            Ok(req) => req,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid request body".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        }
+    };
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID in token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let revocation_repo = TokenRevocationRepository::new(pool.clone());
+    let refresh_repo = RefreshTokenRepository::new(pool.clone());
+    let session_repo = SessionRepository::new(pool);
+
+    // Revoke the current token, its session and optionally every other session
+    let result = if signout_request.all_sessions.unwrap_or(false) {
+        revocation_repo.revoke_all_user_tokens(&user_id).await
+    } else {
+        match &signout_request.refresh_token {
+            Some(refresh_token) => {
+                refresh_repo
+                    .revoke_refresh_token_family(&user_id, refresh_token)
+                    .await
+            }
+            None => match claims.session_id() {
+                Some(session_id) => session_repo
+                    .revoke_session(&user_id, &session_id)
+                    .await
+                    .map(|_| ()),
+                None => Ok(()),
+            },
+        }
+    };
+
+    let result = match result {
+        Ok(()) => revocation_repo.revoke_token(&user_id, &claims).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::Empty)?),
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to sign out".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
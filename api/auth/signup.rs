@@ -1,15 +1,24 @@
 use rust_on_vercel_template::{
-    auth::create_jwt, create_pool, ApiError, AuthResponse, CreateUserRequest, UserRepository,
+    audit::{AuditRepository, ACCOUNT_CREATED},
+    auth::{is_valid_email, issue_auth_response, resolve_audience},
+    email_verification::{
+        send_verification_email, EmailVerificationRepository, UnverifiedSigninPolicy,
+    },
+    invitations::InvitationRepository,
+    password_policy::PasswordPolicy,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError, CreateUserRequest, MessageResponse, PasswordPolicyError, UserRepository,
 };
-use serde_json;
+use serde_json::json;
+use sqlx::PgPool;
 use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    run(handler).await
+    run(|req| rate_limited(req, "auth/signup", RateLimitPolicy::AUTH, handler)).await
 }
 
-pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
     // Load environment variables
     dotenvy::dotenv().ok();
 
@@ -42,13 +51,9 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
     };
 
     // Validate input
-    if create_user_request.email.is_empty()
-        || create_user_request.username.is_empty()
-        || create_user_request.password.len() < 6
-    {
+    if create_user_request.email.is_empty() || create_user_request.username.is_empty() {
         let error = ApiError {
-            message: "Email, username are required and password must be at least 6 characters"
-                .to_string(),
+            message: "Email and username are required".to_string(),
             code: StatusCode::BAD_REQUEST.as_u16(),
         };
         return Ok(Response::builder()
@@ -57,29 +62,144 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
             .body(serde_json::to_string(&error)?.into())?);
     }
 
-    // Connect to database
-    let pool = match create_pool().await {
-        Ok(pool) => pool,
+    if !is_valid_email(&create_user_request.email) {
+        let error = ApiError {
+            message: "Invalid email address".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Check the password against the policy
+    let violations = PasswordPolicy::from_env().validate(
+        &create_user_request.password,
+        &create_user_request.email,
+        &create_user_request.username,
+    );
+    if !violations.is_empty() {
+        let error = PasswordPolicyError {
+            message: "Password does not meet the requirements".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+            violations,
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Resolve which app the tokens are for
+    let audience = match resolve_audience(create_user_request.audience.as_deref()) {
+        Ok(audience) => audience,
         Err(_) => {
             let error = ApiError {
-                message: "Database connection failed".to_string(),
-                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                message: "Unknown audience".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
             };
             return Ok(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .status(StatusCode::BAD_REQUEST)
                 .header("content-type", "application/json")
                 .body(serde_json::to_string(&error)?.into())?);
         }
     };
 
-    let user_repo = UserRepository::new(pool);
+    let user_repo = UserRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool.clone());
+    let verification_repo = EmailVerificationRepository::new(pool.clone());
+    let invitation_repo = InvitationRepository::new(pool.clone());
+
+    // Check the invitation before creating the account, so a bad token creates nothing
+    let invite_token = create_user_request.invite_token.clone();
+    if let Some(invite_token) = invite_token.as_deref() {
+        match invitation_repo.find_pending_invitation(invite_token).await {
+            Ok(invitation)
+                if invitation
+                    .email
+                    .eq_ignore_ascii_case(&create_user_request.email) => {}
+            Ok(_) => {
+                let error = ApiError {
+                    message: "Invitation was sent to a different email address".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid or expired invitation".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        }
+    }
 
     // Create user
     match user_repo.create_user(create_user_request).await {
-        Ok(user) => {
-            // Generate JWT token
-            let token = match create_jwt(&user.id, &user.email) {
-                Ok(token) => token,
+        Ok(mut user) => {
+            // Start the account's history, which is part of its data export
+            if let Err(e) = audit_repo
+                .record(&user.id, ACCOUNT_CREATED, &req, json!({}))
+                .await
+            {
+                eprintln!("Failed to record audit event: {}", e);
+            }
+
+            // Join the inviting organization; the invitation link also proves the address
+            let joined = match invite_token.as_deref() {
+                Some(invite_token) => {
+                    match invitation_repo.accept_invitation(invite_token, &user).await {
+                        Ok(_) => user_repo.get_user_by_id(&user.id).await.ok().flatten(),
+                        Err(e) => {
+                            eprintln!("Failed to accept invitation: {}", e);
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            match joined {
+                Some(joined_user) => user = joined_user,
+                None => {
+                    // Send the verification email; a delivery failure can be fixed with a resend
+                    let sent = match verification_repo.create_verification_token(&user.id).await {
+                        Ok(verification_token) => {
+                            send_verification_email(&user, &verification_token).await
+                        }
+                        Err(e) => Err(e),
+                    };
+
+                    if let Err(e) = sent {
+                        eprintln!("Failed to send verification email: {}", e);
+                    }
+                }
+            }
+
+            // Unverified users get no tokens under the deny policy
+            if !UnverifiedSigninPolicy::from_env().allows_signin(&user) {
+                let response = MessageResponse {
+                    message:
+                        "Account created. Check your email to verify your address before signing in"
+                            .to_string(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::CREATED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&response)?.into())?);
+            }
+
+            // Issue access and refresh tokens
+            let response = match issue_auth_response(&pool, &req, user, &audience).await {
+                Ok(response) => response,
                 Err(_) => {
                     let error = ApiError {
                         message: "Failed to generate token".to_string(),
@@ -92,8 +212,6 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
                 }
             };
 
-            let response = AuthResponse { user, token };
-
             Ok(Response::builder()
                 .status(StatusCode::CREATED)
                 .header("content-type", "application/json")
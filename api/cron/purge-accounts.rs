@@ -0,0 +1,102 @@
+use rust_on_vercel_template::{create_pool, ApiError, MessageResponse, UserRepository};
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(handler).await
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET requests
+    if req.method() != "GET" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Only Vercel Cron, which sends the CRON_SECRET as a bearer token, may trigger a purge
+    if !is_authorized_cron_request(&req) {
+        let error = ApiError {
+            message: "Unauthorized".to_string(),
+            code: StatusCode::UNAUTHORIZED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Connect to database
+    let pool = match create_pool().await {
+        Ok(pool) => pool,
+        Err(_) => {
+            let error = ApiError {
+                message: "Database connection failed".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let user_repo = UserRepository::new(pool);
+
+    // Remove accounts whose grace period is over
+    match user_repo.purge_deleted_accounts().await {
+        Ok(purged) => {
+            let response = MessageResponse {
+                message: format!("Purged {} deleted account(s)", purged),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&response)?.into())?)
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Failed to purge deleted accounts".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?)
+        }
+    }
+}
+
+/// Without a CRON_SECRET configured the endpoint refuses every request.
+fn is_authorized_cron_request(req: &Request) -> bool {
+    let secret = match std::env::var("CRON_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret,
+        _ => return false,
+    };
+    let expected = format!("Bearer {}", secret);
+
+    let provided = match req
+        .headers()
+        .get("authorization")
+        .and_then(|header| header.to_str().ok())
+    {
+        Some(provided) => provided,
+        None => return false,
+    };
+
+    // Compare in constant time so the secret can't be guessed byte by byte
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,224 @@
+use rust_on_vercel_template::{
+    account::record_signin,
+    client_ip,
+    email_verification::UnverifiedSigninPolicy,
+    login_attempts::{LoginAttemptRepository, LoginBlock},
+    oidc_provider::{
+        parse_form, render_error_page, render_signin_page, AuthorizationError,
+        AuthorizationRequest, OidcProviderRepository,
+    },
+    rate_limit::{rate_limited, RateLimitPolicy},
+    UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "oidc/authorize", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET requests, which show the sign-in form, and POST requests from the form
+    if req.method() != "GET" && req.method() != "POST" {
+        return html_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            render_error_page("Method not allowed"),
+        );
+    }
+    let is_post = req.method() == "POST";
+
+    // Parse the authorization request, from the query string or the form
+    let params = if is_post {
+        parse_form(req.body())
+    } else {
+        parse_form(req.uri().query().unwrap_or_default().as_bytes())
+    };
+    let authorization = AuthorizationRequest::from_params(&params);
+
+    let provider_repo = OidcProviderRepository::new(pool.clone());
+
+    // Check the client and its redirect URI before anything is sent back to it
+    let client = match provider_repo.find_client(&authorization.client_id).await {
+        Ok(client) => client,
+        Err(_) => {
+            return html_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                render_error_page("Database error"),
+            )
+        }
+    };
+
+    let scopes = match authorization.validate(client.as_ref()) {
+        Ok(scopes) => scopes,
+        Err(AuthorizationError::InvalidClient(message)) => {
+            return html_response(StatusCode::BAD_REQUEST, render_error_page(message))
+        }
+        Err(AuthorizationError::Redirect { error, description }) => {
+            return match authorization
+                .redirect_url(&[("error", error), ("error_description", description)])
+            {
+                Ok(location) => redirect_response(&location),
+                Err(_) => html_response(StatusCode::BAD_REQUEST, render_error_page(description)),
+            };
+        }
+    };
+    let client_name = client.map(|client| client.name).unwrap_or_default();
+
+    if !is_post {
+        return html_response(
+            StatusCode::OK,
+            render_signin_page(&authorization, &client_name, "", None),
+        );
+    }
+
+    let email = params.get("email").cloned().unwrap_or_default();
+    let password = params.get("password").cloned().unwrap_or_default();
+    let code = params
+        .get("code")
+        .map(|code| code.trim())
+        .unwrap_or_default();
+
+    // Shows the form again with a message, keeping the email address
+    let retry = |status: StatusCode, message: &str| {
+        html_response(
+            status,
+            render_signin_page(&authorization, &client_name, &email, Some(message)),
+        )
+    };
+
+    // Validate input
+    if email.is_empty() || password.is_empty() {
+        return retry(StatusCode::BAD_REQUEST, "Email and password are required");
+    }
+
+    let user_repo = UserRepository::new(pool.clone());
+    let attempt_repo = LoginAttemptRepository::new(pool.clone());
+    let ip = client_ip(&req);
+
+    // Refuse locked accounts and throttled IP addresses before checking the password
+    match attempt_repo.check(&email, &ip).await {
+        Ok(None) => {}
+        Ok(Some(LoginBlock::AccountLocked { .. })) => {
+            return retry(
+                StatusCode::LOCKED,
+                "Account temporarily locked after too many failed attempts",
+            );
+        }
+        Ok(Some(LoginBlock::TooManyAttempts { .. })) => {
+            return retry(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many failed signin attempts, try again later",
+            );
+        }
+        Err(_) => return retry(StatusCode::INTERNAL_SERVER_ERROR, "Database error"),
+    }
+
+    // Authenticate user
+    let user = match user_repo.authenticate_user(&email, &password).await {
+        Ok(user) => user,
+        Err(_) => {
+            if let Err(e) = attempt_repo.record_failure(&email, &ip).await {
+                eprintln!("Failed to record login attempt: {}", e);
+            }
+            return retry(StatusCode::UNAUTHORIZED, "Invalid email or password");
+        }
+    };
+
+    // Enforce the unverified email policy
+    if !UnverifiedSigninPolicy::from_env().allows_signin(&user) {
+        return retry(StatusCode::FORBIDDEN, "Email address not verified");
+    }
+
+    // Users with MFA enabled enter their code in the same form. The form has no way to
+    // use a passkey, so users whose only second factor is a passkey can't sign in here.
+    let mfa_methods = match user_repo.mfa_methods(&user.id).await {
+        Ok(methods) => methods,
+        Err(_) => return retry(StatusCode::INTERNAL_SERVER_ERROR, "Database error"),
+    };
+    if mfa_methods.iter().any(|method| method == "totp") {
+        if code.is_empty() {
+            return retry(
+                StatusCode::UNAUTHORIZED,
+                "Enter the code from your authenticator app or a recovery code",
+            );
+        }
+
+        // Refuse while too many wrong codes have been entered for this user
+        match attempt_repo.check_mfa(&user.id).await {
+            Ok(None) => {}
+            Ok(Some(_)) => {
+                return retry(
+                    StatusCode::LOCKED,
+                    "Two-factor authentication temporarily locked after too many failed attempts",
+                );
+            }
+            Err(_) => return retry(StatusCode::INTERNAL_SERVER_ERROR, "Database error"),
+        }
+
+        if user_repo.verify_mfa_code(&user.id, code).await.is_err() {
+            if let Err(e) = attempt_repo.record_mfa_failure(&user.id).await {
+                eprintln!("Failed to record MFA failure: {}", e);
+            }
+            return retry(StatusCode::UNAUTHORIZED, "Invalid two-factor code");
+        }
+
+        if let Err(e) = attempt_repo.record_mfa_success(&user.id).await {
+            eprintln!("Failed to clear MFA failures: {}", e);
+        }
+    } else if !mfa_methods.is_empty() {
+        return retry(
+            StatusCode::FORBIDDEN,
+            "Set up an authenticator app to sign in here with two-factor authentication",
+        );
+    }
+
+    // The signin is complete, so earlier failures no longer count
+    if let Err(e) = attempt_repo.record_success(&email).await {
+        eprintln!("Failed to reset login attempts: {}", e);
+    }
+
+    // Signing in cancels a pending account deletion
+    record_signin(&pool, &user.id, &req, &authorization.client_id).await;
+
+    // Send the user back to the client with an authorization code
+    let code = match provider_repo
+        .create_authorization_code(&authorization, &user.id, &scopes)
+        .await
+    {
+        Ok(code) => code,
+        Err(_) => return retry(StatusCode::INTERNAL_SERVER_ERROR, "Failed to sign in"),
+    };
+
+    match authorization.redirect_url(&[("code", &code)]) {
+        Ok(location) => redirect_response(&location),
+        Err(_) => html_response(
+            StatusCode::BAD_REQUEST,
+            render_error_page("Invalid redirect URI"),
+        ),
+    }
+}
+
+fn html_response(status: StatusCode, html: String) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header("content-type", "text/html; charset=utf-8")
+        .header("cache-control", "no-store")
+        .header(
+            "content-security-policy",
+            "default-src 'none'; style-src 'unsafe-inline'; frame-ancestors 'none'",
+        )
+        .header("x-frame-options", "DENY")
+        .body(html.into())?)
+}
+
+fn redirect_response(location: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("location", location)
+        .header("cache-control", "no-store")
+        .body(Body::Empty)?)
+}
@@ -0,0 +1,170 @@
+use rust_on_vercel_template::{
+    auth::{access_token_ttl, create_id_token},
+    oidc_provider::{client_credentials, parse_form, user_info, OidcProviderRepository},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    OAuthErrorResponse, OidcTokenResponse, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "oidc/token", RateLimitPolicy::AUTH, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow POST requests
+    if req.method() != "POST" {
+        return oauth_error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "invalid_request",
+            "Method not allowed",
+        );
+    }
+
+    // Parse the form body
+    let form = parse_form(req.body());
+    let param = |name: &str| form.get(name).cloned().unwrap_or_default();
+
+    if param("grant_type") != "authorization_code" {
+        return oauth_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "Only the authorization_code grant is supported",
+        );
+    }
+
+    let (client_id, client_secret) = match client_credentials(&req, &form) {
+        Some(credentials) => credentials,
+        None => {
+            return oauth_error(
+                StatusCode::UNAUTHORIZED,
+                "invalid_client",
+                "Client authentication is required",
+            )
+        }
+    };
+
+    let provider_repo = OidcProviderRepository::new(pool.clone());
+
+    // Authenticate the client
+    if provider_repo
+        .authenticate_client(&client_id, client_secret.as_deref())
+        .await
+        .is_err()
+    {
+        return oauth_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+            "Invalid client credentials",
+        );
+    }
+
+    // Redeem the authorization code, checking the PKCE verifier
+    let grant = match provider_repo
+        .redeem_authorization_code(
+            &client_id,
+            &param("code"),
+            &param("redirect_uri"),
+            &param("code_verifier"),
+        )
+        .await
+    {
+        Ok(grant) => grant,
+        Err(_) => {
+            return oauth_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_grant",
+                "Invalid or expired authorization code",
+            )
+        }
+    };
+
+    let user = match UserRepository::new(pool.clone())
+        .get_user_by_id(&grant.user_id)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return oauth_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_grant",
+                "User no longer exists",
+            )
+        }
+        Err(_) => {
+            return oauth_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "Database error",
+            )
+        }
+    };
+
+    // Issue the ID token and an access token for the userinfo endpoint
+    let id_token = match create_id_token(
+        user_info(&user, &grant.scopes),
+        &client_id,
+        grant.nonce,
+        grant.auth_time,
+    ) {
+        Ok(id_token) => id_token,
+        Err(e) => {
+            eprintln!("Failed to create ID token: {}", e);
+            return oauth_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "Failed to generate token",
+            );
+        }
+    };
+
+    let access_token = match provider_repo
+        .create_access_token(&client_id, &user.id, &grant.scopes)
+        .await
+    {
+        Ok(access_token) => access_token,
+        Err(_) => {
+            return oauth_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "Failed to generate token",
+            )
+        }
+    };
+
+    let response = OidcTokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: access_token_ttl().num_seconds() as usize,
+        id_token,
+        scope: grant.scopes.join(" "),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .header("cache-control", "no-store")
+        .header("pragma", "no-cache")
+        .body(serde_json::to_string(&response)?.into())?)
+}
+
+fn oauth_error(
+    status: StatusCode,
+    error: &str,
+    description: &str,
+) -> Result<Response<Body>, Error> {
+    let error = OAuthErrorResponse {
+        error: error.to_string(),
+        error_description: description.to_string(),
+    };
+
+    Ok(Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .header("cache-control", "no-store")
+        .body(serde_json::to_string(&error)?.into())?)
+}
@@ -0,0 +1,81 @@
+use rust_on_vercel_template::{
+    auth::extract_bearer_token,
+    oidc_provider::{user_info, OidcProviderRepository},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    OAuthErrorResponse, UserRepository,
+};
+use sqlx::PgPool;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "oidc/userinfo", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET and POST requests, as OpenID Connect Core section 5.3.1 requires
+    if req.method() != "GET" && req.method() != "POST" {
+        let error = OAuthErrorResponse {
+            error: "invalid_request".to_string(),
+            error_description: "Method not allowed".to_string(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Extract the access token issued by /api/oidc/token
+    let token = match req
+        .headers()
+        .get("authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| extract_bearer_token(header).ok())
+    {
+        Some(token) => token.to_string(),
+        None => return invalid_token("Missing access token"),
+    };
+
+    // Look up the token and its user
+    let (user_id, scopes) = match OidcProviderRepository::new(pool.clone())
+        .find_access_token(&token)
+        .await
+    {
+        Ok(Some(grant)) => grant,
+        Ok(None) | Err(_) => return invalid_token("Invalid or expired access token"),
+    };
+
+    let user = match UserRepository::new(pool).get_user_by_id(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) | Err(_) => return invalid_token("Invalid or expired access token"),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .header("cache-control", "no-store")
+        .body(serde_json::to_string(&user_info(&user, &scopes))?.into())?)
+}
+
+/// A 401 with the `WWW-Authenticate` challenge RFC 6750 asks for.
+fn invalid_token(description: &str) -> Result<Response<Body>, Error> {
+    let error = OAuthErrorResponse {
+        error: "invalid_token".to_string(),
+        error_description: description.to_string(),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header("content-type", "application/json")
+        .header(
+            "www-authenticate",
+            format!(
+                r#"Bearer error="invalid_token", error_description="{}""#,
+                description
+            ),
+        )
+        .body(serde_json::to_string(&error)?.into())?)
+}
@@ -0,0 +1,306 @@
+use rust_on_vercel_template::{
+    auth::{extract_bearer_token, is_valid_email, verify_access_token},
+    email_verification::require_verified_email,
+    invitations::{send_invitation_email, InvitationRepository},
+    organizations::{OrganizationRepository, Tenant, MEMBER_ROLE},
+    rate_limit::{rate_limited, RateLimitPolicy},
+    rbac::{require_permission, MEMBERS_MANAGE},
+    ApiError, CreateInvitationRequest, MessageResponse, RevokeInvitationRequest,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| rate_limited(req, "orgs/invitations", RateLimitPolicy::DEFAULT, handler)).await
+}
+
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET, POST and DELETE requests
+    if req.method() != "GET" && req.method() != "POST" && req.method() != "DELETE" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+    // Get authorization header
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => match header.to_str() {
+            Ok(h) => h,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid authorization header".to_string(),
+                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        },
+        None => {
+            let error = ApiError {
+                message: "Authorization header required".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Extract token from Bearer header
+    let token = match extract_bearer_token(auth_header) {
+        Ok(token) => token,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid authorization header format".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Verify JWT token
+    let claims = match verify_access_token(&pool, token).await {
+        Ok(claims) => claims,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid or expired token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Require a verified email
+    if let Err(error) = require_verified_email(&claims) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid user ID in token".to_string(),
+                code: StatusCode::UNAUTHORIZED.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Invitations belong to the active organization
+    let tenant = match Tenant::from_claims(&claims) {
+        Ok(tenant) => tenant,
+        Err(_) => {
+            let error = ApiError {
+                message: "No active organization".to_string(),
+                code: StatusCode::FORBIDDEN.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Check permissions
+    if let Err(error) = require_permission(&claims, MEMBERS_MANAGE) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let invitation_repo = InvitationRepository::new(pool.clone());
+    let organization_repo = OrganizationRepository::new(pool);
+
+    if req.method() == "GET" {
+        // List pending invitations
+        return match invitation_repo.list_pending_invitations(&tenant).await {
+            Ok(invitations) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&invitations)?.into())?),
+            Err(_) => {
+                let error = ApiError {
+                    message: "Database error".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+        };
+    }
+
+    if req.method() == "DELETE" {
+        // Parse request body
+        let body = req.body();
+        let revoke_request: RevokeInvitationRequest = match serde_json::from_slice(body) {
+            Ok(req) => req,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid request body".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        let invitation_id = match Uuid::parse_str(&revoke_request.invitation_id) {
+            Ok(id) => id,
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invalid invitation ID".to_string(),
+                    code: StatusCode::BAD_REQUEST.as_u16(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?);
+            }
+        };
+
+        // Revoke the invitation so its link stops working
+        return match invitation_repo
+            .revoke_invitation(&tenant, &invitation_id)
+            .await
+        {
+            Ok(_) => {
+                let response = MessageResponse {
+                    message: "Invitation revoked".to_string(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&response)?.into())?)
+            }
+            Err(_) => {
+                let error = ApiError {
+                    message: "Invitation not found".to_string(),
+                    code: StatusCode::NOT_FOUND.as_u16(),
+                };
+                Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header("content-type", "application/json")
+                    .body(serde_json::to_string(&error)?.into())?)
+            }
+        };
+    }
+    // Parse request body
+    let body = req.body();
+    let create_request: CreateInvitationRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
+            let error = ApiError {
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Validate input
+    if !is_valid_email(&create_request.email) {
+        let error = ApiError {
+            message: "Invalid email address".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let organization_name = match organization_repo
+        .get_organization_name(&tenant.organization_id)
+        .await
+    {
+        Ok(Some(name)) => name,
+        Ok(None) => {
+            let error = ApiError {
+                message: "Organization not found".to_string(),
+                code: StatusCode::NOT_FOUND.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+        Err(_) => {
+            let error = ApiError {
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Create the invitation
+    let role = create_request.role.as_deref().unwrap_or(MEMBER_ROLE);
+    let (invitation, invitation_token) = match invitation_repo
+        .create_invitation(&tenant, &create_request.email, role, &user_id)
+        .await
+    {
+        Ok(created) => created,
+        Err(e) => {
+            let error = ApiError {
+                message: e.to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Send the invitation; a delivery failure can be fixed by inviting again
+    if let Err(e) = send_invitation_email(
+        &invitation,
+        &organization_name,
+        &claims.email,
+        &invitation_token,
+    )
+    .await
+    {
+        eprintln!("Failed to send invitation email: {}", e);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&invitation)?.into())?)
+}
@@ -1,21 +1,27 @@
 use rust_on_vercel_template::{
-    auth::{extract_bearer_token, verify_jwt},
-    ApiError, Product,
+    auth::authenticate_request,
+    email_verification::require_verified_email,
+    organizations::Tenant,
+    products::ProductRepository,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    rbac::{require_permission, PRODUCTS_READ, PRODUCTS_WRITE},
+    ApiError, CreateProductRequest,
 };
-use serde_json;
+use sqlx::PgPool;
+use uuid::Uuid;
 use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    run(handler).await
+    run(|req| rate_limited(req, "routes/products", RateLimitPolicy::DEFAULT, handler)).await
 }
 
-pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+pub async fn handler(req: Request, pool: PgPool) -> Result<Response<Body>, Error> {
     // Load environment variables
     dotenvy::dotenv().ok();
 
-    // Only allow GET requests
-    if req.method() != "GET" {
+    // Only allow GET and POST requests
+    if req.method() != "GET" && req.method() != "POST" {
         let error = ApiError {
             message: "Method not allowed".to_string(),
             code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
@@ -26,39 +32,113 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
             .body(serde_json::to_string(&error)?.into())?);
     }
 
-    // Get authorization header
-    let auth_header = match req.headers().get("authorization") {
-        Some(header) => match header.to_str() {
-            Ok(h) => h,
+    // Authenticate with an access token or API key
+    let claims = match authenticate_request(&pool, &req).await {
+        Ok(claims) => claims,
+        Err(error) => {
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    // Require a verified email
+    if let Err(error) = require_verified_email(&claims) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Products belong to the active organization
+    let tenant = match Tenant::from_claims(&claims) {
+        Ok(tenant) => tenant,
+        Err(_) => {
+            let error = ApiError {
+                message: "No active organization".to_string(),
+                code: StatusCode::FORBIDDEN.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let product_repo = ProductRepository::new(pool, tenant);
+
+    if req.method() == "GET" {
+        // Check permissions
+        if let Err(error) = require_permission(&claims, PRODUCTS_READ) {
+            return Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+
+        return match product_repo.list_products().await {
+            Ok(products) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&products)?.into())?),
             Err(_) => {
                 let error = ApiError {
-                    message: "Invalid authorization header".to_string(),
-                    code: StatusCode::UNAUTHORIZED.as_u16(),
+                    message: "Database error".to_string(),
+                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
                 };
-                return Ok(Response::builder()
-                    .status(StatusCode::UNAUTHORIZED)
+                Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
                     .header("content-type", "application/json")
-                    .body(serde_json::to_string(&error)?.into())?);
+                    .body(serde_json::to_string(&error)?.into())?)
             }
-        },
-        None => {
+        };
+    }
+
+    // Check permissions
+    if let Err(error) = require_permission(&claims, PRODUCTS_WRITE) {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Parse request body
+    let body = req.body();
+    let create_request: CreateProductRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(_) => {
             let error = ApiError {
-                message: "Authorization header required".to_string(),
-                code: StatusCode::UNAUTHORIZED.as_u16(),
+                message: "Invalid request body".to_string(),
+                code: StatusCode::BAD_REQUEST.as_u16(),
             };
             return Ok(Response::builder()
-                .status(StatusCode::UNAUTHORIZED)
+                .status(StatusCode::BAD_REQUEST)
                 .header("content-type", "application/json")
                 .body(serde_json::to_string(&error)?.into())?);
         }
     };
 
-    // Extract token from Bearer header
-    let token = match extract_bearer_token(auth_header) {
-        Ok(token) => token,
+    // Validate input
+    if create_request.name.trim().is_empty()
+        || !create_request.price.is_finite()
+        || create_request.price < 0.0
+    {
+        let error = ApiError {
+            message: "Name is required and price must be a non-negative number".to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
         Err(_) => {
             let error = ApiError {
-                message: "Invalid authorization header format".to_string(),
+                message: "Invalid user ID in token".to_string(),
                 code: StatusCode::UNAUTHORIZED.as_u16(),
             };
             return Ok(Response::builder()
@@ -68,40 +148,19 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         }
     };
 
-    // Verify JWT token
-    match verify_jwt(token) {
-        Ok(_) => {
-            // Token is valid, return sample products
-            let products = vec![
-                Product {
-                    id: "1".to_string(),
-                    name: "Laptop".to_string(),
-                    price: 999.99,
-                },
-                Product {
-                    id: "2".to_string(),
-                    name: "Mouse".to_string(),
-                    price: 29.99,
-                },
-                Product {
-                    id: "3".to_string(),
-                    name: "Keyboard".to_string(),
-                    price: 79.99,
-                },
-            ];
-
-            Ok(Response::builder()
-                .status(StatusCode::OK)
-                .header("content-type", "application/json")
-                .body(serde_json::to_string(&products)?.into())?)
-        }
+    // Create product
+    match product_repo.create_product(&create_request, &user_id).await {
+        Ok(product) => Ok(Response::builder()
+            .status(StatusCode::CREATED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&product)?.into())?),
         Err(_) => {
             let error = ApiError {
-                message: "Invalid or expired token".to_string(),
-                code: StatusCode::UNAUTHORIZED.as_u16(),
+                message: "Database error".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
             };
             Ok(Response::builder()
-                .status(StatusCode::UNAUTHORIZED)
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
                 .header("content-type", "application/json")
                 .body(serde_json::to_string(&error)?.into())?)
         }
@@ -0,0 +1,58 @@
+use rust_on_vercel_template::{
+    oidc_provider::discovery_document,
+    rate_limit::{rate_limited, RateLimitPolicy},
+    ApiError,
+};
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(|req| {
+        rate_limited(
+            req,
+            "well-known/openid-configuration",
+            RateLimitPolicy::DEFAULT,
+            |req, _| handler(req),
+        )
+    })
+    .await
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Only allow GET requests
+    if req.method() != "GET" {
+        let error = ApiError {
+            message: "Method not allowed".to_string(),
+            code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("content-type", "application/json")
+            .body(serde_json::to_string(&error)?.into())?);
+    }
+
+    // Describe the endpoints and the active signing key
+    let document = match discovery_document() {
+        Ok(document) => document,
+        Err(e) => {
+            eprintln!("Failed to load signing keys: {}", e);
+            let error = ApiError {
+                message: "Signing keys are not configured".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .header("cache-control", "public, max-age=300")
+        .body(serde_json::to_string(&document)?.into())?)
+}
@@ -13,7 +13,7 @@ import {
 import { Input } from "@/components/ui/input";
 import { Button } from "@/components/ui/button";
 import { ThemeToggle } from "@/components/theme-toggle";
-import { AuthResponse, User } from "@/types/models";
+import { AuthResponse, PasswordPolicyError, User } from "@/types/models";
 
 export default function Home() {
   const [activeTab, setActiveTab] = useState("signin");
@@ -51,7 +51,12 @@ export default function Home() {
 
       const data = await response.json();
 
-      if (response.ok) {
+      if (response.ok && data.mfa_required) {
+        setAuthStatus({
+          isLoggedIn: false,
+          error: "Two-factor authentication required. Verify your code at /api/auth/mfa/verify.",
+        });
+      } else if (response.ok) {
         const authData: AuthResponse = data;
         setAuthStatus({
           isLoggedIn: true,
@@ -60,6 +65,7 @@ export default function Home() {
         });
         // Store token in localStorage for future requests
         localStorage.setItem("token", authData.token);
+        localStorage.setItem("refresh_token", authData.refresh_token);
       } else {
         setAuthStatus({
           isLoggedIn: false,
@@ -101,10 +107,16 @@ export default function Home() {
         });
         // Store token in localStorage for future requests
         localStorage.setItem("token", authData.token);
+        localStorage.setItem("refresh_token", authData.refresh_token);
       } else {
         setAuthStatus({
           isLoggedIn: false,
-          error: data.message || "Signup failed",
+          error:
+            (data as PasswordPolicyError).violations
+              ?.map((violation) => violation.message)
+              .join(". ") ||
+            data.message ||
+            "Signup failed",
         });
       }
     } catch (error) {
@@ -118,9 +130,28 @@ export default function Home() {
     }
   };
 
-  const handleSignout = () => {
+  const handleSignout = async () => {
+    const token = localStorage.getItem("token");
+    if (token) {
+      try {
+        await fetch("/api/auth/signout", {
+          method: "POST",
+          headers: {
+            "Content-Type": "application/json",
+            Authorization: `Bearer ${token}`,
+          },
+          body: JSON.stringify({
+            refresh_token: localStorage.getItem("refresh_token"),
+          }),
+        });
+      } catch (error) {
+        console.error("Signout error:", error);
+      }
+    }
+
     setAuthStatus({ isLoggedIn: false });
     localStorage.removeItem("token");
+    localStorage.removeItem("refresh_token");
     setSigninForm({ email: "", password: "" });
     setSignupForm({ email: "", username: "", password: "" });
     setTokenCopied(false);
@@ -228,7 +259,7 @@ export default function Home() {
                   <div>
                     <Input
                       type="password"
-                      placeholder="Password (min 6 characters)"
+                      placeholder="Password (min 8 characters)"
                       value={signupForm.password}
                       onChange={(e) =>
                         setSignupForm({
@@ -237,7 +268,7 @@ export default function Home() {
                         })
                       }
                       required
-                      minLength={6}
+                      minLength={8}
                     />
                   </div>
                   <Button type="submit" className="w-full" disabled={isLoading}>
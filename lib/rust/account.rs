@@ -0,0 +1,144 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::Request;
+
+use crate::audit::{AuditRepository, ACCOUNT_DELETION_CANCELLED, SIGNED_IN};
+use crate::auth::ttl_from_env;
+use crate::organizations::OrganizationRepository;
+use crate::passkeys::PasskeyRepository;
+use crate::rbac::RoleRepository;
+use crate::revocation::revoke_all_user_tokens_in;
+use crate::sessions::SessionRepository;
+use crate::{AccountExport, UserRepository};
+
+const DEFAULT_ACCOUNT_DELETION_GRACE_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+impl UserRepository {
+    /// Marks the account as deleted, signs the user out everywhere in the same transaction,
+    /// and returns when it will be purged for good.
+    ///
+    /// Until then the data is kept so the deletion can be cancelled by signing in again.
+    pub async fn schedule_account_deletion(&self, user_id: &Uuid) -> Result<DateTime<Utc>> {
+        let purge_after = Utc::now()
+            + ttl_from_env(
+                "ACCOUNT_DELETION_GRACE_SECONDS",
+                DEFAULT_ACCOUNT_DELETION_GRACE_SECONDS,
+            );
+
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET deleted_at = NOW(), purge_after = $1, updated_at = NOW()
+            WHERE id = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(purge_after)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("User not found"));
+        }
+
+        revoke_all_user_tokens_in(&mut tx, user_id).await?;
+
+        tx.commit().await?;
+
+        Ok(purge_after)
+    }
+
+    /// Restores an account that is waiting to be purged. Returns whether one was.
+    pub async fn cancel_account_deletion(&self, user_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET deleted_at = NULL, purge_after = NULL, updated_at = NOW()
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Permanently removes accounts whose grace period is over, along with everything that
+    /// references them. Content they created for an organization stays, without the author.
+    pub async fn purge_deleted_accounts(&self) -> Result<u64> {
+        let result =
+            sqlx::query("DELETE FROM users WHERE deleted_at IS NOT NULL AND purge_after < NOW()")
+                .execute(&self.pool)
+                .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Collects everything stored about the user for a data export.
+    pub async fn export_account(&self, user_id: &Uuid) -> Result<Option<AccountExport>> {
+        let user = match self.get_user_by_id(user_id).await? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+
+        let access = RoleRepository::new(self.pool.clone())
+            .get_user_access(user_id, None)
+            .await?;
+        let organizations = OrganizationRepository::new(self.pool.clone())
+            .list_user_organizations(user_id)
+            .await?;
+        let audit_events = AuditRepository::new(self.pool.clone())
+            .list_user_events(user_id)
+            .await?;
+
+        let sessions = SessionRepository::new(self.pool.clone())
+            .list_user_sessions(user_id)
+            .await?;
+
+        let passkeys = PasskeyRepository::new(self.pool.clone())
+            .list_passkeys(user_id)
+            .await?;
+
+        Ok(Some(AccountExport {
+            exported_at: Utc::now(),
+            user,
+            mfa_enabled: self.is_mfa_enabled(user_id).await?,
+            roles: access.roles,
+            organizations,
+            sessions,
+            passkeys,
+            audit_events,
+        }))
+    }
+}
+
+/// Bookkeeping once a user has fully signed in: a pending account deletion is cancelled and
+/// the signin is recorded. Failures are only logged, since neither should stop the signin.
+pub async fn record_signin(pool: &PgPool, user_id: &Uuid, req: &Request, audience: &str) {
+    let user_repo = UserRepository::new(pool.clone());
+    let audit_repo = AuditRepository::new(pool.clone());
+
+    match user_repo.cancel_account_deletion(user_id).await {
+        Ok(true) => {
+            if let Err(e) = audit_repo
+                .record(user_id, ACCOUNT_DELETION_CANCELLED, req, json!({}))
+                .await
+            {
+                eprintln!("Failed to record audit event: {}", e);
+            }
+        }
+        Ok(false) => {}
+        Err(e) => eprintln!("Failed to cancel account deletion: {}", e),
+    }
+
+    if let Err(e) = audit_repo
+        .record(user_id, SIGNED_IN, req, json!({ "audience": audience }))
+        .await
+    {
+        eprintln!("Failed to record audit event: {}", e);
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use vercel_runtime::Request;
+
+use crate::auth::{access_token_ttl, generate_token, hash_token, jwt_issuer, resolve_audience};
+use crate::rbac::RoleRepository;
+use crate::{ApiKey, Claims, CreateApiKeyRequest, UserRepository};
+
+/// Every API key starts with this, which is how a bearer token is told apart from a JWT.
+pub const API_KEY_PREFIX: &str = "sk_";
+
+/// How much of a key is stored in the clear and shown in listings.
+const DISPLAY_PREFIX_LENGTH: usize = 11;
+
+/// How often using a key moves its `last_used_at` forward.
+const LAST_USED_INTERVAL_SECONDS: i64 = 60;
+
+const MAX_NAME_LENGTH: usize = 100;
+
+/// The API key sent with a request, either as `X-API-Key` or as a bearer token
+/// starting with `sk_`.
+pub fn extract_api_key(req: &Request) -> Option<&str> {
+    let headers = req.headers();
+
+    if let Some(key) = headers
+        .get("x-api-key")
+        .and_then(|header| header.to_str().ok())
+    {
+        return Some(key.trim());
+    }
+
+    headers
+        .get("authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .filter(|token| token.starts_with(API_KEY_PREFIX))
+}
+
+/// Checks a key request, returning the message for a 400 if it is unacceptable. Keys can
+/// only be given permissions the caller's own token grants.
+pub fn validate_api_key_request(
+    request: &CreateApiKeyRequest,
+    granted_permissions: &[String],
+) -> Result<(), String> {
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "Name must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+
+    if request.scopes.is_empty() {
+        return Err("At least one scope is required".to_string());
+    }
+
+    if let Some(scope) = request
+        .scopes
+        .iter()
+        .find(|scope| !granted_permissions.contains(scope))
+    {
+        return Err(format!("Cannot grant scope: {}", scope));
+    }
+
+    if request.expires_in_days == Some(0) {
+        return Err("expires_in_days must be at least 1".to_string());
+    }
+
+    Ok(())
+}
+
+pub struct ApiKeyRepository {
+    pool: PgPool,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates a key working in the given organization and returns it with its secret,
+    /// which is not stored and cannot be shown again.
+    pub async fn create_api_key(
+        &self,
+        user_id: &Uuid,
+        organization_id: Option<&Uuid>,
+        request: &CreateApiKeyRequest,
+    ) -> Result<(ApiKey, String)> {
+        let key = format!("{}{}", API_KEY_PREFIX, generate_token());
+        let expires_at = request
+            .expires_in_days
+            .map(|days| Utc::now() + Duration::days(days as i64));
+
+        let mut scopes = request.scopes.clone();
+        scopes.sort();
+        scopes.dedup();
+
+        let api_key = sqlx::query_as::<_, ApiKey>(
+            r#"
+            INSERT INTO api_keys
+                (id, user_id, organization_id, name, prefix, key_hash, scopes, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            RETURNING id, name, prefix, scopes, organization_id, expires_at, last_used_at, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(organization_id)
+        .bind(request.name.trim())
+        .bind(&key[..DISPLAY_PREFIX_LENGTH])
+        .bind(hash_token(&key))
+        .bind(&scopes)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((api_key, key))
+    }
+
+    /// Lists the user's keys that have not been revoked, including expired ones.
+    pub async fn list_api_keys(&self, user_id: &Uuid) -> Result<Vec<ApiKey>> {
+        let api_keys = sqlx::query_as::<_, ApiKey>(
+            r#"
+            SELECT id, name, prefix, scopes, organization_id, expires_at, last_used_at, created_at
+            FROM api_keys
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(api_keys)
+    }
+
+    /// Revokes one of the user's keys. Returns whether an unrevoked key was found.
+    pub async fn revoke_api_key(&self, user_id: &Uuid, api_key_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(api_key_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Looks up a key and returns claims for its owner, as if it were an access token.
+    ///
+    /// Roles and memberships are read now, so the key's permissions are its scopes that the
+    /// owner still holds. The key's id is the `jti`, and there is no session. A key without
+    /// an expiry gets the `exp` of an access token issued now.
+    pub async fn authenticate_api_key(&self, key: &str) -> Result<Claims> {
+        let row = sqlx::query(
+            r#"
+            SELECT api_keys.id, api_keys.user_id, api_keys.organization_id, api_keys.scopes,
+                api_keys.expires_at, api_keys.last_used_at, api_keys.created_at
+            FROM api_keys
+            JOIN users ON users.id = api_keys.user_id
+            WHERE api_keys.key_hash = $1
+            AND api_keys.revoked_at IS NULL
+            AND (api_keys.expires_at IS NULL OR api_keys.expires_at > NOW())
+            AND users.deleted_at IS NULL
+            "#,
+        )
+        .bind(hash_token(key))
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("Invalid API key"))?;
+
+        let api_key_id: Uuid = row.get("id");
+        let user_id: Uuid = row.get("user_id");
+        let organization_id: Option<Uuid> = row.get("organization_id");
+        let scopes: Vec<String> = row.get("scopes");
+        let expires_at: Option<DateTime<Utc>> = row.get("expires_at");
+        let last_used_at: Option<DateTime<Utc>> = row.get("last_used_at");
+        let created_at: DateTime<Utc> = row.get("created_at");
+
+        let user = UserRepository::new(self.pool.clone())
+            .get_user_by_id(&user_id)
+            .await?
+            .ok_or_else(|| anyhow!("User not found"))?;
+        let access = RoleRepository::new(self.pool.clone())
+            .get_user_access(&user_id, organization_id.as_ref())
+            .await?;
+
+        // Only write when last_used_at is due to move, not on every request
+        let touch_before = Utc::now() - Duration::seconds(LAST_USED_INTERVAL_SECONDS);
+        if last_used_at.is_none_or(|last_used_at| last_used_at < touch_before) {
+            self.touch_api_key(&api_key_id).await?;
+        }
+
+        Ok(Claims {
+            sub: user.id.to_string(),
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            iss: jwt_issuer(),
+            aud: resolve_audience(None)?,
+            exp: expires_at
+                .unwrap_or_else(|| Utc::now() + access_token_ttl())
+                .timestamp() as usize,
+            nbf: created_at.timestamp() as usize,
+            iat: created_at.timestamp() as usize,
+            jti: api_key_id.to_string(),
+            sid: None,
+            auth_time: None,
+            roles: access.roles,
+            permissions: access
+                .permissions
+                .into_iter()
+                .filter(|permission| scopes.contains(permission))
+                .collect(),
+            org: access.organization_id.map(|id| id.to_string()),
+            org_role: access.organization_role,
+        })
+    }
+
+    async fn touch_api_key(&self, api_key_id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE api_keys SET last_used_at = NOW()
+            WHERE id = $1
+            AND (last_used_at IS NULL OR last_used_at < NOW() - make_interval(secs => $2))
+            "#,
+        )
+        .bind(api_key_id)
+        .bind(LAST_USED_INTERVAL_SECONDS as f64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::Request;
+
+use crate::{client_ip, AuditEvent};
+
+pub const ACCOUNT_CREATED: &str = "account_created";
+pub const SIGNED_IN: &str = "signed_in";
+pub const PASSWORD_CHANGED: &str = "password_changed";
+pub const PASSWORD_RESET: &str = "password_reset";
+pub const EMAIL_CHANGED: &str = "email_changed";
+pub const EMAIL_CHANGE_UNDONE: &str = "email_change_undone";
+pub const MFA_ENABLED: &str = "mfa_enabled";
+pub const PASSKEY_ADDED: &str = "passkey_added";
+pub const PASSKEY_REMOVED: &str = "passkey_removed";
+pub const ACCOUNT_DELETION_REQUESTED: &str = "account_deletion_requested";
+pub const ACCOUNT_DELETION_CANCELLED: &str = "account_deletion_cancelled";
+pub const DATA_EXPORTED: &str = "data_exported";
+
+/// Security-relevant account activity, kept per user and handed back in their data export.
+pub struct AuditRepository {
+    pool: PgPool,
+}
+
+impl AuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Records an event with the client IP and user agent of the request that caused it.
+    pub async fn record(
+        &self,
+        user_id: &Uuid,
+        event: &str,
+        req: &Request,
+        metadata: Value,
+    ) -> Result<()> {
+        let user_agent = req
+            .headers()
+            .get("user-agent")
+            .and_then(|header| header.to_str().ok());
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events (id, user_id, event, ip_address, user_agent, metadata, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(event)
+        .bind(client_ip(req))
+        .bind(user_agent)
+        .bind(metadata)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_user_events(&self, user_id: &Uuid) -> Result<Vec<AuditEvent>> {
+        let events = sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT id, event, ip_address, user_agent, metadata, created_at FROM audit_events
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+}
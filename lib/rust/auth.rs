@@ -1,65 +1,529 @@
 use anyhow::{anyhow, Result};
-use bcrypt::{hash, verify, DEFAULT_COST};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
-use sqlx::{PgPool, Row};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
 use std::env;
 use uuid::Uuid;
+use vercel_runtime::{Request, StatusCode};
 
-use crate::{Claims, CreateUserRequest, User, UserRepository, UserWithPassword};
+use crate::api_keys::{extract_api_key, ApiKeyRepository};
+use crate::keys::JwtKey;
+use crate::organizations::OrganizationRepository;
+use crate::password_hashing::PasswordHasher;
+use crate::rbac::{RoleRepository, UserAccess, DEFAULT_ROLE};
+use crate::refresh_tokens::RefreshTokenRepository;
+use crate::revocation::{
+    revoke_all_api_keys_in, revoke_all_user_tokens_in, TokenRevocationRepository,
+};
+use crate::sessions::SessionRepository;
+use crate::{
+    app_url, ApiError, AuthResponse, Claims, CreateUserRequest, MfaChallengeClaims,
+    OidcIdTokenClaims, OidcUserInfo, User, UserRepository, UserWithPassword,
+};
+
+const DEFAULT_ACCESS_TOKEN_TTL_SECONDS: i64 = 15 * 60;
+const DEFAULT_REFRESH_TOKEN_TTL_SECONDS: i64 = 30 * 24 * 60 * 60;
+const DEFAULT_MFA_CHALLENGE_TTL_SECONDS: i64 = 5 * 60;
+const DEFAULT_REAUTH_MAX_AGE_SECONDS: i64 = 5 * 60;
+const MFA_CHALLENGE_PURPOSE: &str = "mfa_challenge";
+const DEFAULT_JWT_LEEWAY_SECONDS: u64 = 60;
 
 pub fn hash_password(password: &str) -> Result<String> {
-    let hashed = hash(password, DEFAULT_COST)?;
+    let hashed = PasswordHasher::from_env()?.hash(password)?;
     Ok(hashed)
 }
 
 pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
-    let is_valid = verify(password, hash)?;
+    let is_valid = PasswordHasher::from_env()?.verify(password, hash)?;
     Ok(is_valid)
 }
 
-pub fn create_jwt(user_id: &Uuid, email: &str) -> Result<String> {
-    let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+pub(crate) fn ttl_from_env(name: &str, default_seconds: i64) -> chrono::Duration {
+    let seconds = env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(default_seconds);
+
+    chrono::Duration::seconds(seconds)
+}
+
+/// Lifetime of access tokens, configurable through `ACCESS_TOKEN_TTL_SECONDS`.
+pub fn access_token_ttl() -> chrono::Duration {
+    ttl_from_env("ACCESS_TOKEN_TTL_SECONDS", DEFAULT_ACCESS_TOKEN_TTL_SECONDS)
+}
+
+/// Lifetime of refresh tokens, configurable through `REFRESH_TOKEN_TTL_SECONDS`.
+pub fn refresh_token_ttl() -> chrono::Duration {
+    ttl_from_env(
+        "REFRESH_TOKEN_TTL_SECONDS",
+        DEFAULT_REFRESH_TOKEN_TTL_SECONDS,
+    )
+}
+
+/// Lifetime of MFA challenge tokens, configurable through `MFA_CHALLENGE_TTL_SECONDS`.
+pub fn mfa_challenge_ttl() -> chrono::Duration {
+    ttl_from_env(
+        "MFA_CHALLENGE_TTL_SECONDS",
+        DEFAULT_MFA_CHALLENGE_TTL_SECONDS,
+    )
+}
+
+/// How recently the user must have signed in for sensitive account changes, configurable
+/// through `REAUTH_MAX_AGE_SECONDS`.
+pub fn reauth_max_age() -> chrono::Duration {
+    ttl_from_env("REAUTH_MAX_AGE_SECONDS", DEFAULT_REAUTH_MAX_AGE_SECONDS)
+}
+
+/// Checks that the token comes from a sign-in made within `reauth_max_age`, returning the 403
+/// `ApiError` to send back otherwise. Refreshed tokens keep the time of the original sign-in,
+/// and API keys never count as recent.
+pub fn require_recent_auth(claims: &Claims) -> Result<(), ApiError> {
+    let cutoff = (chrono::Utc::now() - reauth_max_age()).timestamp() as usize;
+    if claims
+        .auth_time
+        .is_some_and(|auth_time| auth_time >= cutoff)
+    {
+        return Ok(());
+    }
+    Err(ApiError {
+        message: "Sign in again to confirm it's you".to_string(),
+        code: StatusCode::FORBIDDEN.as_u16(),
+    })
+}
+
+/// The `iss` claim, configurable through `JWT_ISSUER` and defaulting to `APP_URL`.
+pub fn jwt_issuer() -> String {
+    env::var("JWT_ISSUER").unwrap_or_else(|_| app_url())
+}
+
+/// Audiences tokens may be minted for, from the comma separated `JWT_AUDIENCE`.
+/// The first entry is the default; without the variable the issuer is the only audience.
+pub fn jwt_audiences() -> Vec<String> {
+    let audiences: Vec<String> = env::var("JWT_AUDIENCE")
+        .unwrap_or_default()
+        .split(',')
+        .map(|audience| audience.trim().to_string())
+        .filter(|audience| !audience.is_empty())
+        .collect();
+
+    if audiences.is_empty() {
+        vec![jwt_issuer()]
+    } else {
+        audiences
+    }
+}
+
+/// Picks the audience for a new token, rejecting any the backend isn't configured for.
+pub fn resolve_audience(requested: Option<&str>) -> Result<String> {
+    let audiences = jwt_audiences();
+
+    match requested {
+        None => Ok(audiences[0].clone()),
+        Some(audience) if audiences.iter().any(|allowed| allowed == audience) => {
+            Ok(audience.to_string())
+        }
+        Some(audience) => Err(anyhow!("Unknown audience: {}", audience)),
+    }
+}
+
+/// Clock skew tolerated for `exp` and `nbf`, configurable through `JWT_LEEWAY_SECONDS`.
+fn jwt_leeway() -> u64 {
+    env::var("JWT_LEEWAY_SECONDS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_JWT_LEEWAY_SECONDS)
+}
+
+/// Generates a random opaque token suitable for refresh tokens and similar secrets.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes an opaque token for storage; only the hash is ever written to the database.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The keys used for JWTs: one active key that signs new tokens, plus previous keys that
+/// still verify tokens issued before a rotation until those tokens expire.
+pub struct Keyring {
+    pub active: JwtKey,
+    pub previous: Vec<JwtKey>,
+}
+
+/// A retired key as configured in `JWT_PREVIOUS_KEYS`.
+#[derive(Deserialize)]
+struct PreviousKeyConfig {
+    kid: String,
+    alg: String,
+    public_key: Option<String>,
+    secret: Option<String>,
+}
+
+impl PreviousKeyConfig {
+    fn into_key(self) -> Result<JwtKey> {
+        match (self.alg.as_str(), self.secret, self.public_key) {
+            ("HS256", Some(secret), _) => Ok(JwtKey::hmac(&self.kid, &secret)),
+            ("RS256", _, Some(public_key)) => JwtKey::rsa_public_pem(&self.kid, &public_key),
+            ("EdDSA", _, Some(public_key)) => JwtKey::ed25519_public_pem(&self.kid, &public_key),
+            (alg, _, _) => Err(anyhow!(
+                "Previous key {} needs a secret (HS256) or public_key (RS256, EdDSA), got {}",
+                self.kid,
+                alg
+            )),
+        }
+    }
+}
+
+impl Keyring {
+    /// Loads the active key with `JwtKey::from_env` and the previous keys from
+    /// `JWT_PREVIOUS_KEYS`, a JSON array of `{ "kid", "alg", "public_key" | "secret" }`.
+    pub fn from_env() -> Result<Self> {
+        let active = JwtKey::from_env()?;
+
+        let previous = match env::var("JWT_PREVIOUS_KEYS") {
+            Ok(value) if !value.trim().is_empty() => {
+                serde_json::from_str::<Vec<PreviousKeyConfig>>(&value)?
+                    .into_iter()
+                    .map(PreviousKeyConfig::into_key)
+                    .collect::<Result<Vec<_>>>()?
+            }
+            _ => Vec::new(),
+        };
+
+        if previous.iter().any(|key| key.kid == active.kid) {
+            return Err(anyhow!("Previous key reuses the active kid {}", active.kid));
+        }
+
+        Ok(Self { active, previous })
+    }
+
+    /// Finds the key a token was signed with. Tokens issued before key ids were added carry
+    /// no `kid` and are checked against the active key.
+    pub fn find(&self, kid: Option<&str>) -> Option<&JwtKey> {
+        match kid {
+            None => Some(&self.active),
+            Some(kid) => std::iter::once(&self.active)
+                .chain(self.previous.iter())
+                .find(|key| key.kid == kid),
+        }
+    }
+
+    /// The public keys other services can use to verify our tokens, including previous keys
+    /// so tokens issued before a rotation still verify elsewhere.
+    pub fn public_jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: std::iter::once(&self.active)
+                .chain(self.previous.iter())
+                .filter_map(|key| key.public_jwk().cloned())
+                .collect(),
+        }
+    }
+
+    /// Signs claims with the active key, tagging the header with its `kid`.
+    fn sign<T: Serialize>(&self, claims: &T) -> Result<String> {
+        let key = &self.active;
+
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+
+        let token = encode(&header, claims, key.encoding_key()?)?;
+
+        Ok(token)
+    }
+
+    /// Verifies a token against the key named by its `kid` header, along with its expiry,
+    /// not-before time, issuer and audience.
+    fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T> {
+        let header = decode_header(token)?;
+        let key = self
+            .find(header.kid.as_deref())
+            .ok_or_else(|| anyhow!("Unknown signing key"))?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[jwt_issuer()]);
+        validation.set_audience(&jwt_audiences());
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud"]);
+        validation.validate_nbf = true;
+        validation.leeway = jwt_leeway();
+
+        let token_data = decode::<T>(token, key.decoding_key(), &validation)?;
+
+        Ok(token_data.claims)
+    }
+}
+
+/// Signs claims with the active key from the environment.
+fn sign_token<T: Serialize>(claims: &T) -> Result<String> {
+    Keyring::from_env()?.sign(claims)
+}
+
+/// Verifies a token against the keys from the environment.
+fn verify_token<T: DeserializeOwned>(token: &str) -> Result<T> {
+    Keyring::from_env()?.verify(token)
+}
+
+/// The public keys other services can use to verify our tokens. HS256 keys are never listed.
+pub fn public_jwks() -> Result<JwkSet> {
+    Ok(Keyring::from_env()?.public_jwks())
+}
 
+/// Creates an access token for the given audience (see `resolve_audience`) and session,
+/// embedding the user's roles and permissions.
+pub fn create_jwt(
+    user: &User,
+    audience: &str,
+    access: &UserAccess,
+    session_id: Option<&Uuid>,
+    auth_time: Option<usize>,
+) -> Result<String> {
     let now = chrono::Utc::now();
-    let exp = (now + chrono::Duration::hours(24)).timestamp() as usize;
+    let exp = (now + access_token_ttl()).timestamp() as usize;
     let iat = now.timestamp() as usize;
 
     let claims = Claims {
-        sub: user_id.to_string(),
-        email: email.to_string(),
+        sub: user.id.to_string(),
+        email: user.email.clone(),
+        email_verified: user.email_verified_at.is_some(),
+        iss: jwt_issuer(),
+        aud: audience.to_string(),
         exp,
+        nbf: iat,
         iat,
+        jti: Uuid::new_v4().to_string(),
+        sid: session_id.map(|id| id.to_string()),
+        auth_time,
+        roles: access.roles.clone(),
+        permissions: access.permissions.clone(),
+        org: access.organization_id.map(|id| id.to_string()),
+        org_role: access.organization_role.clone(),
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_ref()),
-    )?;
-
-    Ok(token)
+    sign_token(&claims)
 }
 
 pub fn verify_jwt(token: &str) -> Result<Claims> {
-    let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    verify_token(token)
+}
+
+/// Creates the short-lived token a user with MFA enabled receives after a correct password.
+///
+/// It carries no email, so it can never be decoded as access token `Claims`.
+pub fn create_mfa_challenge_token(user_id: &Uuid, audience: &str) -> Result<String> {
+    let now = chrono::Utc::now();
+    let claims = MfaChallengeClaims {
+        sub: user_id.to_string(),
+        purpose: MFA_CHALLENGE_PURPOSE.to_string(),
+        iss: jwt_issuer(),
+        aud: audience.to_string(),
+        exp: (now + mfa_challenge_ttl()).timestamp() as usize,
+        nbf: now.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        jti: Uuid::new_v4().to_string(),
+    };
+
+    sign_token(&claims)
+}
+
+/// Verifies an MFA challenge token and returns the user it was issued to and the audience
+/// the final tokens are for.
+pub fn verify_mfa_challenge_token(token: &str) -> Result<(Uuid, String)> {
+    let claims = verify_mfa_challenge_claims(token)?;
+    Ok((Uuid::parse_str(&claims.sub)?, claims.aud))
+}
+
+/// Verifies an MFA challenge token and uses it up once the second factor has been checked,
+/// so the same challenge cannot finish another signin.
+pub async fn consume_mfa_challenge_token(pool: &PgPool, token: &str) -> Result<(Uuid, String)> {
+    let claims = verify_mfa_challenge_claims(token)?;
+    let user_id = Uuid::parse_str(&claims.sub)?;
+
+    if !TokenRevocationRepository::new(pool.clone())
+        .use_once(&claims.jti, &user_id, claims.exp)
+        .await?
+    {
+        return Err(anyhow!("MFA challenge has already been used"));
+    }
+
+    Ok((user_id, claims.aud))
+}
+
+fn verify_mfa_challenge_claims(token: &str) -> Result<MfaChallengeClaims> {
+    let claims: MfaChallengeClaims = verify_token(token)?;
+
+    if claims.purpose != MFA_CHALLENGE_PURPOSE {
+        return Err(anyhow!("Not an MFA challenge token"));
+    }
+
+    Ok(claims)
+}
+
+/// Creates an ID token for an OpenID Connect client (see `oidc_provider`), valid as long as
+/// an access token.
+///
+/// Clients verify it against the published JWKS, so the active key must not be HS256.
+pub fn create_id_token(
+    user: OidcUserInfo,
+    client_id: &str,
+    nonce: Option<String>,
+    auth_time: chrono::DateTime<chrono::Utc>,
+) -> Result<String> {
+    if Keyring::from_env()?.active.public_jwk().is_none() {
+        return Err(anyhow!("ID tokens need an RS256 or EdDSA signing key"));
+    }
+
+    let now = chrono::Utc::now();
+    let claims = OidcIdTokenClaims {
+        iss: jwt_issuer(),
+        aud: client_id.to_string(),
+        exp: (now + access_token_ttl()).timestamp() as usize,
+        iat: now.timestamp() as usize,
+        auth_time: auth_time.timestamp() as usize,
+        nonce,
+        user,
+    };
+
+    sign_token(&claims)
+}
+
+/// Verifies an access token's signature and expiry, then checks that it has not been revoked.
+pub async fn verify_access_token(pool: &PgPool, token: &str) -> Result<Claims> {
+    let claims = verify_jwt(token)?;
+    let user_id = Uuid::parse_str(&claims.sub)?;
+
+    if TokenRevocationRepository::new(pool.clone())
+        .is_revoked(&user_id, &claims)
+        .await?
+    {
+        return Err(anyhow!("Token has been revoked"));
+    }
 
-    let token_data = decode::<Claims>(
+    if let Some(session_id) = claims.session_id() {
+        if let Err(e) = SessionRepository::new(pool.clone())
+            .touch_session(&session_id, &claims.jti)
+            .await
+        {
+            eprintln!("Failed to update session activity: {}", e);
+        }
+    }
+
+    Ok(claims)
+}
+
+/// Authenticates a request to an endpoint that machine clients may call: an API key (see
+/// `api_keys::extract_api_key`) or an access token in the `Authorization` header.
+///
+/// Returns the 401 `ApiError` to send back when neither is valid.
+pub async fn authenticate_request(pool: &PgPool, req: &Request) -> Result<Claims, ApiError> {
+    let unauthorized = |message: &str| ApiError {
+        message: message.to_string(),
+        code: StatusCode::UNAUTHORIZED.as_u16(),
+    };
+
+    if let Some(key) = extract_api_key(req) {
+        return ApiKeyRepository::new(pool.clone())
+            .authenticate_api_key(key)
+            .await
+            .map_err(|_| unauthorized("Invalid or expired API key"));
+    }
+
+    let auth_header = match req.headers().get("authorization") {
+        Some(header) => header
+            .to_str()
+            .map_err(|_| unauthorized("Invalid authorization header"))?,
+        None => return Err(unauthorized("Authorization header required")),
+    };
+    let token = extract_bearer_token(auth_header)
+        .map_err(|_| unauthorized("Invalid authorization header format"))?;
+
+    verify_access_token(pool, token)
+        .await
+        .map_err(|_| unauthorized("Invalid or expired token"))
+}
+
+/// Starts a session from the requesting device for a freshly authenticated user, working in
+/// the first organization they joined, and issues its access and refresh tokens.
+pub async fn issue_auth_response(
+    pool: &PgPool,
+    req: &Request,
+    user: User,
+    audience: &str,
+) -> Result<AuthResponse> {
+    let organization_id = OrganizationRepository::new(pool.clone())
+        .default_organization(&user.id)
+        .await?;
+
+    issue_auth_response_in_organization(pool, req, user, audience, organization_id.as_ref()).await
+}
+
+/// Starts a session working in the given organization, for when the user should carry on
+/// where they were rather than start over.
+pub async fn issue_auth_response_in_organization(
+    pool: &PgPool,
+    req: &Request,
+    user: User,
+    audience: &str,
+    organization_id: Option<&Uuid>,
+) -> Result<AuthResponse> {
+    let access = RoleRepository::new(pool.clone())
+        .get_user_access(&user.id, organization_id)
+        .await?;
+    let session_id = SessionRepository::new(pool.clone())
+        .create_session(&user.id, audience, req)
+        .await?;
+    let auth_time = chrono::Utc::now().timestamp() as usize;
+    let token = create_jwt(&user, audience, &access, Some(&session_id), Some(auth_time))?;
+    let refresh_token = RefreshTokenRepository::new(pool.clone())
+        .create_refresh_token(
+            &user.id,
+            &session_id,
+            audience,
+            access.organization_id.as_ref(),
+        )
+        .await?;
+
+    Ok(AuthResponse {
+        user,
         token,
-        &DecodingKey::from_secret(jwt_secret.as_ref()),
-        &Validation::new(Algorithm::HS256),
-    )?;
+        refresh_token,
+        expires_in: access_token_ttl().num_seconds() as usize,
+    })
+}
+
+/// Performs a deliberately loose syntax check; only a verification email proves an address works.
+pub fn is_valid_email(email: &str) -> bool {
+    if email.len() > 255 || email.chars().any(char::is_whitespace) {
+        return false;
+    }
 
-    Ok(token_data.claims)
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+        }
+        None => false,
+    }
 }
 
 pub fn extract_bearer_token(auth_header: &str) -> Result<&str> {
-    if auth_header.starts_with("Bearer ") {
-        Ok(&auth_header[7..])
-    } else {
-        Err(anyhow!("Invalid authorization header format"))
-    }
+    auth_header
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| anyhow!("Invalid authorization header format"))
 }
 
+/// Columns selected for every `User` read, in `User` field order.
+pub(crate) const USER_COLUMNS: &str = "id, email, username, display_name, avatar_url, locale, timezone, email_verified_at, created_at, updated_at";
+
 impl UserRepository {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
@@ -81,92 +545,296 @@ impl UserRepository {
         let password_hash = hash_password(&request.password)?;
         let user_id = Uuid::new_v4();
 
+        let mut tx = self.pool.begin().await?;
+
         // Create user
-        let user_row = sqlx::query(
+        let user = sqlx::query_as::<_, User>(&format!(
             r#"
             INSERT INTO users (id, email, username, password_hash, created_at, updated_at)
             VALUES ($1, $2, $3, $4, NOW(), NOW())
-            RETURNING id, email, username, created_at, updated_at
+            RETURNING {}
             "#,
-        )
-        .bind(&user_id)
+            USER_COLUMNS
+        ))
+        .bind(user_id)
         .bind(&request.email)
         .bind(&request.username)
         .bind(&password_hash)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
-        Ok(User {
-            id: user_row.get("id"),
-            email: user_row.get("email"),
-            username: user_row.get("username"),
-            created_at: user_row.get("created_at"),
-            updated_at: user_row.get("updated_at"),
-        })
+        // Every account starts with the default role
+        sqlx::query(
+            r#"
+            INSERT INTO user_roles (user_id, role_id, created_at)
+            SELECT $1, id, NOW() FROM roles WHERE name = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(DEFAULT_ROLE)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(user)
     }
 
     pub async fn authenticate_user(&self, email: &str, password: &str) -> Result<User> {
-        let user_row = sqlx::query_as::<_, UserWithPassword>(
-            "SELECT id, email, username, password_hash, created_at, updated_at FROM users WHERE email = $1"
-        )
+        let user_row = sqlx::query_as::<_, UserWithPassword>(&format!(
+            "SELECT {}, password_hash FROM users WHERE email = $1",
+            USER_COLUMNS
+        ))
         .bind(email)
         .fetch_optional(&self.pool)
         .await?;
 
         let user_data = user_row.ok_or_else(|| anyhow!("Invalid credentials"))?;
 
-        if !verify_password(password, &user_data.password_hash)? {
+        let hasher = PasswordHasher::from_env()?;
+        if !hasher.verify(password, &user_data.password_hash)? {
             return Err(anyhow!("Invalid credentials"));
         }
 
-        Ok(User {
-            id: user_data.id,
-            email: user_data.email,
-            username: user_data.username,
-            created_at: user_data.created_at,
-            updated_at: user_data.updated_at,
-        })
+        // The plaintext is only available now, so this is when old bcrypt hashes or
+        // outdated parameters get upgraded. A failure here must not block the signin.
+        if hasher.needs_rehash(&user_data.password_hash) {
+            if let Err(e) = self
+                .rehash_password(
+                    &user_data.user.id,
+                    &user_data.password_hash,
+                    password,
+                    &hasher,
+                )
+                .await
+            {
+                eprintln!("Failed to upgrade password hash: {}", e);
+            }
+        }
+
+        Ok(user_data.user)
+    }
+
+    async fn rehash_password(
+        &self,
+        user_id: &Uuid,
+        old_hash: &str,
+        password: &str,
+        hasher: &PasswordHasher,
+    ) -> Result<()> {
+        let password_hash = hasher.hash(password)?;
+
+        // Only replace the hash that was verified, in case the password changed meanwhile
+        sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3")
+            .bind(&password_hash)
+            .bind(user_id)
+            .bind(old_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
     }
 
     pub async fn get_user_by_id(&self, user_id: &Uuid) -> Result<Option<User>> {
-        let user_row = sqlx::query(
-            "SELECT id, email, username, created_at, updated_at FROM users WHERE id = $1",
-        )
-        .bind(user_id)
-        .fetch_optional(&self.pool)
-        .await?;
+        let user =
+            sqlx::query_as::<_, User>(&format!("SELECT {} FROM users WHERE id = $1", USER_COLUMNS))
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
 
-        if let Some(row) = user_row {
-            Ok(Some(User {
-                id: row.get("id"),
-                email: row.get("email"),
-                username: row.get("username"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            }))
-        } else {
-            Ok(None)
-        }
+        Ok(user)
     }
 
     pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
-        let user_row = sqlx::query(
-            "SELECT id, email, username, created_at, updated_at FROM users WHERE email = $1",
-        )
+        let user = sqlx::query_as::<_, User>(&format!(
+            "SELECT {} FROM users WHERE email = $1",
+            USER_COLUMNS
+        ))
         .bind(email)
         .fetch_optional(&self.pool)
         .await?;
 
-        if let Some(row) = user_row {
-            Ok(Some(User {
-                id: row.get("id"),
-                email: row.get("email"),
-                username: row.get("username"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            }))
-        } else {
-            Ok(None)
+        Ok(user)
+    }
+
+    /// Sets a new password, signs the user out everywhere and revokes their API keys, all in
+    /// one transaction, so a key created with a stolen password can't outlive it.
+    pub async fn update_password(&self, user_id: &Uuid, new_password: &str) -> Result<()> {
+        let password_hash = hash_password(new_password)?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let result =
+            sqlx::query("UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2")
+                .bind(&password_hash)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("User not found"));
+        }
+
+        revoke_all_user_tokens_in(&mut tx, user_id).await?;
+        revoke_all_api_keys_in(&mut tx, user_id).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_valid_email_accepts_ordinary_addresses() {
+        for email in [
+            "user@example.com",
+            "first.last+tag@mail.example.co.uk",
+            "a@b.c",
+        ] {
+            assert!(is_valid_email(email), "{}", email);
+        }
+    }
+
+    #[test]
+    fn is_valid_email_rejects_malformed_addresses() {
+        for email in [
+            "",
+            "user",
+            "@example.com",
+            "user@",
+            "user@localhost",
+            "user@.example.com",
+            "user@example.com.",
+            "user@@example.com",
+            "user@exa@mple.com",
+            "us er@example.com",
+            "user@example.com\n",
+        ] {
+            assert!(!is_valid_email(email), "{:?}", email);
+        }
+    }
+
+    #[test]
+    fn is_valid_email_limits_length() {
+        let local = "a".repeat(255 - "@example.com".len());
+        assert!(is_valid_email(&format!("{}@example.com", local)));
+        assert!(!is_valid_email(&format!("{}a@example.com", local)));
+    }
+
+    fn keyring() -> Keyring {
+        Keyring {
+            active: JwtKey::hmac("current", "current-secret"),
+            previous: vec![
+                JwtKey::hmac("older", "older-secret"),
+                JwtKey::hmac("oldest", "oldest-secret"),
+            ],
         }
     }
+
+    #[test]
+    fn keyring_find_uses_the_active_key_without_a_kid() {
+        assert_eq!(
+            keyring().find(None).map(|key| key.kid.as_str()),
+            Some("current")
+        );
+    }
+
+    #[test]
+    fn keyring_find_matches_active_and_previous_kids() {
+        let keyring = keyring();
+        for kid in ["current", "older", "oldest"] {
+            assert_eq!(
+                keyring.find(Some(kid)).map(|key| key.kid.as_str()),
+                Some(kid)
+            );
+        }
+    }
+
+    #[test]
+    fn keyring_find_rejects_unknown_kids() {
+        let keyring = keyring();
+        assert!(keyring.find(Some("retired")).is_none());
+        assert!(keyring.find(Some("")).is_none());
+        assert!(keyring.find(Some("Current")).is_none());
+    }
+
+    fn challenge_claims() -> MfaChallengeClaims {
+        let now = chrono::Utc::now().timestamp() as usize;
+        MfaChallengeClaims {
+            sub: Uuid::new_v4().to_string(),
+            purpose: MFA_CHALLENGE_PURPOSE.to_string(),
+            iss: jwt_issuer(),
+            aud: jwt_audiences()[0].clone(),
+            exp: now + 60,
+            nbf: now,
+            iat: now,
+            jti: Uuid::new_v4().to_string(),
+        }
+    }
+
+    fn rsa_key(kid: &str) -> JwtKey {
+        JwtKey::rsa_private_pem(kid, include_str!("data/test-rsa-private.pem")).unwrap()
+    }
+
+    fn rsa_public_key(kid: &str) -> JwtKey {
+        JwtKey::rsa_public_pem(kid, include_str!("data/test-rsa-public.pem")).unwrap()
+    }
+
+    #[test]
+    fn tokens_signed_by_a_rotated_key_still_verify() {
+        let before = Keyring {
+            active: rsa_key("2026-01"),
+            previous: Vec::new(),
+        };
+        let claims = challenge_claims();
+        let token = before.sign(&claims).unwrap();
+
+        let after = Keyring {
+            active: JwtKey::hmac("2026-02", "new-secret"),
+            previous: vec![rsa_public_key("2026-01")],
+        };
+        let verified: MfaChallengeClaims = after.verify(&token).unwrap();
+        assert_eq!(verified.jti, claims.jti);
+
+        // New tokens come from the new active key
+        let token = after.sign(&claims).unwrap();
+        assert_eq!(
+            decode_header(&token).unwrap().kid.as_deref(),
+            Some("2026-02")
+        );
+        assert!(after.verify::<MfaChallengeClaims>(&token).is_ok());
+    }
+
+    #[test]
+    fn tokens_signed_by_an_unknown_kid_are_rejected() {
+        let retired = Keyring {
+            active: rsa_key("2025-12"),
+            previous: Vec::new(),
+        };
+        let token = retired.sign(&challenge_claims()).unwrap();
+
+        // Same key material, but the kid is no longer in the keyring
+        let current = Keyring {
+            active: JwtKey::hmac("2026-02", "new-secret"),
+            previous: vec![rsa_public_key("2026-01")],
+        };
+        let error = current.verify::<MfaChallengeClaims>(&token).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown signing key");
+    }
+
+    #[test]
+    fn tokens_are_checked_against_the_key_their_kid_names() {
+        let keyring = keyring();
+        let forged = Keyring {
+            active: JwtKey::hmac("older", "guessed-secret"),
+            previous: Vec::new(),
+        };
+        let token = forged.sign(&challenge_claims()).unwrap();
+
+        assert!(keyring.verify::<MfaChallengeClaims>(&token).is_err());
+    }
 }
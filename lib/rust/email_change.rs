@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::auth::{generate_token, hash_token, ttl_from_env};
+use crate::mailer::{mailer_from_env, EmailMessage};
+use crate::{app_url, User};
+
+const DEFAULT_EMAIL_CHANGE_TTL_SECONDS: i64 = 24 * 60 * 60;
+const DEFAULT_EMAIL_CHANGE_UNDO_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+/// An email change that has just been applied.
+#[derive(Debug)]
+pub struct AppliedEmailChange {
+    pub user_id: Uuid,
+    pub old_email: String,
+    pub new_email: String,
+    /// Emailed to the old address so its owner can reverse the change.
+    pub undo_token: String,
+}
+
+pub struct EmailChangeRepository {
+    pool: PgPool,
+}
+
+impl EmailChangeRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Records a request to move the user to a new address and returns the token to send
+    /// there. Earlier requests that were never confirmed are cancelled.
+    pub async fn request_email_change(&self, user: &User, new_email: &str) -> Result<String> {
+        let confirmation_token = generate_token();
+        let expires_at =
+            Utc::now() + ttl_from_env("EMAIL_CHANGE_TTL_SECONDS", DEFAULT_EMAIL_CHANGE_TTL_SECONDS);
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE email_change_requests SET cancelled_at = NOW()
+            WHERE user_id = $1 AND confirmed_at IS NULL AND cancelled_at IS NULL
+            "#,
+        )
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO email_change_requests
+                (id, user_id, old_email, new_email, token_hash, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(new_email)
+        .bind(hash_token(&confirmation_token))
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(confirmation_token)
+    }
+
+    /// Consumes a confirmation token and switches the user to the new, now verified, address.
+    ///
+    /// The change is refused if the user's address has moved on since the request was made.
+    /// Access tokens that still carry the old address stop being accepted from here on.
+    pub async fn confirm_email_change(
+        &self,
+        confirmation_token: &str,
+    ) -> Result<AppliedEmailChange> {
+        let undo_token = generate_token();
+        let undo_expires_at = Utc::now()
+            + ttl_from_env(
+                "EMAIL_CHANGE_UNDO_TTL_SECONDS",
+                DEFAULT_EMAIL_CHANGE_UNDO_TTL_SECONDS,
+            );
+
+        let mut tx = self.pool.begin().await?;
+
+        let request_row = sqlx::query(
+            r#"
+            UPDATE email_change_requests
+            SET confirmed_at = NOW(), undo_token_hash = $2, undo_expires_at = $3
+            WHERE token_hash = $1 AND confirmed_at IS NULL AND cancelled_at IS NULL
+            AND expires_at > NOW()
+            RETURNING user_id, old_email, new_email
+            "#,
+        )
+        .bind(hash_token(confirmation_token))
+        .bind(hash_token(&undo_token))
+        .bind(undo_expires_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let row = request_row.ok_or_else(|| anyhow!("Invalid or expired confirmation token"))?;
+        let user_id: Uuid = row.get("user_id");
+        let old_email: String = row.get("old_email");
+        let new_email: String = row.get("new_email");
+
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET email = $1, email_verified_at = NOW(), updated_at = NOW()
+            WHERE id = $2 AND email = $3
+            "#,
+        )
+        .bind(&new_email)
+        .bind(user_id)
+        .bind(&old_email)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Email address has changed since this request"));
+        }
+
+        tx.commit().await?;
+
+        Ok(AppliedEmailChange {
+            user_id,
+            old_email,
+            new_email,
+            undo_token,
+        })
+    }
+
+    /// Reverses an applied change using the token sent to the old address, returning the
+    /// user it belongs to. The caller should sign the user out everywhere, since an unwanted
+    /// change suggests someone else had access to the account.
+    pub async fn undo_email_change(&self, undo_token: &str) -> Result<Uuid> {
+        let mut tx = self.pool.begin().await?;
+
+        let request_row = sqlx::query(
+            r#"
+            UPDATE email_change_requests SET undone_at = NOW()
+            WHERE undo_token_hash = $1 AND undone_at IS NULL AND undo_expires_at > NOW()
+            RETURNING user_id, old_email, new_email
+            "#,
+        )
+        .bind(hash_token(undo_token))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let row = request_row.ok_or_else(|| anyhow!("Invalid or expired undo token"))?;
+        let user_id: Uuid = row.get("user_id");
+        let old_email: String = row.get("old_email");
+        let new_email: String = row.get("new_email");
+
+        // The undo link was delivered to the old address, which proves it still works
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET email = $1, email_verified_at = NOW(), updated_at = NOW()
+            WHERE id = $2 AND email = $3
+            "#,
+        )
+        .bind(&old_email)
+        .bind(user_id)
+        .bind(&new_email)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Email address has changed since this request"));
+        }
+
+        tx.commit().await?;
+
+        Ok(user_id)
+    }
+}
+
+/// Sends the confirmation link for a token created by `request_email_change` to the new address.
+pub async fn send_email_change_confirmation(
+    user: &User,
+    new_email: &str,
+    confirmation_token: &str,
+) -> Result<()> {
+    let message = EmailMessage {
+        to: new_email.to_string(),
+        subject: "Confirm your new email address".to_string(),
+        body: format!(
+            "Hi {},\n\nConfirm that you want to use this address for your account by opening the link below.\n\n{}/confirm-email-change?token={}\n\nIf you didn't ask for this, you can ignore this email and nothing will change.",
+            user.username,
+            app_url(),
+            confirmation_token
+        ),
+    };
+
+    mailer_from_env()?.send(&message).await
+}
+
+/// Tells the old address that the account moved, with a link to reverse the change.
+pub async fn send_email_change_notice(username: &str, change: &AppliedEmailChange) -> Result<()> {
+    let message = EmailMessage {
+        to: change.old_email.clone(),
+        subject: "Your email address was changed".to_string(),
+        body: format!(
+            "Hi {},\n\nThe email address on your account was changed to {}.\n\nIf you didn't make this change, open the link below to switch back and sign out every session, then reset your password.\n\n{}/undo-email-change?token={}",
+            username,
+            change.new_email,
+            app_url(),
+            change.undo_token
+        ),
+    };
+
+    mailer_from_env()?.send(&message).await
+}
@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use sqlx::{PgPool, Row};
+use std::env;
+use uuid::Uuid;
+use vercel_runtime::StatusCode;
+
+use crate::auth::{generate_token, hash_token, ttl_from_env};
+use crate::mailer::{mailer_from_env, EmailMessage};
+use crate::{app_url, ApiError, Claims, User};
+
+const DEFAULT_EMAIL_VERIFICATION_TTL_SECONDS: i64 = 48 * 60 * 60;
+
+/// What users who have not verified their email address are allowed to do,
+/// configured through `UNVERIFIED_SIGNIN_POLICY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnverifiedSigninPolicy {
+    /// Unverified users sign in normally (`allow`, the default).
+    Allow,
+    /// Unverified users sign in and manage their own account, but endpoints that call
+    /// `require_verified_email` reject them (`restricted`).
+    Restricted,
+    /// Unverified users cannot sign in at all (`deny`).
+    Deny,
+}
+
+impl UnverifiedSigninPolicy {
+    pub fn from_env() -> Self {
+        match env::var("UNVERIFIED_SIGNIN_POLICY").as_deref() {
+            Ok("restricted") => Self::Restricted,
+            Ok("deny") => Self::Deny,
+            _ => Self::Allow,
+        }
+    }
+
+    /// Whether an unverified user may be issued tokens at all.
+    pub fn allows_signin(&self, user: &User) -> bool {
+        user.email_verified_at.is_some() || *self != Self::Deny
+    }
+
+    /// Whether a token grants access to endpoints that require a verified email address.
+    pub fn permits(&self, claims: &Claims) -> bool {
+        claims.email_verified || *self == Self::Allow
+    }
+}
+
+/// Checks a token against `UNVERIFIED_SIGNIN_POLICY` for endpoints beyond the user's own
+/// account, returning the 403 `ApiError` to send back for an unverified user under `restricted`.
+pub fn require_verified_email(claims: &Claims) -> Result<(), ApiError> {
+    if UnverifiedSigninPolicy::from_env().permits(claims) {
+        return Ok(());
+    }
+    Err(ApiError {
+        message: "Email address not verified".to_string(),
+        code: StatusCode::FORBIDDEN.as_u16(),
+    })
+}
+
+pub struct EmailVerificationRepository {
+    pool: PgPool,
+}
+
+impl EmailVerificationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates a verification token for the user, invalidating any earlier ones that were not used.
+    pub async fn create_verification_token(&self, user_id: &Uuid) -> Result<String> {
+        let verification_token = generate_token();
+        let expires_at = Utc::now()
+            + ttl_from_env(
+                "EMAIL_VERIFICATION_TTL_SECONDS",
+                DEFAULT_EMAIL_VERIFICATION_TTL_SECONDS,
+            );
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE email_verification_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO email_verification_tokens (id, user_id, token_hash, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(hash_token(&verification_token))
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(verification_token)
+    }
+
+    /// Consumes a verification token and marks the owner's email address as verified.
+    pub async fn verify_email(&self, verification_token: &str) -> Result<Uuid> {
+        let mut tx = self.pool.begin().await?;
+
+        let token_row = sqlx::query(
+            r#"
+            UPDATE email_verification_tokens SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+            "#,
+        )
+        .bind(hash_token(verification_token))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let row = token_row.ok_or_else(|| anyhow!("Invalid or expired verification token"))?;
+        let user_id: Uuid = row.get("user_id");
+
+        sqlx::query(
+            r#"
+            UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(user_id)
+    }
+}
+
+/// Sends the verification link for a token created by `create_verification_token`.
+pub async fn send_verification_email(user: &User, verification_token: &str) -> Result<()> {
+    let message = EmailMessage {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nConfirm your email address by opening the link below.\n\n{}/verify-email?token={}\n\nIf you didn't create an account, you can ignore this email.",
+            user.username,
+            app_url(),
+            verification_token
+        ),
+    };
+
+    mailer_from_env()?.send(&message).await
+}
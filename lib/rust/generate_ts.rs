@@ -1,5 +1,19 @@
 use rust_on_vercel_template::{
-    ApiError, AuthResponse, Claims, CreateUserRequest, LoginRequest, Product, User,
+    AcceptInvitationRequest, AccountExport, ApiError, ApiKey, AuditEvent, AuthResponse,
+    ChangeEmailRequest, ChangePasswordRequest, Claims, ConfirmEmailChangeRequest,
+    CreateApiKeyRequest, CreateApiKeyResponse, CreateInvitationRequest, CreateOrganizationRequest,
+    CreateProductRequest, CreateUserRequest, DeleteAccountRequest, DeleteAccountResponse,
+    ExportedSession, ForgotPasswordRequest, Invitation, LoginRequest, MagicLinkRequest,
+    MagicLinkResponse, MagicLinkVerifyRequest, MessageResponse, MfaChallengeResponse,
+    MfaCodeRequest, MfaVerifyRequest, OAuthErrorResponse, OidcAuthorizeRequest,
+    OidcAuthorizeResponse, OidcCallbackRequest, OidcTokenResponse, OidcUserInfo, Organization,
+    OrganizationMember, Passkey, PasskeyAssertion, PasskeyAssertionResponse, PasskeyAttestation,
+    PasskeyAttestationResponse, PasskeyAuthenticationOptionsRequest, PasskeyAuthenticationRequest,
+    PasswordPolicyError, PasswordViolation, Product, RecoveryCodesResponse, RefreshRequest,
+    RegisterPasskeyRequest, RemovePasskeyRequest, ResendVerificationRequest, ResetPasswordRequest,
+    RevokeApiKeyRequest, RevokeInvitationRequest, RevokeSessionRequest, Session, SignoutRequest,
+    SwitchOrganizationRequest, TotpSetupResponse, UndoEmailChangeRequest, UpdateProfileRequest,
+    User, UserRoleRequest, VerifyEmailRequest,
 };
 use std::fs;
 use ts_rs::TS;
@@ -9,23 +23,83 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     fs::create_dir_all("types")?;
 
     // Generate TypeScript definitions for all types
-    let product_ts = Product::decl();
-    let user_ts = User::decl();
-    let create_user_request_ts = CreateUserRequest::decl();
-    let login_request_ts = LoginRequest::decl();
-    let auth_response_ts = AuthResponse::decl();
-    let api_error_ts = ApiError::decl();
-    let claims_ts = Claims::decl();
+    let declarations = [
+        Product::decl(),
+        CreateProductRequest::decl(),
+        User::decl(),
+        UpdateProfileRequest::decl(),
+        DeleteAccountRequest::decl(),
+        DeleteAccountResponse::decl(),
+        ExportedSession::decl(),
+        AuditEvent::decl(),
+        AccountExport::decl(),
+        CreateUserRequest::decl(),
+        LoginRequest::decl(),
+        AuthResponse::decl(),
+        RefreshRequest::decl(),
+        SignoutRequest::decl(),
+        Session::decl(),
+        RevokeSessionRequest::decl(),
+        ApiKey::decl(),
+        CreateApiKeyRequest::decl(),
+        CreateApiKeyResponse::decl(),
+        RevokeApiKeyRequest::decl(),
+        MfaChallengeResponse::decl(),
+        MfaVerifyRequest::decl(),
+        MfaCodeRequest::decl(),
+        TotpSetupResponse::decl(),
+        RecoveryCodesResponse::decl(),
+        Passkey::decl(),
+        PasskeyAttestation::decl(),
+        PasskeyAttestationResponse::decl(),
+        PasskeyAssertion::decl(),
+        PasskeyAssertionResponse::decl(),
+        RegisterPasskeyRequest::decl(),
+        RemovePasskeyRequest::decl(),
+        PasskeyAuthenticationOptionsRequest::decl(),
+        PasskeyAuthenticationRequest::decl(),
+        OidcAuthorizeRequest::decl(),
+        OidcAuthorizeResponse::decl(),
+        OidcCallbackRequest::decl(),
+        MagicLinkRequest::decl(),
+        MagicLinkResponse::decl(),
+        MagicLinkVerifyRequest::decl(),
+        OidcUserInfo::decl(),
+        OidcTokenResponse::decl(),
+        OAuthErrorResponse::decl(),
+        ForgotPasswordRequest::decl(),
+        ResetPasswordRequest::decl(),
+        ChangePasswordRequest::decl(),
+        ChangeEmailRequest::decl(),
+        ConfirmEmailChangeRequest::decl(),
+        UndoEmailChangeRequest::decl(),
+        VerifyEmailRequest::decl(),
+        ResendVerificationRequest::decl(),
+        Organization::decl(),
+        OrganizationMember::decl(),
+        CreateOrganizationRequest::decl(),
+        SwitchOrganizationRequest::decl(),
+        Invitation::decl(),
+        CreateInvitationRequest::decl(),
+        RevokeInvitationRequest::decl(),
+        AcceptInvitationRequest::decl(),
+        UserRoleRequest::decl(),
+        MessageResponse::decl(),
+        ApiError::decl(),
+        PasswordViolation::decl(),
+        PasswordPolicyError::decl(),
+        Claims::decl(),
+    ];
+
+    let exports = declarations
+        .iter()
+        .map(|declaration| format!("export {}", declaration))
+        .collect::<Vec<_>>()
+        .join("\n\n");
 
     let combined_ts = format!(
-        "// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.\n\nexport {}\n\nexport {}\n\nexport {}\n\nexport {}\n\nexport {}\n\nexport {}\n\nexport {}",
-        product_ts,
-        user_ts,
-        create_user_request_ts,
-        login_request_ts,
-        auth_response_ts,
-        api_error_ts,
-        claims_ts,
+        "// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.\n\n{}",
+        exports,
     );
 
     // Write the combined definitions to the file
@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::auth::{generate_token, hash_token, ttl_from_env};
+use crate::mailer::{mailer_from_env, EmailMessage};
+use crate::organizations::{Tenant, ORGANIZATION_ROLES};
+use crate::{app_url, Invitation, User};
+
+const DEFAULT_INVITATION_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+const INVITATION_COLUMNS: &str = "organization_invitations.id, organization_invitations.organization_id, organization_invitations.email, organization_roles.name AS role, organization_invitations.expires_at, organization_invitations.created_at";
+
+pub struct InvitationRepository {
+    pool: PgPool,
+}
+
+impl InvitationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Invites an email address to the tenant's organization, replacing any pending invitation
+    /// for the same address. Returns the invitation and the token to email.
+    pub async fn create_invitation(
+        &self,
+        tenant: &Tenant,
+        email: &str,
+        role: &str,
+        invited_by: &Uuid,
+    ) -> Result<(Invitation, String)> {
+        if !ORGANIZATION_ROLES.contains(&role) {
+            return Err(anyhow!("Unknown organization role: {}", role));
+        }
+
+        let invitation_token = generate_token();
+        let expires_at =
+            Utc::now() + ttl_from_env("INVITATION_TTL_SECONDS", DEFAULT_INVITATION_TTL_SECONDS);
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE organization_invitations SET revoked_at = NOW()
+            WHERE organization_id = $1 AND lower(email) = lower($2)
+            AND accepted_at IS NULL AND revoked_at IS NULL
+            "#,
+        )
+        .bind(tenant.organization_id)
+        .bind(email)
+        .execute(&mut *tx)
+        .await?;
+
+        let invitation_id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO organization_invitations
+                (id, organization_id, email, role_id, token_hash, invited_by, expires_at, created_at)
+            SELECT $1, $2, $3, id, $4, $5, $6, NOW() FROM organization_roles WHERE name = $7
+            "#,
+        )
+        .bind(invitation_id)
+        .bind(tenant.organization_id)
+        .bind(email)
+        .bind(hash_token(&invitation_token))
+        .bind(invited_by)
+        .bind(expires_at)
+        .bind(role)
+        .execute(&mut *tx)
+        .await?;
+
+        let invitation = sqlx::query_as::<_, Invitation>(&format!(
+            r#"
+            SELECT {} FROM organization_invitations
+            JOIN organization_roles ON organization_roles.id = organization_invitations.role_id
+            WHERE organization_invitations.id = $1
+            "#,
+            INVITATION_COLUMNS
+        ))
+        .bind(invitation_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok((invitation, invitation_token))
+    }
+
+    /// Lists the tenant's invitations that are still waiting to be accepted.
+    pub async fn list_pending_invitations(&self, tenant: &Tenant) -> Result<Vec<Invitation>> {
+        let invitations = sqlx::query_as::<_, Invitation>(&format!(
+            r#"
+            SELECT {} FROM organization_invitations
+            JOIN organization_roles ON organization_roles.id = organization_invitations.role_id
+            WHERE organization_invitations.organization_id = $1
+            AND organization_invitations.accepted_at IS NULL
+            AND organization_invitations.revoked_at IS NULL
+            AND organization_invitations.expires_at > NOW()
+            ORDER BY organization_invitations.created_at DESC
+            "#,
+            INVITATION_COLUMNS
+        ))
+        .bind(tenant.organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(invitations)
+    }
+
+    pub async fn revoke_invitation(&self, tenant: &Tenant, invitation_id: &Uuid) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE organization_invitations SET revoked_at = NOW()
+            WHERE id = $1 AND organization_id = $2 AND accepted_at IS NULL AND revoked_at IS NULL
+            "#,
+        )
+        .bind(invitation_id)
+        .bind(tenant.organization_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Invitation not found"));
+        }
+
+        Ok(())
+    }
+
+    /// Looks up a usable invitation without consuming it, so signup can check it up front.
+    pub async fn find_pending_invitation(&self, invitation_token: &str) -> Result<Invitation> {
+        let invitation = sqlx::query_as::<_, Invitation>(&format!(
+            r#"
+            SELECT {} FROM organization_invitations
+            JOIN organization_roles ON organization_roles.id = organization_invitations.role_id
+            WHERE organization_invitations.token_hash = $1
+            AND organization_invitations.accepted_at IS NULL
+            AND organization_invitations.revoked_at IS NULL
+            AND organization_invitations.expires_at > NOW()
+            "#,
+            INVITATION_COLUMNS
+        ))
+        .bind(hash_token(invitation_token))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        invitation.ok_or_else(|| anyhow!("Invalid or expired invitation"))
+    }
+
+    /// Consumes an invitation and adds the user to its organization with the invited role.
+    ///
+    /// Only the invited address can accept. Since the token was delivered to that address,
+    /// accepting also verifies the user's email.
+    pub async fn accept_invitation(&self, invitation_token: &str, user: &User) -> Result<Uuid> {
+        let mut tx = self.pool.begin().await?;
+
+        let invitation_row = sqlx::query(
+            r#"
+            UPDATE organization_invitations SET accepted_at = NOW()
+            WHERE token_hash = $1 AND lower(email) = lower($2)
+            AND accepted_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            RETURNING organization_id, role_id
+            "#,
+        )
+        .bind(hash_token(invitation_token))
+        .bind(&user.email)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let row = invitation_row.ok_or_else(|| anyhow!("Invalid or expired invitation"))?;
+        let organization_id: Uuid = row.get("organization_id");
+        let role_id: Uuid = row.get("role_id");
+
+        sqlx::query(
+            r#"
+            INSERT INTO organization_members (organization_id, user_id, role_id, created_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (organization_id, user_id) DO NOTHING
+            "#,
+        )
+        .bind(organization_id)
+        .bind(user.id)
+        .bind(role_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(organization_id)
+    }
+}
+
+/// Sends the invitation link for a token created by `create_invitation`.
+pub async fn send_invitation_email(
+    invitation: &Invitation,
+    organization_name: &str,
+    invited_by: &str,
+    invitation_token: &str,
+) -> Result<()> {
+    let message = EmailMessage {
+        to: invitation.email.clone(),
+        subject: format!("You've been invited to join {}", organization_name),
+        body: format!(
+            "Hi,\n\n{} invited you to join {}. Open the link below to accept; you can create an account there if you don't have one yet.\n\n{}/accept-invite?token={}\n\nIf you weren't expecting this invitation, you can ignore this email.",
+            invited_by,
+            organization_name,
+            app_url(),
+            invitation_token
+        ),
+    };
+
+    mailer_from_env()?.send(&message).await
+}
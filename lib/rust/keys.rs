@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey};
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use rsa::traits::PublicKeyParts;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use std::env;
+use std::fs;
+
+/// DER prefix of an Ed25519 SubjectPublicKeyInfo (RFC 8410); the 32 key bytes follow it.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// Length of an Ed25519 public key.
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+
+/// A key used to sign or verify JWTs, identified by the `kid` header.
+///
+/// HMAC keys are shared secrets and are never published. RSA and Ed25519 keys expose
+/// their public half as a JWK so other services can verify tokens on their own.
+pub struct JwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+impl JwtKey {
+    pub fn hmac(kid: &str, secret: &str) -> Self {
+        Self {
+            kid: kid.to_string(),
+            algorithm: Algorithm::HS256,
+            encoding: Some(EncodingKey::from_secret(secret.as_bytes())),
+            decoding: DecodingKey::from_secret(secret.as_bytes()),
+            jwk: None,
+        }
+    }
+
+    /// Loads an RS256 key from a PKCS#8 or PKCS#1 private key PEM.
+    pub fn rsa_private_pem(kid: &str, private_pem: &str) -> Result<Self> {
+        let private_key = RsaPrivateKey::from_pkcs8_pem(private_pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(private_pem))
+            .map_err(|_| anyhow!("Invalid RSA private key"))?;
+        let mut key = Self::rsa_public(kid, &private_key.to_public_key())?;
+        key.encoding = Some(EncodingKey::from_rsa_pem(private_pem.as_bytes())?);
+        Ok(key)
+    }
+
+    /// Loads a verification-only RS256 key from an SPKI or PKCS#1 public key PEM.
+    pub fn rsa_public_pem(kid: &str, public_pem: &str) -> Result<Self> {
+        let public_key = RsaPublicKey::from_public_key_pem(public_pem)
+            .or_else(|_| RsaPublicKey::from_pkcs1_pem(public_pem))
+            .map_err(|_| anyhow!("Invalid RSA public key"))?;
+        Self::rsa_public(kid, &public_key)
+    }
+
+    fn rsa_public(kid: &str, public_key: &RsaPublicKey) -> Result<Self> {
+        let n = URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be());
+        let e = URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be());
+
+        Ok(Self {
+            kid: kid.to_string(),
+            algorithm: Algorithm::RS256,
+            encoding: None,
+            decoding: DecodingKey::from_rsa_components(&n, &e)?,
+            jwk: Some(Jwk {
+                common: public_jwk_parameters(kid, KeyAlgorithm::RS256),
+                algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n,
+                    e,
+                }),
+            }),
+        })
+    }
+
+    /// Loads an EdDSA (Ed25519) key from a PKCS#8 private key PEM.
+    pub fn ed25519_private_pem(kid: &str, private_pem: &str) -> Result<Self> {
+        let der = pem::parse(private_pem)?;
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.contents())
+            .map_err(|_| anyhow!("Invalid Ed25519 private key"))?;
+        let mut key = Self::ed25519_public(kid, key_pair.public_key().as_ref())?;
+        key.encoding = Some(EncodingKey::from_ed_pem(private_pem.as_bytes())?);
+        Ok(key)
+    }
+
+    /// Loads a verification-only EdDSA (Ed25519) key from an SPKI public key PEM.
+    pub fn ed25519_public_pem(kid: &str, public_pem: &str) -> Result<Self> {
+        let der = pem::parse(public_pem)?;
+        let contents = der.contents();
+        if der.tag() != "PUBLIC KEY"
+            || contents.len() != ED25519_SPKI_PREFIX.len() + ED25519_PUBLIC_KEY_LEN
+            || !contents.starts_with(&ED25519_SPKI_PREFIX)
+        {
+            return Err(anyhow!("Invalid Ed25519 public key"));
+        }
+        Self::ed25519_public(kid, &contents[ED25519_SPKI_PREFIX.len()..])
+    }
+
+    fn ed25519_public(kid: &str, public_key: &[u8]) -> Result<Self> {
+        let x = URL_SAFE_NO_PAD.encode(public_key);
+
+        Ok(Self {
+            kid: kid.to_string(),
+            algorithm: Algorithm::EdDSA,
+            encoding: None,
+            decoding: DecodingKey::from_ed_components(&x)?,
+            jwk: Some(Jwk {
+                common: public_jwk_parameters(kid, KeyAlgorithm::EdDSA),
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x,
+                }),
+            }),
+        })
+    }
+
+    /// Loads the signing key described by `JWT_ALGORITHM`, `JWT_KEY_ID` and either
+    /// `JWT_SECRET` (HS256) or `JWT_PRIVATE_KEY` / `JWT_PRIVATE_KEY_FILE` (RS256, EdDSA).
+    pub fn from_env() -> Result<Self> {
+        let algorithm = env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+        let kid = env::var("JWT_KEY_ID").unwrap_or_else(|_| "default".to_string());
+
+        match algorithm.as_str() {
+            "HS256" => {
+                let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+                Ok(Self::hmac(&kid, &jwt_secret))
+            }
+            "RS256" => Self::rsa_private_pem(&kid, &pem_from_env("JWT_PRIVATE_KEY")?),
+            "EdDSA" => Self::ed25519_private_pem(&kid, &pem_from_env("JWT_PRIVATE_KEY")?),
+            other => Err(anyhow!("Unsupported JWT_ALGORITHM: {}", other)),
+        }
+    }
+
+    pub fn encoding_key(&self) -> Result<&EncodingKey> {
+        self.encoding
+            .as_ref()
+            .ok_or_else(|| anyhow!("Key {} can only verify tokens", self.kid))
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding
+    }
+
+    /// The public JWK for this key, or `None` for shared-secret keys.
+    pub fn public_jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+}
+
+fn public_jwk_parameters(kid: &str, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Reads a PEM from `NAME`, or from the file named by `NAME_FILE`.
+///
+/// Environment variables often can't hold newlines, so a literal `\n` is accepted too.
+pub(crate) fn pem_from_env(name: &str) -> Result<String> {
+    if let Ok(pem) = env::var(name) {
+        return Ok(pem.replace("\\n", "\n"));
+    }
+
+    let path = env::var(format!("{}_FILE", name))
+        .map_err(|_| anyhow!("{} or {}_FILE must be set", name, name))?;
+    Ok(fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
+    use ring::rand::SystemRandom;
+    use serde::{Deserialize, Serialize};
+
+    const RSA_PRIVATE_PEM: &str = include_str!("data/test-rsa-private.pem");
+    const RSA_PUBLIC_PEM: &str = include_str!("data/test-rsa-public.pem");
+
+    #[derive(Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: i64,
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "user".to_string(),
+            exp: chrono::Utc::now().timestamp() + 60,
+        }
+    }
+
+    fn sign(key: &JwtKey) -> String {
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        encode(&header, &claims(), key.encoding_key().unwrap()).unwrap()
+    }
+
+    fn verifies(token: &str, algorithm: Algorithm, decoding_key: &DecodingKey) -> bool {
+        decode::<Claims>(token, decoding_key, &Validation::new(algorithm)).is_ok()
+    }
+
+    /// A fresh Ed25519 key as PKCS#8 private and SPKI public PEMs.
+    fn ed25519_pems() -> (String, String) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let spki = [&ED25519_SPKI_PREFIX[..], key_pair.public_key().as_ref()].concat();
+        (
+            pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref())),
+            pem::encode(&pem::Pem::new("PUBLIC KEY", spki)),
+        )
+    }
+
+    #[test]
+    fn rs256_tokens_verify_against_the_public_pem_and_jwk() {
+        let signing = JwtKey::rsa_private_pem("rsa-1", RSA_PRIVATE_PEM).unwrap();
+        let token = sign(&signing);
+
+        assert_eq!(decode_header(&token).unwrap().kid.as_deref(), Some("rsa-1"));
+
+        let public = JwtKey::rsa_public_pem("rsa-1", RSA_PUBLIC_PEM).unwrap();
+        assert!(verifies(&token, Algorithm::RS256, public.decoding_key()));
+
+        let jwk = DecodingKey::from_jwk(signing.public_jwk().unwrap()).unwrap();
+        assert!(verifies(&token, Algorithm::RS256, &jwk));
+    }
+
+    #[test]
+    fn eddsa_tokens_verify_against_the_public_pem_and_jwk() {
+        let (private_pem, public_pem) = ed25519_pems();
+        let signing = JwtKey::ed25519_private_pem("ed-1", &private_pem).unwrap();
+        let token = sign(&signing);
+
+        assert_eq!(decode_header(&token).unwrap().kid.as_deref(), Some("ed-1"));
+
+        let public = JwtKey::ed25519_public_pem("ed-1", &public_pem).unwrap();
+        assert!(verifies(&token, Algorithm::EdDSA, public.decoding_key()));
+
+        let jwk = DecodingKey::from_jwk(public.public_jwk().unwrap()).unwrap();
+        assert!(verifies(&token, Algorithm::EdDSA, &jwk));
+    }
+
+    #[test]
+    fn eddsa_tokens_do_not_verify_against_another_key() {
+        let (private_pem, _) = ed25519_pems();
+        let (_, other_public_pem) = ed25519_pems();
+        let token = sign(&JwtKey::ed25519_private_pem("ed-1", &private_pem).unwrap());
+
+        let other = JwtKey::ed25519_public_pem("ed-1", &other_public_pem).unwrap();
+        assert!(!verifies(&token, Algorithm::EdDSA, other.decoding_key()));
+    }
+
+    #[test]
+    fn ed25519_public_pem_rejects_other_keys() {
+        let (private_pem, public_pem) = ed25519_pems();
+        let spki = pem::parse(&public_pem).unwrap().into_contents();
+
+        // An RSA public key and an Ed25519 private key
+        assert!(JwtKey::ed25519_public_pem("ed-1", RSA_PUBLIC_PEM).is_err());
+        assert!(JwtKey::ed25519_public_pem("ed-1", &private_pem).is_err());
+
+        // The right bytes under the wrong label
+        let mislabeled = pem::encode(&pem::Pem::new("PRIVATE KEY", spki.clone()));
+        assert!(JwtKey::ed25519_public_pem("ed-1", &mislabeled).is_err());
+
+        // An X25519 key, which shares the layout but not the OID
+        let mut x25519 = spki.clone();
+        x25519[8] = 0x6e;
+        let x25519 = pem::encode(&pem::Pem::new("PUBLIC KEY", x25519));
+        assert!(JwtKey::ed25519_public_pem("ed-1", &x25519).is_err());
+
+        // Trailing bytes
+        let mut long = spki;
+        long.push(0);
+        let long = pem::encode(&pem::Pem::new("PUBLIC KEY", long));
+        assert!(JwtKey::ed25519_public_pem("ed-1", &long).is_err());
+    }
+
+    #[test]
+    fn rsa_pems_are_rejected_as_the_wrong_kind() {
+        let (private_pem, public_pem) = ed25519_pems();
+        assert!(JwtKey::rsa_private_pem("rsa-1", &private_pem).is_err());
+        assert!(JwtKey::rsa_public_pem("rsa-1", &public_pem).is_err());
+        assert!(JwtKey::ed25519_private_pem("ed-1", RSA_PRIVATE_PEM).is_err());
+    }
+}
@@ -1,5 +1,31 @@
 // This is a shared module for your structs and common functions.
+pub mod account;
+pub mod api_keys;
+pub mod audit;
 pub mod auth;
+pub mod email_change;
+pub mod email_verification;
+pub mod invitations;
+pub mod keys;
+pub mod login_attempts;
+pub mod magic_links;
+pub mod mailer;
+pub mod mfa;
+pub mod oidc;
+pub mod oidc_provider;
+pub mod organizations;
+pub mod passkeys;
+pub mod password_hashing;
+pub mod password_policy;
+pub mod password_resets;
+pub mod products;
+pub mod profile;
+pub mod rate_limit;
+pub mod rbac;
+pub mod refresh_tokens;
+pub mod revocation;
+pub mod sessions;
+pub mod webauthn;
 use std::env;
 
 use anyhow::Result;
@@ -8,8 +34,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgPoolOptions, FromRow, PgPool};
 use ts_rs::TS;
 use uuid::Uuid;
+use vercel_runtime::Request;
 
-#[derive(TS, Serialize, Deserialize)]
+#[derive(TS, Serialize, Deserialize, FromRow)]
 #[ts(export)]
 pub struct Product {
     pub id: String,
@@ -19,11 +46,24 @@ pub struct Product {
 
 #[derive(Debug, Serialize, Deserialize, TS)]
 #[ts(export)]
+pub struct CreateProductRequest {
+    pub name: String,
+    pub price: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
 pub struct User {
     #[ts(type = "string")]
     pub id: Uuid,
     pub email: String,
     pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub locale: Option<String>,   // BCP 47 language tag, e.g. "en-GB"
+    pub timezone: Option<String>, // IANA time zone, e.g. "Europe/London"
+    #[ts(type = "string | null")]
+    pub email_verified_at: Option<DateTime<Utc>>,
     #[ts(type = "string")]
     pub created_at: DateTime<Utc>,
     #[ts(type = "string")]
@@ -36,6 +76,8 @@ pub struct CreateUserRequest {
     pub email: String,
     pub username: String,
     pub password: String,
+    pub audience: Option<String>, // app the tokens are for, defaults to the first JWT_AUDIENCE
+    pub invite_token: Option<String>, // joins the inviting organization on signup
 }
 
 #[derive(Debug, Serialize, Deserialize, TS)]
@@ -43,6 +85,7 @@ pub struct CreateUserRequest {
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    pub audience: Option<String>, // app the tokens are for, defaults to the first JWT_AUDIENCE
 }
 
 #[derive(Debug, Serialize, Deserialize, TS)]
@@ -50,6 +93,14 @@ pub struct LoginRequest {
 pub struct AuthResponse {
     pub user: User,
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: usize, // access token lifetime in seconds
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS)]
@@ -57,8 +108,527 @@ pub struct AuthResponse {
 pub struct Claims {
     pub sub: String, // user id
     pub email: String,
-    pub exp: usize, // expiration time
-    pub iat: usize, // issued at
+    #[serde(default)]
+    pub email_verified: bool,
+    pub iss: String, // issuer, JWT_ISSUER
+    pub aud: String, // audience, the app the token was minted for
+    pub exp: usize,  // expiration time
+    pub nbf: usize,  // not valid before
+    pub iat: usize,  // issued at
+    pub jti: String, // unique token id, used for revocation
+    #[serde(default)]
+    pub sid: Option<String>, // session the token belongs to, see sessions::SessionRepository
+    #[serde(default)]
+    pub auth_time: Option<usize>, // when the user last signed in, checked by auth::require_recent_auth
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>, // granted by the roles, checked by rbac::require_permission
+    #[serde(default)]
+    pub org: Option<String>, // active organization id
+    #[serde(default)]
+    pub org_role: Option<String>, // role in the active organization
+}
+
+/// An active sign-in of the caller, as listed by `GET /api/auth/sessions`.
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct Session {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub audience: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: DateTime<Utc>,
+    #[ts(type = "string")]
+    pub last_seen_at: DateTime<Utc>,
+    #[ts(type = "string")]
+    pub expires_at: DateTime<Utc>,
+    pub current: bool, // the session of the token making the request
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RevokeSessionRequest {
+    pub session_id: Option<String>,
+    pub all_others: Option<bool>, // revoke every session except the current one
+}
+
+/// A personal API key as listed to its owner; the secret itself is only shown on creation.
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct ApiKey {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>, // permissions the key may use, see rbac
+    #[ts(type = "string | null")]
+    pub organization_id: Option<Uuid>,
+    #[ts(type = "string | null")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[ts(type = "string | null")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[ts(type = "string")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<u32>, // never expires when left out
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CreateApiKeyResponse {
+    pub api_key: ApiKey,
+    pub key: String, // the secret, sent as `Authorization: Bearer sk_...` or `X-API-Key`
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RevokeApiKeyRequest {
+    pub api_key_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SignoutRequest {
+    pub refresh_token: Option<String>,
+    pub all_sessions: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: usize,    // challenge lifetime in seconds
+    pub methods: Vec<String>, // second factors the user can use: "totp", "passkey"
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    pub code: String, // TOTP code or recovery code
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MfaCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TotpSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+/// A registered passkey as listed to its owner.
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct Passkey {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub name: String,
+    pub transports: Vec<String>, // hints for the browser, e.g. "internal" or "usb"
+    #[ts(type = "string | null")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[ts(type = "string")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// The result of `navigator.credentials.create()`, as serialized by `PublicKeyCredential.toJSON()`.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PasskeyAttestation {
+    pub id: String, // base64url credential id
+    pub response: PasskeyAttestationResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyAttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+/// The result of `navigator.credentials.get()`, as serialized by `PublicKeyCredential.toJSON()`.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PasskeyAssertion {
+    pub id: String,
+    pub response: PasskeyAssertionResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyAssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RegisterPasskeyRequest {
+    pub name: Option<String>, // defaults to "Passkey"
+    pub credential: PasskeyAttestation,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RemovePasskeyRequest {
+    pub passkey_id: String,
+}
+
+/// Starts a passkey sign-in. With an `mfa_token` from signin the passkey is the second
+/// factor for that user; without one it is the only one.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PasskeyAuthenticationOptionsRequest {
+    pub audience: Option<String>, // ignored with an mfa_token, which carries its own
+    pub mfa_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PasskeyAuthenticationRequest {
+    pub credential: PasskeyAssertion,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaChallengeClaims {
+    pub sub: String, // user id
+    pub purpose: String,
+    pub iss: String,
+    pub aud: String, // carried over to the access token once the code is verified
+    pub exp: usize,
+    pub nbf: usize,
+    pub iat: usize,
+    pub jti: String, // recorded once the challenge is completed, so it works only once
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OidcAuthorizeRequest {
+    pub provider: String, // one of OIDC_PROVIDERS
+    pub audience: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OidcAuthorizeResponse {
+    pub authorization_url: String,
+}
+
+/// The query parameters the provider sent the user back with.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OidcCallbackRequest {
+    pub state: String,
+    pub code: String,
+}
+
+/// An ID token this backend issues to a registered client, see oidc_provider.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcIdTokenClaims {
+    pub iss: String,
+    pub aud: String, // the client's client_id
+    pub exp: usize,
+    pub iat: usize,
+    pub auth_time: usize, // when the user entered their credentials
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub user: OidcUserInfo, // sub and the claims the client's scopes allow
+}
+
+/// Claims about the user released to a client, limited by the scopes it was granted.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OidcUserInfo {
+    pub sub: String, // user id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zoneinfo: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OidcTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: usize,
+    pub id_token: String,
+    pub scope: String,
+}
+
+/// Error body of the token and userinfo endpoints, in the shape OAuth 2.0 clients expect.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    pub error_description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MagicLinkRequest {
+    pub email: String,
+    pub audience: Option<String>, // app the tokens are for, defaults to the first JWT_AUDIENCE
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MagicLinkResponse {
+    pub message: String,
+    pub device_nonce: String, // kept by the requesting device and sent back with the link's token
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MagicLinkVerifyRequest {
+    pub token: String,
+    pub device_nonce: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ChangeEmailRequest {
+    pub new_email: String,
+    pub current_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UndoEmailChangeRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeleteAccountRequest {
+    pub password: Option<String>, // not needed right after signing in
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeleteAccountResponse {
+    pub message: String,
+    #[ts(type = "string")]
+    pub purge_after: DateTime<Utc>, // signing in before this cancels the deletion
+}
+
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct AuditEvent {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub event: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    #[ts(type = "Record<string, unknown>")]
+    pub metadata: serde_json::Value,
+    #[ts(type = "string")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A sign-in as it appears in the data export, including ended ones.
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct ExportedSession {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub audience: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: DateTime<Utc>,
+    #[ts(type = "string")]
+    pub last_seen_at: DateTime<Utc>,
+    #[ts(type = "string")]
+    pub expires_at: DateTime<Utc>,
+    #[ts(type = "string | null")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Everything stored about a user, returned by `GET /api/auth/me/export`.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AccountExport {
+    #[ts(type = "string")]
+    pub exported_at: DateTime<Utc>,
+    pub user: User,
+    pub mfa_enabled: bool,
+    pub roles: Vec<String>,
+    pub organizations: Vec<Organization>,
+    pub sessions: Vec<ExportedSession>,
+    pub passkeys: Vec<Passkey>,
+    pub audit_events: Vec<AuditEvent>,
+}
+
+/// Fields left out are unchanged; an empty string clears an optional field.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UpdateProfileRequest {
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct Organization {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub name: String,
+    pub role: String, // the caller's role in the organization
+    #[ts(type = "string")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct OrganizationMember {
+    #[ts(type = "string")]
+    pub user_id: Uuid,
+    pub email: String,
+    pub username: String,
+    pub role: String,
+    #[ts(type = "string")]
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SwitchOrganizationRequest {
+    pub organization_id: String,
+    pub refresh_token: String, // the sign-in to switch; it keeps the new organization on refresh
+}
+
+#[derive(Debug, Serialize, Deserialize, TS, FromRow)]
+#[ts(export)]
+pub struct Invitation {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    #[ts(type = "string")]
+    pub organization_id: Uuid,
+    pub email: String,
+    pub role: String,
+    #[ts(type = "string")]
+    pub expires_at: DateTime<Utc>,
+    #[ts(type = "string")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    pub role: Option<String>, // organization role, defaults to "member"
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RevokeInvitationRequest {
+    pub invitation_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AcceptInvitationRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UserRoleRequest {
+    pub user_id: String,
+    pub role: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MessageResponse {
+    pub message: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS)]
@@ -68,14 +638,27 @@ pub struct ApiError {
     pub code: u16,
 }
 
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PasswordViolation {
+    pub rule: String, // min_length, contains_email, contains_username, too_weak or breached
+    pub message: String,
+}
+
+/// A 400 for a password the policy rejected, listing every rule it broke.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PasswordPolicyError {
+    pub message: String,
+    pub code: u16,
+    pub violations: Vec<PasswordViolation>,
+}
+
 #[derive(FromRow)]
 struct UserWithPassword {
-    id: Uuid,
-    email: String,
-    username: String,
+    #[sqlx(flatten)]
+    user: User,
     password_hash: String,
-    created_at: DateTime<Utc>,
-    updated_at: DateTime<Utc>,
 }
 
 pub struct UserRepository {
@@ -93,6 +676,32 @@ pub async fn create_pool() -> Result<PgPool> {
     Ok(pool)
 }
 
+/// Public URL of the frontend, used to build links in outgoing email.
+pub fn app_url() -> String {
+    env::var("APP_URL")
+        .unwrap_or_else(|_| "http://localhost:3000".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// The caller's IP address as reported by Vercel's proxy headers.
+pub fn client_ip(req: &Request) -> String {
+    let headers = req.headers();
+
+    headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .or_else(|| {
+            headers
+                .get("x-real-ip")
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
 pub async fn run_migrations(pool: &PgPool) -> Result<()> {
     sqlx::migrate!("./migrations").run(pool).await?;
     Ok(())
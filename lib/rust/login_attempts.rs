@@ -0,0 +1,298 @@
+use anyhow::Result;
+use sqlx::{PgPool, Row};
+use std::env;
+use uuid::Uuid;
+
+use crate::auth::ttl_from_env;
+use crate::UserRepository;
+
+const DEFAULT_MAX_FAILED_ATTEMPTS_PER_ACCOUNT: i32 = 5;
+const DEFAULT_MAX_FAILED_ATTEMPTS_PER_IP: i32 = 20;
+const DEFAULT_MAX_FAILED_MFA_ATTEMPTS: i32 = 5;
+const DEFAULT_LOCKOUT_SECONDS: i64 = 30;
+const DEFAULT_MAX_LOCKOUT_SECONDS: i64 = 60 * 60;
+const DEFAULT_LOGIN_ATTEMPT_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+/// Why a signin attempt is refused before the password is even checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginBlock {
+    /// Too many failures for this account; answered with 423 Locked.
+    AccountLocked { retry_after_seconds: i64 },
+    /// Too many failures from this IP address; answered with 429 Too Many Requests.
+    TooManyAttempts { retry_after_seconds: i64 },
+}
+
+/// The outcome of re-checking a signed-in user's password before a sensitive change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    Confirmed,
+    Incorrect,
+    /// Not checked, because the account or IP address is blocked.
+    Blocked(LoginBlock),
+}
+
+fn max_failed_attempts(name: &str, default_attempts: i32) -> i32 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<i32>().ok())
+        .filter(|attempts| *attempts > 0)
+        .unwrap_or(default_attempts)
+}
+
+/// Lockout for the given number of failures: nothing below the threshold, then
+/// `LOGIN_LOCKOUT_SECONDS` doubling with every further failure up to `LOGIN_MAX_LOCKOUT_SECONDS`.
+fn lockout_seconds(failed_count: i32, threshold: i32) -> Option<i64> {
+    if failed_count < threshold {
+        return None;
+    }
+
+    let base = ttl_from_env("LOGIN_LOCKOUT_SECONDS", DEFAULT_LOCKOUT_SECONDS).num_seconds();
+    let max = ttl_from_env("LOGIN_MAX_LOCKOUT_SECONDS", DEFAULT_MAX_LOCKOUT_SECONDS).num_seconds();
+    let doublings = (failed_count - threshold).min(30) as u32;
+
+    Some(base.saturating_mul(1 << doublings).min(max))
+}
+
+fn account_key(email: &str) -> String {
+    format!("account:{}", email.trim().to_lowercase())
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+fn mfa_key(user_id: &Uuid) -> String {
+    format!("mfa:{}", user_id)
+}
+
+/// Tracks failed signins per account and per client IP so credential stuffing slows to a crawl.
+pub struct LoginAttemptRepository {
+    pool: PgPool,
+}
+
+impl LoginAttemptRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Returns the block in force for this email or IP address, if any.
+    pub async fn check(&self, email: &str, ip: &str) -> Result<Option<LoginBlock>> {
+        let mut block = None;
+        for (key, retry_after_seconds) in self.locks(vec![account_key(email), ip_key(ip)]).await? {
+            // An account lock is reported over an IP block; it's the one the user can act on
+            if key.starts_with("account:") {
+                return Ok(Some(LoginBlock::AccountLocked {
+                    retry_after_seconds,
+                }));
+            }
+            block = Some(LoginBlock::TooManyAttempts {
+                retry_after_seconds,
+            });
+        }
+
+        Ok(block)
+    }
+
+    /// Returns the lock on the user's second factor after too many wrong codes, if any.
+    pub async fn check_mfa(&self, user_id: &Uuid) -> Result<Option<LoginBlock>> {
+        let locks = self.locks(vec![mfa_key(user_id)]).await?;
+
+        Ok(locks
+            .into_iter()
+            .next()
+            .map(|(_, retry_after_seconds)| LoginBlock::AccountLocked {
+                retry_after_seconds,
+            }))
+    }
+
+    /// The keys among `keys` that are locked, with the seconds until each lock lifts.
+    async fn locks(&self, keys: Vec<String>) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT key, CEIL(EXTRACT(EPOCH FROM locked_until - NOW()))::BIGINT AS retry_after
+            FROM login_attempts
+            WHERE key = ANY($1) AND locked_until > NOW()
+            "#,
+        )
+        .bind(keys)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("key"), row.get("retry_after")))
+            .collect())
+    }
+
+    /// Re-checks a signed-in user's password before a sensitive change, under the same
+    /// lockout as signin: a wrong password counts as a failed signin, so a stolen session
+    /// can't be used to guess the password.
+    pub async fn confirm_password(
+        &self,
+        email: &str,
+        password: &str,
+        ip: &str,
+    ) -> Result<PasswordCheck> {
+        if let Some(block) = self.check(email, ip).await? {
+            return Ok(PasswordCheck::Blocked(block));
+        }
+
+        if UserRepository::new(self.pool.clone())
+            .authenticate_user(email, password)
+            .await
+            .is_ok()
+        {
+            return Ok(PasswordCheck::Confirmed);
+        }
+
+        self.record_failure(email, ip).await?;
+        Ok(PasswordCheck::Incorrect)
+    }
+
+    /// Counts a failed signin against both the account and the IP address, locking either
+    /// once it passes its threshold.
+    pub async fn record_failure(&self, email: &str, ip: &str) -> Result<()> {
+        self.record_failure_for_key(
+            &account_key(email),
+            max_failed_attempts(
+                "LOGIN_MAX_FAILED_ATTEMPTS",
+                DEFAULT_MAX_FAILED_ATTEMPTS_PER_ACCOUNT,
+            ),
+        )
+        .await?;
+        self.record_failure_for_key(
+            &ip_key(ip),
+            max_failed_attempts(
+                "LOGIN_MAX_FAILED_ATTEMPTS_PER_IP",
+                DEFAULT_MAX_FAILED_ATTEMPTS_PER_IP,
+            ),
+        )
+        .await?;
+
+        // There is no scheduler in a serverless deployment, so stale counters
+        // are cleaned up whenever a new failure is written.
+        self.purge_expired().await?;
+
+        Ok(())
+    }
+
+    /// Counts a wrong MFA code against the user, locking their second factor once it passes
+    /// `MFA_MAX_FAILED_ATTEMPTS`.
+    pub async fn record_mfa_failure(&self, user_id: &Uuid) -> Result<()> {
+        self.record_failure_for_key(
+            &mfa_key(user_id),
+            max_failed_attempts("MFA_MAX_FAILED_ATTEMPTS", DEFAULT_MAX_FAILED_MFA_ATTEMPTS),
+        )
+        .await?;
+        self.purge_expired().await?;
+
+        Ok(())
+    }
+
+    async fn record_failure_for_key(&self, key: &str, threshold: i32) -> Result<()> {
+        let window = ttl_from_env(
+            "LOGIN_ATTEMPT_WINDOW_SECONDS",
+            DEFAULT_LOGIN_ATTEMPT_WINDOW_SECONDS,
+        );
+
+        // Failures older than the window are forgotten rather than added to
+        let row = sqlx::query(
+            r#"
+            INSERT INTO login_attempts (key, failed_count, last_failed_at)
+            VALUES ($1, 1, NOW())
+            ON CONFLICT (key) DO UPDATE SET
+                failed_count = CASE
+                    WHEN login_attempts.last_failed_at < NOW() - make_interval(secs => $2)
+                    THEN 1
+                    ELSE login_attempts.failed_count + 1
+                END,
+                last_failed_at = NOW()
+            RETURNING failed_count
+            "#,
+        )
+        .bind(key)
+        .bind(window.num_seconds() as f64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        if let Some(seconds) = lockout_seconds(row.get("failed_count"), threshold) {
+            sqlx::query(
+                "UPDATE login_attempts SET locked_until = NOW() + make_interval(secs => $1) WHERE key = $2",
+            )
+            .bind(seconds as f64)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Clears the account's counter once a signin completes, second factor included. The IP
+    /// counter is left to expire, so one good login can't reset a spraying address.
+    pub async fn record_success(&self, email: &str) -> Result<()> {
+        sqlx::query("DELETE FROM login_attempts WHERE key = $1")
+            .bind(account_key(email))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Clears the user's MFA failure counter once a code is accepted.
+    pub async fn record_mfa_success(&self, user_id: &Uuid) -> Result<()> {
+        sqlx::query("DELETE FROM login_attempts WHERE key = $1")
+            .bind(mfa_key(user_id))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn purge_expired(&self) -> Result<u64> {
+        let window = ttl_from_env(
+            "LOGIN_ATTEMPT_WINDOW_SECONDS",
+            DEFAULT_LOGIN_ATTEMPT_WINDOW_SECONDS,
+        );
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM login_attempts
+            WHERE last_failed_at < NOW() - make_interval(secs => $1)
+            AND (locked_until IS NULL OR locked_until < NOW())
+            "#,
+        )
+        .bind(window.num_seconds() as f64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_seconds_is_none_below_the_threshold() {
+        assert_eq!(lockout_seconds(0, 5), None);
+        assert_eq!(lockout_seconds(4, 5), None);
+    }
+
+    #[test]
+    fn lockout_seconds_doubles_from_the_threshold() {
+        assert_eq!(lockout_seconds(5, 5), Some(DEFAULT_LOCKOUT_SECONDS));
+        assert_eq!(lockout_seconds(6, 5), Some(DEFAULT_LOCKOUT_SECONDS * 2));
+        assert_eq!(lockout_seconds(7, 5), Some(DEFAULT_LOCKOUT_SECONDS * 4));
+    }
+
+    #[test]
+    fn lockout_seconds_is_capped() {
+        assert_eq!(lockout_seconds(5 + 7, 5), Some(DEFAULT_MAX_LOCKOUT_SECONDS));
+        assert_eq!(
+            lockout_seconds(i32::MAX, 5),
+            Some(DEFAULT_MAX_LOCKOUT_SECONDS)
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::auth::{generate_token, hash_token, ttl_from_env};
+use crate::User;
+
+const DEFAULT_MAGIC_LINK_TTL_SECONDS: i64 = 15 * 60;
+
+/// Lifetime of sign-in links, configurable through `MAGIC_LINK_TTL_SECONDS`.
+pub fn magic_link_ttl() -> chrono::Duration {
+    ttl_from_env("MAGIC_LINK_TTL_SECONDS", DEFAULT_MAGIC_LINK_TTL_SECONDS)
+}
+
+/// A sign-in link that was just created. The `token` goes into the emailed link and the
+/// `device_nonce` back to the device that asked for it; both are needed to sign in.
+pub struct PendingMagicLink {
+    pub token: String,
+    pub device_nonce: String,
+}
+
+pub struct MagicLinkRepository {
+    pool: PgPool,
+}
+
+impl MagicLinkRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates a sign-in link for the user, invalidating any earlier ones that were not used.
+    pub async fn create_magic_link(&self, user: &User, audience: &str) -> Result<PendingMagicLink> {
+        let pending = PendingMagicLink {
+            token: generate_token(),
+            device_nonce: generate_token(),
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE magic_links SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO magic_links
+                (id, user_id, email, token_hash, device_nonce_hash, audience, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(hash_token(&pending.token))
+        .bind(hash_token(&pending.device_nonce))
+        .bind(audience)
+        .bind(Utc::now() + magic_link_ttl())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(pending)
+    }
+
+    /// Uses up a valid link presented with the nonce of the device that asked for it and
+    /// returns the user and the audience the tokens are for.
+    ///
+    /// Following the link proves the user controls the address it was sent to, so an
+    /// unverified email is marked verified. A link sent before the user changed their email
+    /// address no longer works.
+    pub async fn consume_magic_link(
+        &self,
+        token: &str,
+        device_nonce: &str,
+    ) -> Result<(Uuid, String)> {
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query(
+            r#"
+            UPDATE magic_links SET used_at = NOW()
+            FROM users
+            WHERE magic_links.token_hash = $1
+            AND magic_links.device_nonce_hash = $2
+            AND magic_links.used_at IS NULL
+            AND magic_links.expires_at > NOW()
+            AND users.id = magic_links.user_id
+            AND users.email = magic_links.email
+            RETURNING magic_links.user_id, magic_links.audience
+            "#,
+        )
+        .bind(hash_token(token))
+        .bind(hash_token(device_nonce))
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| anyhow!("Invalid or expired sign-in link"))?;
+
+        let user_id: Uuid = row.get("user_id");
+
+        sqlx::query(
+            "UPDATE users SET email_verified_at = NOW() WHERE id = $1 AND email_verified_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok((user_id, row.get("audience")))
+    }
+}
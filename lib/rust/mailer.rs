@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::env;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers transactional email. Pick an implementation with `mailer_from_env`.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: &EmailMessage) -> Result<()>;
+}
+
+/// Prints every message to stdout, for local development only.
+pub struct StdoutMailer;
+
+#[async_trait]
+impl Mailer for StdoutMailer {
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        println!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            mail_from(),
+            message.to,
+            message.subject,
+            message.body
+        );
+        Ok(())
+    }
+}
+
+/// Writes every message to its own file so tests and local tooling can pick them up.
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+
+        let file_name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3f"),
+            Uuid::new_v4()
+        );
+        let contents = format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            mail_from(),
+            message.to,
+            message.subject,
+            message.body
+        );
+
+        tokio::fs::write(self.directory.join(file_name), contents).await?;
+        Ok(())
+    }
+}
+
+fn mail_from() -> String {
+    env::var("MAIL_FROM").unwrap_or_else(|_| "no-reply@localhost".to_string())
+}
+
+/// Builds the mailer selected by `MAILER` (`stdout` or `file`, writing to `MAILER_DIR`).
+///
+/// There is no default: messages carry sign-in, reset and verification links, so printing
+/// them to the function logs has to be asked for. `stdout` is refused unless `LOCAL_DEV=true`.
+pub fn mailer_from_env() -> Result<Box<dyn Mailer>> {
+    let mailer = env::var("MAILER").map_err(|_| anyhow!("MAILER is not set"))?;
+
+    match mailer.as_str() {
+        "stdout" if is_local_dev() => Ok(Box::new(StdoutMailer)),
+        "stdout" => Err(anyhow!("MAILER=stdout is only allowed with LOCAL_DEV=true")),
+        "file" => {
+            let directory = env::var("MAILER_DIR").unwrap_or_else(|_| "mail".to_string());
+            Ok(Box::new(FileMailer::new(directory)))
+        }
+        other => Err(anyhow!("Unknown MAILER: {}", other)),
+    }
+}
+
+fn is_local_dev() -> bool {
+    env::var("LOCAL_DEV").is_ok_and(|value| value == "true")
+}
@@ -0,0 +1,305 @@
+use anyhow::{anyhow, Result};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use sqlx::Row;
+use std::env;
+use url::form_urlencoded::byte_serialize;
+use uuid::Uuid;
+
+use crate::auth::hash_token;
+use crate::UserRepository;
+
+const TOTP_PERIOD_SECONDS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Generates a random 160-bit TOTP secret, base32 encoded as authenticator apps expect.
+pub fn generate_totp_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// Builds the `otpauth://` URI that authenticator apps read from a QR code.
+pub fn otpauth_uri(account: &str, secret: &str) -> String {
+    let issuer = env::var("MFA_ISSUER").unwrap_or_else(|_| "Rust on Vercel".to_string());
+    let label: String = byte_serialize(format!("{}:{}", issuer, account).as_bytes()).collect();
+    let issuer: String = byte_serialize(issuer.as_bytes()).collect();
+
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        label, secret, issuer, TOTP_DIGITS, TOTP_PERIOD_SECONDS
+    )
+}
+
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation as described in RFC 4226 section 5.3
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+/// Checks a TOTP code against the current time step and one step either side to allow
+/// for clock drift, returning the matching step.
+pub fn verify_totp(secret: &str, code: &str) -> Option<i64> {
+    verify_totp_at(secret, code, chrono::Utc::now().timestamp())
+}
+
+fn verify_totp_at(secret: &str, code: &str, now: i64) -> Option<i64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current_step = now / TOTP_PERIOD_SECONDS;
+
+    (current_step - 1..=current_step + 1).find(|step| hotp(&secret, *step as u64) == code)
+}
+
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_lowercase().replace([' ', '-'], "")
+}
+
+impl UserRepository {
+    /// Stores a new, not yet confirmed TOTP secret for the user and returns it.
+    pub async fn begin_totp_enrollment(&self, user_id: &Uuid) -> Result<String> {
+        let secret = generate_totp_secret();
+
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET totp_secret = $1, totp_last_used_step = NULL, updated_at = NOW()
+            WHERE id = $2 AND totp_enabled_at IS NULL
+            "#,
+        )
+        .bind(&secret)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Two-factor authentication is already enabled"));
+        }
+
+        Ok(secret)
+    }
+
+    /// Enables TOTP once the user proves their authenticator produces valid codes,
+    /// returning a fresh set of single-use recovery codes.
+    pub async fn confirm_totp_enrollment(&self, user_id: &Uuid, code: &str) -> Result<Vec<String>> {
+        let row = sqlx::query(
+            "SELECT totp_secret, totp_enabled_at IS NOT NULL AS enabled FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("User not found"))?;
+
+        if row.get::<bool, _>("enabled") {
+            return Err(anyhow!("Two-factor authentication is already enabled"));
+        }
+
+        let secret: Option<String> = row.get("totp_secret");
+        let secret = secret.ok_or_else(|| anyhow!("Two-factor enrollment has not been started"))?;
+        let step = verify_totp(&secret, code).ok_or_else(|| anyhow!("Invalid code"))?;
+
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE users SET totp_enabled_at = NOW(), totp_last_used_step = $1, updated_at = NOW()
+            WHERE id = $2
+            "#,
+        )
+        .bind(step)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for recovery_code in &recovery_codes {
+            sqlx::query(
+                "INSERT INTO mfa_recovery_codes (id, user_id, code_hash, created_at) VALUES ($1, $2, $3, NOW())",
+            )
+            .bind(Uuid::new_v4())
+            .bind(user_id)
+            .bind(hash_token(&normalize_recovery_code(recovery_code)))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(recovery_codes)
+    }
+
+    pub async fn is_mfa_enabled(&self, user_id: &Uuid) -> Result<bool> {
+        Ok(!self.mfa_methods(user_id).await?.is_empty())
+    }
+
+    /// The second factors the user has set up: "totp" for an authenticator app and
+    /// "passkey" for any registered passkey.
+    pub async fn mfa_methods(&self, user_id: &Uuid) -> Result<Vec<String>> {
+        let row = sqlx::query(
+            r#"
+            SELECT totp_enabled_at IS NOT NULL AS totp,
+                EXISTS (SELECT 1 FROM passkeys WHERE user_id = users.id) AS passkey
+            FROM users WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let mut methods = Vec::new();
+        if let Some(row) = row {
+            if row.get("totp") {
+                methods.push("totp".to_string());
+            }
+            if row.get("passkey") {
+                methods.push("passkey".to_string());
+            }
+        }
+
+        Ok(methods)
+    }
+
+    /// Accepts either a current TOTP code or an unused recovery code.
+    ///
+    /// A TOTP code is only accepted once: its time step must be newer than the last one used.
+    pub async fn verify_mfa_code(&self, user_id: &Uuid, code: &str) -> Result<()> {
+        let row = sqlx::query(
+            "SELECT totp_secret FROM users WHERE id = $1 AND totp_enabled_at IS NOT NULL",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("Two-factor authentication is not enabled"))?;
+
+        let secret: Option<String> = row.get("totp_secret");
+        if let Some(step) = secret
+            .as_deref()
+            .and_then(|secret| verify_totp(secret, code))
+        {
+            let result = sqlx::query(
+                r#"
+                UPDATE users SET totp_last_used_step = $1
+                WHERE id = $2 AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+                "#,
+            )
+            .bind(step)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+            if result.rows_affected() == 1 {
+                return Ok(());
+            }
+            return Err(anyhow!("Code has already been used"));
+        }
+
+        let result = sqlx::query(
+            r#"
+            UPDATE mfa_recovery_codes SET used_at = NOW()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(hash_token(&normalize_recovery_code(code)))
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 1 {
+            Ok(())
+        } else {
+            Err(anyhow!("Invalid code"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SHA-1 seed from RFC 6238 appendix B, "12345678901234567890", in base32
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    // RFC 6238 appendix B SHA-1 vectors, cut from eight digits to the six we use
+    const RFC_VECTORS: [(i64, &str); 6] = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    #[test]
+    fn hotp_matches_rfc_6238_vectors() {
+        let secret = b"12345678901234567890";
+        for (time, code) in RFC_VECTORS {
+            let step = (time / TOTP_PERIOD_SECONDS) as u64;
+            assert_eq!(format!("{:06}", hotp(secret, step)), code, "T = {}", time);
+        }
+    }
+
+    #[test]
+    fn verify_totp_accepts_rfc_6238_codes() {
+        for (time, code) in RFC_VECTORS {
+            assert_eq!(
+                verify_totp_at(RFC_SECRET, code, time),
+                Some(time / TOTP_PERIOD_SECONDS),
+                "T = {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn verify_totp_allows_one_step_of_drift() {
+        assert_eq!(verify_totp_at(RFC_SECRET, "287082", 59 + 30), Some(1));
+        assert_eq!(verify_totp_at(RFC_SECRET, "287082", 59 - 30), Some(1));
+        assert_eq!(verify_totp_at(RFC_SECRET, "287082", 59 + 60), None);
+    }
+
+    #[test]
+    fn verify_totp_requires_exactly_six_digits() {
+        let time = 1111111109;
+        assert!(verify_totp_at(RFC_SECRET, " 081804 ", time).is_some());
+        for code in ["81804", "0081804", "+81804", "08180４", "081 804", ""] {
+            assert_eq!(verify_totp_at(RFC_SECRET, code, time), None, "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn verify_totp_rejects_a_malformed_secret() {
+        assert_eq!(verify_totp_at("not base32!", "287082", 59), None);
+    }
+}
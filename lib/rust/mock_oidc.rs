@@ -0,0 +1,296 @@
+//! A minimal OpenID Connect provider for trying "Sign in with ..." locally.
+//!
+//! Every authorization request is approved straight away for the address in `login_hint`
+//! (or `MOCK_OIDC_EMAIL`), and the user is redirected back with a code. Point a provider at it:
+//!
+//! ```text
+//! OIDC_PROVIDERS=mock
+//! OIDC_MOCK_ISSUER=http://localhost:9400
+//! OIDC_MOCK_CLIENT_ID=mock-client
+//! ```
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{encode, Header};
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+use rust_on_vercel_template::auth::generate_token;
+use rust_on_vercel_template::keys::JwtKey;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const KEY_ID: &str = "mock-oidc";
+
+/// An issued authorization code, waiting to be redeemed at the token endpoint.
+struct AuthorizationCode {
+    client_id: String,
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: Option<String>,
+    email: String,
+}
+
+struct Provider {
+    issuer: String,
+    client_id: String,
+    key: JwtKey,
+    codes: Mutex<HashMap<String, AuthorizationCode>>,
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: HashMap<String, String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+
+    let port = env::var("MOCK_OIDC_PORT").unwrap_or_else(|_| "9400".to_string());
+    let issuer =
+        env::var("MOCK_OIDC_ISSUER").unwrap_or_else(|_| format!("http://localhost:{}", port));
+
+    // A fresh signing key per run; relying parties fetch it from the JWKS endpoint
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| anyhow!("Failed to generate signing key"))?;
+    let private_pem = pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref().to_vec()));
+
+    let provider = Arc::new(Provider {
+        client_id: env::var("OIDC_MOCK_CLIENT_ID").unwrap_or_else(|_| "mock-client".to_string()),
+        key: JwtKey::ed25519_private_pem(KEY_ID, &private_pem)?,
+        codes: Mutex::new(HashMap::new()),
+        issuer,
+    });
+
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+    println!("Mock OIDC provider listening on {}", provider.issuer);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let provider = provider.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &provider).await {
+                eprintln!("Request failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, provider: &Provider) -> Result<()> {
+    let request = read_request(&mut stream).await?;
+    println!("{} {}", request.method, request.path);
+
+    let response = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/.well-known/openid-configuration") => json_response(
+            200,
+            &json!({
+                "issuer": provider.issuer,
+                "authorization_endpoint": format!("{}/authorize", provider.issuer),
+                "token_endpoint": format!("{}/token", provider.issuer),
+                "jwks_uri": format!("{}/jwks", provider.issuer),
+                "response_types_supported": ["code"],
+                "subject_types_supported": ["public"],
+                "id_token_signing_alg_values_supported": ["EdDSA"],
+                "code_challenge_methods_supported": ["S256"],
+            }),
+        ),
+        ("GET", "/jwks") => {
+            let jwks = JwkSet {
+                keys: provider.key.public_jwk().cloned().into_iter().collect(),
+            };
+            json_response(200, &serde_json::to_value(jwks)?)
+        }
+        ("GET", "/authorize") => authorize(provider, &request),
+        ("POST", "/token") => token(provider, &request)?,
+        _ => json_response(404, &json!({ "error": "not_found" })),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Approves the request and sends the user back to the relying party with a code.
+fn authorize(provider: &Provider, request: &HttpRequest) -> String {
+    let param = |name: &str| request.query.get(name).cloned().unwrap_or_default();
+
+    if param("client_id") != provider.client_id {
+        return json_response(400, &json!({ "error": "unauthorized_client" }));
+    }
+    if param("response_type") != "code" || param("code_challenge_method") != "S256" {
+        return json_response(400, &json!({ "error": "invalid_request" }));
+    }
+
+    let mut redirect = match url::Url::parse(&param("redirect_uri")) {
+        Ok(url) => url,
+        Err(_) => return json_response(400, &json!({ "error": "invalid_request" })),
+    };
+
+    let code = generate_token();
+    let email = request
+        .query
+        .get("login_hint")
+        .cloned()
+        .or_else(|| env::var("MOCK_OIDC_EMAIL").ok())
+        .unwrap_or_else(|| "jane@example.com".to_string());
+
+    provider.codes.lock().unwrap().insert(
+        code.clone(),
+        AuthorizationCode {
+            client_id: param("client_id"),
+            redirect_uri: param("redirect_uri"),
+            code_challenge: param("code_challenge"),
+            nonce: request.query.get("nonce").cloned(),
+            email,
+        },
+    );
+
+    redirect
+        .query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", &param("state"));
+
+    format!(
+        "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        redirect
+    )
+}
+
+/// Redeems a code, checking the PKCE verifier, and returns a signed ID token.
+fn token(provider: &Provider, request: &HttpRequest) -> Result<String> {
+    let param = |name: &str| request.body.get(name).cloned().unwrap_or_default();
+    let invalid_grant = || json_response(400, &json!({ "error": "invalid_grant" }));
+
+    let client_id = basic_auth_user(request).unwrap_or_else(|| param("client_id"));
+
+    let code = match provider.codes.lock().unwrap().remove(&param("code")) {
+        Some(code) => code,
+        None => return Ok(invalid_grant()),
+    };
+
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(param("code_verifier").as_bytes()));
+    if param("grant_type") != "authorization_code"
+        || client_id != code.client_id
+        || param("redirect_uri") != code.redirect_uri
+        || challenge != code.code_challenge
+    {
+        return Ok(invalid_grant());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = json!({
+        "iss": provider.issuer,
+        "aud": code.client_id,
+        "sub": format!("mock|{}", code.email),
+        "email": code.email,
+        "email_verified": true,
+        "name": code.email.split('@').next().unwrap_or_default(),
+        "nonce": code.nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+
+    let mut header = Header::new(provider.key.algorithm);
+    header.kid = Some(KEY_ID.to_string());
+    let id_token = encode(&header, &claims, provider.key.encoding_key()?)?;
+
+    Ok(json_response(
+        200,
+        &json!({
+            "access_token": generate_token(),
+            "token_type": "Bearer",
+            "expires_in": 300,
+            "id_token": id_token,
+        }),
+    ))
+}
+
+fn basic_auth_user(request: &HttpRequest) -> Option<String> {
+    let credentials = request
+        .headers
+        .get("authorization")?
+        .strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(credentials)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    decoded.split(':').next().map(str::to_string)
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<HttpRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(anyhow!("Connection closed"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let body = &buffer[header_end..];
+
+    Ok(HttpRequest {
+        method,
+        path: path.to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        headers,
+        body: url::form_urlencoded::parse(body).into_owned().collect(),
+    })
+}
+
+fn json_response(status: u16, body: &serde_json::Value) -> String {
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        _ => "Not Found",
+    };
+
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,614 @@
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Row};
+use std::env;
+use uuid::Uuid;
+
+use crate::auth::{generate_token, hash_token};
+use crate::profile::validate_profile_update;
+use crate::{app_url, CreateUserRequest, UpdateProfileRequest, User, UserRepository};
+
+/// How long a user has to finish signing in at the provider.
+const LOGIN_STATE_TTL_SECONDS: i64 = 10 * 60;
+
+const DEFAULT_SCOPES: &str = "openid email profile";
+
+/// Signature algorithms accepted on ID tokens. Shared-secret (HS*) tokens are refused.
+const ID_TOKEN_ALGORITHMS: [Algorithm; 5] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::ES256,
+    Algorithm::EdDSA,
+];
+
+/// An OpenID Connect provider configured through the environment. For `OIDC_PROVIDERS=google`:
+///
+/// ```text
+/// OIDC_GOOGLE_ISSUER=https://accounts.google.com
+/// OIDC_GOOGLE_CLIENT_ID=...
+/// OIDC_GOOGLE_CLIENT_SECRET=...
+/// OIDC_GOOGLE_SCOPES=openid email profile   # optional
+/// ```
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>, // None for public clients, which rely on PKCE alone
+    pub scopes: String,
+}
+
+/// The parts of the provider's discovery document the client uses.
+#[derive(Debug, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// The verified claims of an ID token that are used to find or create the user.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub picture: Option<String>,
+    nonce: Option<String>,
+}
+
+impl OidcProvider {
+    /// Loads a provider named in `OIDC_PROVIDERS` (comma separated, case insensitive).
+    pub fn from_env(name: &str) -> Result<Self> {
+        let name = name.trim().to_lowercase();
+        if !configured_providers().contains(&name) {
+            return Err(anyhow!("Unknown identity provider: {}", name));
+        }
+
+        let var = |field: &str| env::var(format!("OIDC_{}_{}", name.to_uppercase(), field));
+
+        Ok(Self {
+            issuer: var("ISSUER")
+                .map_err(|_| anyhow!("OIDC_{}_ISSUER must be set", name.to_uppercase()))?
+                .trim_end_matches('/')
+                .to_string(),
+            client_id: var("CLIENT_ID")
+                .map_err(|_| anyhow!("OIDC_{}_CLIENT_ID must be set", name.to_uppercase()))?,
+            client_secret: var("CLIENT_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            scopes: var("SCOPES").unwrap_or_else(|_| DEFAULT_SCOPES.to_string()),
+            name,
+        })
+    }
+
+    /// Fetches the provider's discovery document, which has to be for the configured issuer.
+    pub async fn discover(&self) -> Result<ProviderMetadata> {
+        let metadata: ProviderMetadata =
+            reqwest::get(format!("{}/.well-known/openid-configuration", self.issuer))
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+        if metadata.issuer.trim_end_matches('/') != self.issuer {
+            return Err(anyhow!("Discovery document is for another issuer"));
+        }
+
+        Ok(metadata)
+    }
+
+    /// The URL to send the user to. The `code_verifier` is kept server-side; only its S256
+    /// challenge goes to the provider.
+    pub fn authorization_url(
+        &self,
+        metadata: &ProviderMetadata,
+        redirect_uri: &str,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String> {
+        let mut url = url::Url::parse(&metadata.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &self.scopes)
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &pkce_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.into())
+    }
+
+    /// Redeems an authorization code at the token endpoint and returns the ID token.
+    pub async fn exchange_code(
+        &self,
+        metadata: &ProviderMetadata,
+        code: &str,
+        code_verifier: &str,
+        redirect_uri: &str,
+    ) -> Result<String> {
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", code_verifier),
+            ("client_id", &self.client_id),
+        ];
+
+        let request = reqwest::Client::new()
+            .post(&metadata.token_endpoint)
+            .header("accept", "application/json")
+            .form(&form);
+        let request = match &self.client_secret {
+            Some(secret) => request.basic_auth(&self.client_id, Some(secret)),
+            None => request,
+        };
+
+        let response: TokenResponse = request.send().await?.error_for_status()?.json().await?;
+
+        response
+            .id_token
+            .ok_or_else(|| anyhow!("Token response has no ID token"))
+    }
+
+    /// Verifies an ID token's signature against the provider's JWKS, its issuer, audience and
+    /// expiry, and that it carries the nonce of this sign-in.
+    pub async fn verify_id_token(
+        &self,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims> {
+        let jwks: JwkSet = reqwest::get(&metadata.jwks_uri)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        self.verify_id_token_with(metadata, &jwks, id_token, nonce)
+    }
+
+    /// Checks an ID token against keys already fetched from the provider's `jwks_uri`.
+    fn verify_id_token_with(
+        &self,
+        metadata: &ProviderMetadata,
+        jwks: &JwkSet,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims> {
+        let header = decode_header(id_token)?;
+        if !ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+            return Err(anyhow!("Unsupported ID token algorithm"));
+        }
+
+        let jwk = match header.kid.as_deref() {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| anyhow!("Unknown ID token signing key"))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims =
+            decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(anyhow!("ID token nonce mismatch"));
+        }
+
+        Ok(claims)
+    }
+}
+
+/// The names listed in `OIDC_PROVIDERS`, lowercased.
+pub fn configured_providers() -> Vec<String> {
+    env::var("OIDC_PROVIDERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Where providers send the user back to, `OIDC_REDIRECT_URI` or `APP_URL/auth/callback`.
+/// The frontend page there posts the `code` and `state` to `/api/auth/oidc/callback`.
+pub fn oidc_redirect_uri() -> String {
+    env::var("OIDC_REDIRECT_URI").unwrap_or_else(|_| format!("{}/auth/callback", app_url()))
+}
+
+pub(crate) fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// The secrets of a sign-in that was just started; `state` goes to the provider in the
+/// authorization URL and comes back with the code.
+pub struct PendingLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// A sign-in coming back from the provider, as started by `create_login_state`.
+pub struct LoginState {
+    pub provider: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub redirect_uri: String,
+    pub audience: String,
+}
+
+/// The user an external identity belongs to.
+pub struct ResolvedIdentity {
+    pub user: User,
+    pub created: bool, // the account was created for this sign-in
+}
+
+pub struct OidcRepository {
+    pool: PgPool,
+}
+
+impl OidcRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Starts a sign-in with the provider for the given audience.
+    pub async fn create_login_state(
+        &self,
+        provider: &str,
+        audience: &str,
+        redirect_uri: &str,
+    ) -> Result<PendingLogin> {
+        let pending = PendingLogin {
+            state: generate_token(),
+            nonce: generate_token(),
+            code_verifier: generate_token(),
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO oidc_login_states
+                (id, state_hash, provider, nonce, code_verifier, redirect_uri, audience, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(hash_token(&pending.state))
+        .bind(provider)
+        .bind(&pending.nonce)
+        .bind(&pending.code_verifier)
+        .bind(redirect_uri)
+        .bind(audience)
+        .bind(Utc::now() + Duration::seconds(LOGIN_STATE_TTL_SECONDS))
+        .execute(&self.pool)
+        .await?;
+
+        // There is no scheduler in a serverless deployment, so abandoned sign-ins
+        // are cleaned up whenever a new one starts.
+        sqlx::query("DELETE FROM oidc_login_states WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(pending)
+    }
+
+    /// Consumes the sign-in a `state` belongs to, so each can only be completed once.
+    pub async fn take_login_state(&self, state: &str) -> Result<Option<LoginState>> {
+        let row = sqlx::query(
+            r#"
+            DELETE FROM oidc_login_states
+            WHERE state_hash = $1 AND expires_at > NOW()
+            RETURNING provider, nonce, code_verifier, redirect_uri, audience
+            "#,
+        )
+        .bind(hash_token(state))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| LoginState {
+            provider: row.get("provider"),
+            nonce: row.get("nonce"),
+            code_verifier: row.get("code_verifier"),
+            redirect_uri: row.get("redirect_uri"),
+            audience: row.get("audience"),
+        }))
+    }
+
+    /// Finds the user linked to an external identity, linking or creating one on first use.
+    ///
+    /// A new identity is only accepted when the provider verified its email address, and an
+    /// existing account is only linked by email when this backend has verified it too;
+    /// otherwise `None` is returned. Either way round, whoever registered the address first
+    /// could otherwise keep a way into the owner's account.
+    /// New accounts get a random password, which can be set with the password reset flow.
+    pub async fn find_or_create_user(
+        &self,
+        provider: &str,
+        claims: &IdTokenClaims,
+    ) -> Result<Option<ResolvedIdentity>> {
+        let user_repo = UserRepository::new(self.pool.clone());
+
+        let linked_user_id: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            UPDATE user_identities SET last_signin_at = NOW(), email = COALESCE($3, email)
+            WHERE provider = $1 AND subject = $2
+            RETURNING user_id
+            "#,
+        )
+        .bind(provider)
+        .bind(&claims.sub)
+        .bind(&claims.email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(user_id) = linked_user_id {
+            return Ok(user_repo
+                .get_user_by_id(&user_id)
+                .await?
+                .map(|user| ResolvedIdentity {
+                    user,
+                    created: false,
+                }));
+        }
+
+        let email = claims
+            .email
+            .as_deref()
+            .ok_or_else(|| anyhow!("Identity provider did not share an email address"))?;
+        if !claims.email_verified {
+            return Ok(None);
+        }
+
+        let resolved = match user_repo.get_user_by_email(email).await? {
+            Some(user) if user.email_verified_at.is_none() => return Ok(None),
+            Some(user) => ResolvedIdentity {
+                user,
+                created: false,
+            },
+            None => ResolvedIdentity {
+                user: self.create_user_for_identity(email, claims).await?,
+                created: true,
+            },
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO user_identities (id, user_id, provider, subject, email, created_at, last_signin_at)
+            VALUES ($1, $2, $3, $4, $5, NOW(), NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(resolved.user.id)
+        .bind(provider)
+        .bind(&claims.sub)
+        .bind(email)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(Some(resolved))
+    }
+
+    async fn create_user_for_identity(&self, email: &str, claims: &IdTokenClaims) -> Result<User> {
+        let user_repo = UserRepository::new(self.pool.clone());
+
+        let user = user_repo
+            .create_user(CreateUserRequest {
+                email: email.to_string(),
+                username: self.available_username(email).await?,
+                password: generate_token(),
+                audience: None,
+                invite_token: None,
+            })
+            .await?;
+
+        // Only identities whose email the provider verified get this far
+        sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE id = $1")
+            .bind(user.id)
+            .execute(&self.pool)
+            .await?;
+
+        // Take over the name and picture where they pass the usual profile checks
+        let profile = UpdateProfileRequest {
+            username: None,
+            display_name: claims.name.clone(),
+            avatar_url: claims.picture.clone(),
+            locale: None,
+            timezone: None,
+        };
+        if validate_profile_update(&profile).is_ok() {
+            user_repo.update_profile(&user.id, &profile).await?;
+        }
+
+        user_repo
+            .get_user_by_id(&user.id)
+            .await?
+            .ok_or_else(|| anyhow!("User not found"))
+    }
+
+    /// Derives a free username from the email's local part, adding a random suffix if taken.
+    async fn available_username(&self, email: &str) -> Result<String> {
+        let base: String = email
+            .split('@')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+            .take(40)
+            .collect();
+        let base = if base.is_empty() {
+            "user".to_string()
+        } else {
+            base
+        };
+
+        let user_repo = UserRepository::new(self.pool.clone());
+        let mut username = base.clone();
+        while user_repo.get_user_by_username(&username).await?.is_some() {
+            username = format!("{}-{}", base, &generate_token()[..6]);
+        }
+
+        Ok(username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::JwtKey;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::Ed25519KeyPair;
+    use serde_json::{json, Value};
+
+    const ISSUER: &str = "https://idp.example.com";
+    const CLIENT_ID: &str = "client";
+    const NONCE: &str = "nonce";
+
+    fn provider() -> OidcProvider {
+        OidcProvider {
+            name: "example".to_string(),
+            issuer: ISSUER.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            scopes: DEFAULT_SCOPES.to_string(),
+        }
+    }
+
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata {
+            issuer: ISSUER.to_string(),
+            authorization_endpoint: format!("{}/authorize", ISSUER),
+            token_endpoint: format!("{}/token", ISSUER),
+            jwks_uri: format!("{}/jwks", ISSUER),
+        }
+    }
+
+    /// A fresh Ed25519 signing key and the JWKS the provider would publish for it.
+    fn signing_key() -> (JwtKey, JwkSet) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref()));
+        let key = JwtKey::ed25519_private_pem("idp-1", &pem).unwrap();
+        let jwks = JwkSet {
+            keys: vec![key.public_jwk().unwrap().clone()],
+        };
+        (key, jwks)
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": ISSUER,
+            "aud": CLIENT_ID,
+            "sub": "subject",
+            "email": "user@example.com",
+            "email_verified": true,
+            "exp": Utc::now().timestamp() + 300,
+            "nonce": NONCE,
+        })
+    }
+
+    fn sign(key: &JwtKey, claims: &Value) -> String {
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        encode(&header, claims, key.encoding_key().unwrap()).unwrap()
+    }
+
+    fn verify(jwks: &JwkSet, id_token: &str) -> Result<IdTokenClaims> {
+        provider().verify_id_token_with(&metadata(), jwks, id_token, NONCE)
+    }
+
+    #[test]
+    fn pkce_challenge_matches_rfc_7636() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn verify_id_token_accepts_a_valid_token() {
+        let (key, jwks) = signing_key();
+        let claims = verify(&jwks, &sign(&key, &claims())).unwrap();
+
+        assert_eq!(claims.sub, "subject");
+        assert_eq!(claims.email.as_deref(), Some("user@example.com"));
+        assert!(claims.email_verified);
+    }
+
+    #[test]
+    fn verify_id_token_rejects_a_wrong_nonce() {
+        let (key, jwks) = signing_key();
+        let mut claims = claims();
+        claims["nonce"] = json!("another-nonce");
+        assert!(verify(&jwks, &sign(&key, &claims)).is_err());
+
+        claims.as_object_mut().unwrap().remove("nonce");
+        assert!(verify(&jwks, &sign(&key, &claims)).is_err());
+    }
+
+    #[test]
+    fn verify_id_token_rejects_a_wrong_audience() {
+        let (key, jwks) = signing_key();
+        let mut claims = claims();
+        claims["aud"] = json!("another-client");
+        assert!(verify(&jwks, &sign(&key, &claims)).is_err());
+    }
+
+    #[test]
+    fn verify_id_token_rejects_a_wrong_issuer() {
+        let (key, jwks) = signing_key();
+        let mut claims = claims();
+        claims["iss"] = json!("https://evil.example.com");
+        assert!(verify(&jwks, &sign(&key, &claims)).is_err());
+    }
+
+    #[test]
+    fn verify_id_token_rejects_an_expired_token() {
+        let (key, jwks) = signing_key();
+        let mut claims = claims();
+        claims["exp"] = json!(Utc::now().timestamp() - 3600);
+        assert!(verify(&jwks, &sign(&key, &claims)).is_err());
+    }
+
+    #[test]
+    fn verify_id_token_rejects_a_token_from_another_key() {
+        let (_, jwks) = signing_key();
+        let (other_key, _) = signing_key();
+        assert!(verify(&jwks, &sign(&other_key, &claims())).is_err());
+    }
+
+    #[test]
+    fn verify_id_token_rejects_hs256() {
+        let (_, jwks) = signing_key();
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("idp-1".to_string());
+        let id_token = encode(
+            &header,
+            &claims(),
+            &EncodingKey::from_secret(CLIENT_ID.as_bytes()),
+        )
+        .unwrap();
+
+        let error = verify(&jwks, &id_token).unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported ID token algorithm");
+    }
+}
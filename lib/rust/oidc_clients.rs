@@ -0,0 +1,78 @@
+//! Registers the apps that sign users in with this backend as their OpenID Connect provider.
+//!
+//! ```text
+//! cargo run --bin oidc-clients -- create "Internal dashboard" https://dashboard.example.com/callback
+//! cargo run --bin oidc-clients -- create --public "CLI" http://localhost:8400/callback
+//! cargo run --bin oidc-clients -- list
+//! cargo run --bin oidc-clients -- revoke <client_id>
+//! ```
+
+use anyhow::{anyhow, Result};
+use rust_on_vercel_template::create_pool;
+use rust_on_vercel_template::oidc_provider::OidcProviderRepository;
+
+const USAGE: &str = "Usage:
+  oidc-clients create [--public] <name> <redirect_uri>...
+  oidc-clients list
+  oidc-clients revoke <client_id>";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = args.first().map(String::as_str).unwrap_or_default();
+
+    let pool = create_pool().await?;
+    let repo = OidcProviderRepository::new(pool);
+
+    match command {
+        "create" => {
+            let public = args.iter().any(|arg| arg == "--public");
+            let rest: Vec<String> = args[1..]
+                .iter()
+                .filter(|arg| *arg != "--public")
+                .cloned()
+                .collect();
+            let (name, redirect_uris) = rest.split_first().ok_or_else(|| anyhow!(USAGE))?;
+
+            let (client, client_secret) = repo.create_client(name, redirect_uris, !public).await?;
+
+            println!("Created client {}", client.name);
+            println!("client_id:     {}", client.client_id);
+            match client_secret {
+                Some(secret) => {
+                    println!("client_secret: {}", secret);
+                    println!("The secret is not stored and cannot be shown again.");
+                }
+                None => println!("Public client: no secret, PKCE is required."),
+            }
+        }
+        "list" => {
+            for client in repo.list_clients().await? {
+                println!(
+                    "{}  {}  {}  {}",
+                    client.client_id,
+                    if client.confidential {
+                        "confidential"
+                    } else {
+                        "public"
+                    },
+                    client.name,
+                    client.redirect_uris.join(" ")
+                );
+            }
+        }
+        "revoke" => {
+            let client_id = args.get(1).ok_or_else(|| anyhow!(USAGE))?;
+            if repo.revoke_client(client_id).await? {
+                println!("Revoked client {}", client_id);
+            } else {
+                return Err(anyhow!("No active client {}", client_id));
+            }
+        }
+        _ => return Err(anyhow!(USAGE)),
+    }
+
+    Ok(())
+}
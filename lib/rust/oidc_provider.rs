@@ -0,0 +1,748 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgPool, Row};
+use std::collections::HashMap;
+use uuid::Uuid;
+use vercel_runtime::Request;
+
+use crate::auth::{access_token_ttl, generate_token, hash_token, jwt_issuer, Keyring};
+use crate::oidc::pkce_challenge;
+use crate::{app_url, OidcUserInfo, User};
+
+/// How long a client has to redeem an authorization code; RFC 6749 recommends at most 10 minutes.
+const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 5 * 60;
+
+/// Scopes clients can ask for. `openid` is required; anything else is ignored.
+pub const SUPPORTED_SCOPES: [&str; 3] = ["openid", "email", "profile"];
+
+/// An app registered to sign users in here, see the `oidc-clients` command.
+#[derive(Debug, FromRow)]
+pub struct OidcClient {
+    pub client_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub confidential: bool, // has a client secret; public clients rely on PKCE alone
+    pub created_at: DateTime<Utc>,
+}
+
+/// What a redeemed authorization code grants the client.
+pub struct AuthorizationGrant {
+    pub user_id: Uuid,
+    pub scopes: Vec<String>,
+    pub nonce: Option<String>,
+    pub auth_time: DateTime<Utc>,
+}
+
+/// The parameters of an authorization request. They arrive in the query string and are
+/// carried through the sign-in form as hidden fields.
+#[derive(Debug)]
+pub struct AuthorizationRequest {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub response_type: String,
+    pub scope: String,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: String,
+    pub code_challenge_method: String,
+}
+
+/// Why an authorization request was refused.
+pub enum AuthorizationError {
+    /// The client or redirect URI can't be trusted, so the user is shown the error instead
+    /// of being sent anywhere.
+    InvalidClient(&'static str),
+    /// Reported back to the client's redirect URI, as RFC 6749 section 4.1.2.1 describes.
+    Redirect {
+        error: &'static str,
+        description: &'static str,
+    },
+}
+
+/// Parses a query string or `application/x-www-form-urlencoded` body.
+pub fn parse_form(input: &[u8]) -> HashMap<String, String> {
+    url::form_urlencoded::parse(input).into_owned().collect()
+}
+
+impl AuthorizationRequest {
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        let param = |name: &str| params.get(name).cloned().unwrap_or_default();
+        let optional = |name: &str| params.get(name).filter(|value| !value.is_empty()).cloned();
+
+        Self {
+            client_id: param("client_id"),
+            redirect_uri: param("redirect_uri"),
+            response_type: param("response_type"),
+            scope: param("scope"),
+            state: optional("state"),
+            nonce: optional("nonce"),
+            code_challenge: param("code_challenge"),
+            code_challenge_method: param("code_challenge_method"),
+        }
+    }
+
+    /// Checks the request against the client it names and returns the granted scopes.
+    pub fn validate(&self, client: Option<&OidcClient>) -> Result<Vec<String>, AuthorizationError> {
+        let client = client.ok_or(AuthorizationError::InvalidClient("Unknown client"))?;
+
+        // Redirect URIs are matched exactly, never by prefix
+        if !client.redirect_uris.contains(&self.redirect_uri) {
+            return Err(AuthorizationError::InvalidClient(
+                "Redirect URI is not registered for this client",
+            ));
+        }
+
+        if self.response_type != "code" {
+            return Err(AuthorizationError::Redirect {
+                error: "unsupported_response_type",
+                description: "Only the authorization code flow is supported",
+            });
+        }
+
+        let scopes: Vec<String> = self
+            .scope
+            .split_whitespace()
+            .filter(|scope| SUPPORTED_SCOPES.contains(scope))
+            .map(str::to_string)
+            .collect();
+        if !scopes.iter().any(|scope| scope == "openid") {
+            return Err(AuthorizationError::Redirect {
+                error: "invalid_scope",
+                description: "The openid scope is required",
+            });
+        }
+
+        if self.code_challenge.is_empty() || self.code_challenge_method != "S256" {
+            return Err(AuthorizationError::Redirect {
+                error: "invalid_request",
+                description: "PKCE with code_challenge_method S256 is required",
+            });
+        }
+
+        Ok(scopes)
+    }
+
+    /// The client's redirect URI with the given parameters and the request's `state`.
+    pub fn redirect_url(&self, params: &[(&str, &str)]) -> Result<String> {
+        let mut url = url::Url::parse(&self.redirect_uri)?;
+        {
+            let mut query = url.query_pairs_mut();
+            for (name, value) in params {
+                query.append_pair(name, value);
+            }
+            if let Some(state) = &self.state {
+                query.append_pair("state", state);
+            }
+        }
+
+        Ok(url.into())
+    }
+}
+
+/// The client id and secret sent to the token endpoint, either with HTTP Basic
+/// authentication or as `client_id` and `client_secret` form fields.
+pub fn client_credentials(
+    req: &Request,
+    form: &HashMap<String, String>,
+) -> Option<(String, Option<String>)> {
+    use base64::Engine;
+
+    let basic = req
+        .headers()
+        .get("authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Basic "))
+        .and_then(|credentials| {
+            base64::engine::general_purpose::STANDARD
+                .decode(credentials.trim())
+                .ok()
+        })
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+
+    if let Some(basic) = basic {
+        let (client_id, secret) = basic.split_once(':')?;
+        return Some((client_id.to_string(), Some(secret.to_string())));
+    }
+
+    let client_id = form.get("client_id").filter(|id| !id.is_empty())?;
+    let secret = form
+        .get("client_secret")
+        .filter(|secret| !secret.is_empty())
+        .cloned();
+
+    Some((client_id.clone(), secret))
+}
+
+/// The claims about a user that the granted scopes release.
+pub fn user_info(user: &User, scopes: &[String]) -> OidcUserInfo {
+    let has = |scope: &str| scopes.iter().any(|granted| granted == scope);
+    let email = has("email");
+    let profile = has("profile");
+
+    OidcUserInfo {
+        sub: user.id.to_string(),
+        email: email.then(|| user.email.clone()),
+        email_verified: email.then(|| user.email_verified_at.is_some()),
+        name: profile.then(|| user.display_name.clone()).flatten(),
+        preferred_username: profile.then(|| user.username.clone()),
+        picture: profile.then(|| user.avatar_url.clone()).flatten(),
+        locale: profile.then(|| user.locale.clone()).flatten(),
+        zoneinfo: profile.then(|| user.timezone.clone()).flatten(),
+    }
+}
+
+/// The discovery document served at `/.well-known/openid-configuration`. ID tokens are
+/// signed with the active key, so that is the only algorithm advertised.
+pub fn discovery_document() -> Result<Value> {
+    let base = app_url();
+    let algorithm = Keyring::from_env()?.active.algorithm;
+
+    Ok(json!({
+        "issuer": jwt_issuer(),
+        "authorization_endpoint": format!("{}/api/oidc/authorize", base),
+        "token_endpoint": format!("{}/api/oidc/token", base),
+        "userinfo_endpoint": format!("{}/api/oidc/userinfo", base),
+        "jwks_uri": format!("{}/.well-known/jwks.json", base),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": [algorithm],
+        "scopes_supported": SUPPORTED_SCOPES,
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+        "claims_supported": [
+            "sub", "email", "email_verified", "name", "preferred_username", "picture", "locale",
+            "zoneinfo", "auth_time", "nonce",
+        ],
+    }))
+}
+
+/// The sign-in form shown at the authorization endpoint. It posts back to the same URL with
+/// the authorization request in hidden fields.
+pub fn render_signin_page(
+    request: &AuthorizationRequest,
+    client_name: &str,
+    email: &str,
+    error: Option<&str>,
+) -> String {
+    let hidden = [
+        ("client_id", request.client_id.as_str()),
+        ("redirect_uri", request.redirect_uri.as_str()),
+        ("response_type", request.response_type.as_str()),
+        ("scope", request.scope.as_str()),
+        ("state", request.state.as_deref().unwrap_or_default()),
+        ("nonce", request.nonce.as_deref().unwrap_or_default()),
+        ("code_challenge", request.code_challenge.as_str()),
+        (
+            "code_challenge_method",
+            request.code_challenge_method.as_str(),
+        ),
+    ]
+    .iter()
+    .map(|(name, value)| {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            name,
+            escape_html(value)
+        )
+    })
+    .collect::<Vec<_>>()
+    .join("\n      ");
+
+    let error = error
+        .map(|message| format!(r#"<p class="error">{}</p>"#, escape_html(message)))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Sign in to {client}</title>
+    <style>
+      body {{ font-family: system-ui, sans-serif; max-width: 22rem; margin: 4rem auto; padding: 0 1rem; }}
+      label {{ display: block; margin-top: 1rem; }}
+      input:not([type=hidden]) {{ width: 100%; padding: 0.5rem; box-sizing: border-box; }}
+      button {{ margin-top: 1.5rem; width: 100%; padding: 0.6rem; }}
+      .error {{ color: #b00020; }}
+    </style>
+  </head>
+  <body>
+    <h1>Sign in to {client}</h1>
+    {error}
+    <form method="post">
+      {hidden}
+      <label>Email <input type="email" name="email" value="{email}" autocomplete="username" required autofocus></label>
+      <label>Password <input type="password" name="password" autocomplete="current-password" required></label>
+      <label>Two-factor code, if enabled <input type="text" name="code" autocomplete="one-time-code"></label>
+      <button type="submit">Sign in</button>
+    </form>
+  </body>
+</html>
+"#,
+        client = escape_html(client_name),
+        error = error,
+        hidden = hidden,
+        email = escape_html(email),
+    )
+}
+
+/// A page telling the user the authorization request can't be processed.
+pub fn render_error_page(message: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <title>Sign-in failed</title>
+  </head>
+  <body>
+    <h1>Sign-in failed</h1>
+    <p>{}</p>
+  </body>
+</html>
+"#,
+        escape_html(message)
+    )
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Checks that a redirect URI is absolute, without a fragment, and uses HTTPS unless it
+/// points at the local machine.
+fn validate_redirect_uri(redirect_uri: &str) -> Result<()> {
+    let url = url::Url::parse(redirect_uri)
+        .map_err(|_| anyhow!("Invalid redirect URI: {}", redirect_uri))?;
+
+    if url.fragment().is_some() {
+        return Err(anyhow!(
+            "Redirect URI must not have a fragment: {}",
+            redirect_uri
+        ));
+    }
+
+    let local = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    if url.scheme() != "https" && !(url.scheme() == "http" && local) {
+        return Err(anyhow!(
+            "Redirect URI must use https unless it is on localhost: {}",
+            redirect_uri
+        ));
+    }
+
+    Ok(())
+}
+
+/// Registered clients and the codes and tokens issued to them, for this backend acting as an
+/// OpenID Connect provider.
+pub struct OidcProviderRepository {
+    pool: PgPool,
+}
+
+impl OidcProviderRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Registers a client and returns it with its secret, if it is confidential. Only a hash
+    /// of the secret is stored.
+    pub async fn create_client(
+        &self,
+        name: &str,
+        redirect_uris: &[String],
+        confidential: bool,
+    ) -> Result<(OidcClient, Option<String>)> {
+        if name.trim().is_empty() {
+            return Err(anyhow!("Client name is required"));
+        }
+        if redirect_uris.is_empty() {
+            return Err(anyhow!("At least one redirect URI is required"));
+        }
+        for redirect_uri in redirect_uris {
+            validate_redirect_uri(redirect_uri)?;
+        }
+
+        let client_secret = confidential.then(generate_token);
+
+        let client = sqlx::query_as::<_, OidcClient>(
+            r#"
+            INSERT INTO oidc_clients (id, client_id, name, client_secret_hash, redirect_uris, created_at)
+            VALUES ($1, $2, $3, $4, $5, NOW())
+            RETURNING client_id, name, redirect_uris, client_secret_hash IS NOT NULL AS confidential,
+                created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(Uuid::new_v4().simple().to_string())
+        .bind(name.trim())
+        .bind(client_secret.as_deref().map(hash_token))
+        .bind(redirect_uris)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((client, client_secret))
+    }
+
+    pub async fn list_clients(&self) -> Result<Vec<OidcClient>> {
+        let clients = sqlx::query_as::<_, OidcClient>(
+            r#"
+            SELECT client_id, name, redirect_uris, client_secret_hash IS NOT NULL AS confidential,
+                created_at
+            FROM oidc_clients
+            WHERE revoked_at IS NULL
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(clients)
+    }
+
+    /// Revokes a client along with the access tokens issued to it. Returns whether an active
+    /// client was found.
+    pub async fn revoke_client(&self, client_id: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE oidc_clients SET revoked_at = NOW() WHERE client_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(client_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE oidc_access_tokens SET revoked_at = NOW() WHERE client_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(client_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Finds an active client by its id.
+    pub async fn find_client(&self, client_id: &str) -> Result<Option<OidcClient>> {
+        let client = sqlx::query_as::<_, OidcClient>(
+            r#"
+            SELECT client_id, name, redirect_uris, client_secret_hash IS NOT NULL AS confidential,
+                created_at
+            FROM oidc_clients
+            WHERE client_id = $1 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(client)
+    }
+
+    /// Authenticates a client at the token endpoint. Confidential clients must send their
+    /// secret; public clients are identified by their id alone.
+    pub async fn authenticate_client(
+        &self,
+        client_id: &str,
+        client_secret: Option<&str>,
+    ) -> Result<()> {
+        let row = sqlx::query(
+            "SELECT client_secret_hash FROM oidc_clients WHERE client_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("Unknown client"))?;
+
+        let secret_hash: Option<String> = row.get("client_secret_hash");
+        match (secret_hash, client_secret) {
+            (None, _) => Ok(()),
+            (Some(secret_hash), Some(secret)) if hash_token(secret) == secret_hash => Ok(()),
+            (Some(_), _) => Err(anyhow!("Invalid client credentials")),
+        }
+    }
+
+    /// Issues an authorization code for a user who just signed in at the authorization
+    /// endpoint. The code itself is only returned, never stored.
+    pub async fn create_authorization_code(
+        &self,
+        request: &AuthorizationRequest,
+        user_id: &Uuid,
+        scopes: &[String],
+    ) -> Result<String> {
+        let code = generate_token();
+
+        sqlx::query(
+            r#"
+            INSERT INTO oidc_authorization_codes
+                (id, code_hash, client_id, user_id, redirect_uri, scopes, nonce, code_challenge,
+                 auth_time, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW(), $9, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(hash_token(&code))
+        .bind(&request.client_id)
+        .bind(user_id)
+        .bind(&request.redirect_uri)
+        .bind(scopes)
+        .bind(&request.nonce)
+        .bind(&request.code_challenge)
+        .bind(Utc::now() + Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS))
+        .execute(&self.pool)
+        .await?;
+
+        // Codes that were never redeemed are cleaned up whenever a new one is issued
+        sqlx::query("DELETE FROM oidc_authorization_codes WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(code)
+    }
+
+    /// Redeems an authorization code. The code is consumed even if the checks fail, so it
+    /// can only ever be tried once.
+    pub async fn redeem_authorization_code(
+        &self,
+        client_id: &str,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<AuthorizationGrant> {
+        let row = sqlx::query(
+            r#"
+            DELETE FROM oidc_authorization_codes
+            WHERE code_hash = $1 AND expires_at > NOW()
+            RETURNING client_id, user_id, redirect_uri, scopes, nonce, code_challenge, auth_time
+            "#,
+        )
+        .bind(hash_token(code))
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("Invalid or expired authorization code"))?;
+
+        let code_client_id: String = row.get("client_id");
+        let code_redirect_uri: String = row.get("redirect_uri");
+        let code_challenge: String = row.get("code_challenge");
+
+        if code_client_id != client_id {
+            return Err(anyhow!("Authorization code was issued to another client"));
+        }
+        if code_redirect_uri != redirect_uri {
+            return Err(anyhow!(
+                "Redirect URI does not match the authorization request"
+            ));
+        }
+        if pkce_challenge(code_verifier) != code_challenge {
+            return Err(anyhow!("Invalid code verifier"));
+        }
+
+        Ok(AuthorizationGrant {
+            user_id: row.get("user_id"),
+            scopes: row.get("scopes"),
+            nonce: row.get("nonce"),
+            auth_time: row.get("auth_time"),
+        })
+    }
+
+    /// Issues an opaque access token for the userinfo endpoint, valid as long as one of our
+    /// own access tokens.
+    pub async fn create_access_token(
+        &self,
+        client_id: &str,
+        user_id: &Uuid,
+        scopes: &[String],
+    ) -> Result<String> {
+        let token = generate_token();
+
+        sqlx::query(
+            r#"
+            INSERT INTO oidc_access_tokens (id, token_hash, client_id, user_id, scopes, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(hash_token(&token))
+        .bind(client_id)
+        .bind(user_id)
+        .bind(scopes)
+        .bind(Utc::now() + access_token_ttl())
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("DELETE FROM oidc_access_tokens WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(token)
+    }
+
+    /// Looks up an access token and returns the user it was issued for and its scopes.
+    pub async fn find_access_token(&self, token: &str) -> Result<Option<(Uuid, Vec<String>)>> {
+        let row = sqlx::query(
+            r#"
+            SELECT oidc_access_tokens.user_id, oidc_access_tokens.scopes
+            FROM oidc_access_tokens
+            JOIN oidc_clients ON oidc_clients.client_id = oidc_access_tokens.client_id
+            JOIN users ON users.id = oidc_access_tokens.user_id
+            WHERE oidc_access_tokens.token_hash = $1
+            AND oidc_access_tokens.revoked_at IS NULL
+            AND oidc_access_tokens.expires_at > NOW()
+            AND oidc_clients.revoked_at IS NULL
+            AND users.deleted_at IS NULL
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| (row.get("user_id"), row.get("scopes"))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REDIRECT_URI: &str = "https://app.example.com/callback";
+
+    fn client() -> OidcClient {
+        OidcClient {
+            client_id: "app".to_string(),
+            name: "App".to_string(),
+            redirect_uris: vec![REDIRECT_URI.to_string()],
+            confidential: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn request(query: &str) -> AuthorizationRequest {
+        let base = format!(
+            "client_id=app&redirect_uri={}&response_type=code&scope=openid%20email\
+             &code_challenge=abc&code_challenge_method=S256",
+            url::form_urlencoded::byte_serialize(REDIRECT_URI.as_bytes()).collect::<String>()
+        );
+        let mut params = parse_form(base.as_bytes());
+        params.extend(parse_form(query.as_bytes()));
+        AuthorizationRequest::from_params(&params)
+    }
+
+    fn redirect_error(result: Result<Vec<String>, AuthorizationError>) -> Option<&'static str> {
+        match result {
+            Err(AuthorizationError::Redirect { error, .. }) => Some(error),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn accepts_a_valid_request_and_keeps_supported_scopes() {
+        let scopes = request("scope=openid%20email%20offline_access%20profile")
+            .validate(Some(&client()))
+            .ok();
+
+        assert_eq!(
+            scopes,
+            Some(vec![
+                "openid".to_string(),
+                "email".to_string(),
+                "profile".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn shows_an_error_instead_of_redirecting_for_untrusted_clients() {
+        assert!(matches!(
+            request("").validate(None),
+            Err(AuthorizationError::InvalidClient(_))
+        ));
+        for redirect_uri in [
+            "https://app.example.com/callback/evil",
+            "https://app.example.com/callbac",
+            "https://evil.example.com/callback",
+            "",
+        ] {
+            let query = format!(
+                "redirect_uri={}",
+                url::form_urlencoded::byte_serialize(redirect_uri.as_bytes()).collect::<String>()
+            );
+            assert!(
+                matches!(
+                    request(&query).validate(Some(&client())),
+                    Err(AuthorizationError::InvalidClient(_))
+                ),
+                "{}",
+                redirect_uri
+            );
+        }
+    }
+
+    #[test]
+    fn redirects_with_an_error_for_unsupported_response_types() {
+        for response_type in ["token", "id_token", "code id_token", ""] {
+            let query = format!(
+                "response_type={}",
+                url::form_urlencoded::byte_serialize(response_type.as_bytes()).collect::<String>()
+            );
+            assert_eq!(
+                redirect_error(request(&query).validate(Some(&client()))),
+                Some("unsupported_response_type"),
+                "{}",
+                response_type
+            );
+        }
+    }
+
+    #[test]
+    fn requires_the_openid_scope() {
+        for scope in ["", "email%20profile", "openidx", "OPENID"] {
+            assert_eq!(
+                redirect_error(request(&format!("scope={}", scope)).validate(Some(&client()))),
+                Some("invalid_scope"),
+                "{}",
+                scope
+            );
+        }
+    }
+
+    #[test]
+    fn requires_pkce_with_s256() {
+        for query in [
+            "code_challenge=",
+            "code_challenge_method=plain",
+            "code_challenge_method=",
+            "code_challenge_method=s256",
+        ] {
+            assert_eq!(
+                redirect_error(request(query).validate(Some(&client()))),
+                Some("invalid_request"),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn redirect_url_adds_parameters_and_state() {
+        let url = request("state=xyz%20123")
+            .redirect_url(&[("code", "c0de")])
+            .unwrap();
+
+        assert_eq!(url, format!("{}?code=c0de&state=xyz+123", REDIRECT_URI));
+        assert_eq!(
+            request("")
+                .redirect_url(&[("error", "access_denied")])
+                .unwrap(),
+            format!("{}?error=access_denied", REDIRECT_URI)
+        );
+    }
+}
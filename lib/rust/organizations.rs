@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Result};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::{Claims, Organization, OrganizationMember, UserRepository};
+
+/// Role given to whoever creates an organization.
+pub const OWNER_ROLE: &str = "owner";
+
+/// Role given to invited members unless the invitation names another.
+pub const MEMBER_ROLE: &str = "member";
+
+/// Roles that can be held within an organization, stored in `organization_roles`. Global
+/// roles such as `admin` live in `roles` and are granted through `RoleRepository` instead.
+pub const ORGANIZATION_ROLES: [&str; 2] = [OWNER_ROLE, MEMBER_ROLE];
+
+/// The organization a request is scoped to, taken from the active-org claim.
+///
+/// Tenant-scoped repositories take a `Tenant` and only ever read or write its rows.
+#[derive(Debug, Clone, Copy)]
+pub struct Tenant {
+    pub organization_id: Uuid,
+}
+
+impl Tenant {
+    pub fn from_claims(claims: &Claims) -> Result<Self> {
+        let organization_id = claims
+            .org
+            .as_deref()
+            .ok_or_else(|| anyhow!("No active organization"))?;
+
+        Ok(Self {
+            organization_id: Uuid::parse_str(organization_id)?,
+        })
+    }
+}
+
+pub struct OrganizationRepository {
+    pool: PgPool,
+}
+
+impl OrganizationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates an organization with the user as its owner.
+    pub async fn create_organization(&self, name: &str, owner_id: &Uuid) -> Result<Organization> {
+        let mut tx = self.pool.begin().await?;
+
+        let organization_id = Uuid::new_v4();
+        let row = sqlx::query(
+            r#"
+            INSERT INTO organizations (id, name, created_by, created_at, updated_at)
+            VALUES ($1, $2, $3, NOW(), NOW())
+            RETURNING created_at
+            "#,
+        )
+        .bind(organization_id)
+        .bind(name)
+        .bind(owner_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO organization_members (organization_id, user_id, role_id, created_at)
+            SELECT $1, $2, id, NOW() FROM organization_roles WHERE name = $3
+            "#,
+        )
+        .bind(organization_id)
+        .bind(owner_id)
+        .bind(OWNER_ROLE)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Organization {
+            id: organization_id,
+            name: name.to_string(),
+            role: OWNER_ROLE.to_string(),
+            created_at: row.get("created_at"),
+        })
+    }
+
+    /// Lists the organizations the user belongs to, with their role in each.
+    pub async fn list_user_organizations(&self, user_id: &Uuid) -> Result<Vec<Organization>> {
+        let organizations = sqlx::query_as::<_, Organization>(
+            r#"
+            SELECT organizations.id, organizations.name, organization_roles.name AS role, organizations.created_at
+            FROM organization_members
+            JOIN organizations ON organizations.id = organization_members.organization_id
+            JOIN organization_roles ON organization_roles.id = organization_members.role_id
+            WHERE organization_members.user_id = $1
+            ORDER BY organization_members.created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(organizations)
+    }
+
+    /// The organization a new sign-in starts in: the first one the user joined.
+    pub async fn default_organization(&self, user_id: &Uuid) -> Result<Option<Uuid>> {
+        let row = sqlx::query(
+            r#"
+            SELECT organization_id FROM organization_members
+            WHERE user_id = $1
+            ORDER BY created_at
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.get("organization_id")))
+    }
+
+    pub async fn get_organization_name(&self, organization_id: &Uuid) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT name FROM organizations WHERE id = $1")
+            .bind(organization_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.get("name")))
+    }
+
+    pub async fn is_member(&self, organization_id: &Uuid, user_id: &Uuid) -> Result<bool> {
+        let row = sqlx::query(
+            "SELECT 1 AS member FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.is_some())
+    }
+}
+
+impl UserRepository {
+    /// Lists the users in the tenant's organization; users outside it are never returned.
+    pub async fn list_organization_members(
+        &self,
+        tenant: &Tenant,
+    ) -> Result<Vec<OrganizationMember>> {
+        let members = sqlx::query_as::<_, OrganizationMember>(
+            r#"
+            SELECT users.id AS user_id, users.email, users.username, organization_roles.name AS role,
+                organization_members.created_at AS joined_at
+            FROM organization_members
+            JOIN users ON users.id = organization_members.user_id
+            JOIN organization_roles ON organization_roles.id = organization_members.role_id
+            WHERE organization_members.organization_id = $1
+            ORDER BY organization_members.created_at
+            "#,
+        )
+        .bind(tenant.organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+}
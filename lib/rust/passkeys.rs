@@ -0,0 +1,326 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use serde_json::{json, Value};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::auth::hash_token;
+use crate::webauthn::{
+    decode_base64url, encode_base64url, verify_assertion, verify_registration, ClientData,
+    RelyingParty, SUPPORTED_ALGORITHMS,
+};
+use crate::{Passkey, PasskeyAssertion, PasskeyAttestation, User};
+
+const CHALLENGE_TTL_SECONDS: i64 = 5 * 60;
+const REGISTRATION: &str = "registration";
+const AUTHENTICATION: &str = "authentication";
+
+const DEFAULT_PASSKEY_NAME: &str = "Passkey";
+const MAX_PASSKEY_NAME_LENGTH: usize = 100;
+
+/// Checks the name given to a new passkey, returning the message for a 400 if it is too long.
+pub fn validate_passkey_name(name: Option<&str>) -> Result<(), String> {
+    if name.map_or(0, |name| name.trim().chars().count()) > MAX_PASSKEY_NAME_LENGTH {
+        return Err(format!(
+            "Name must be at most {} characters",
+            MAX_PASSKEY_NAME_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// A passkey sign-in that checked out.
+pub struct PasskeySignin {
+    pub user_id: Uuid,
+    pub audience: String,    // as resolved when the sign-in started
+    pub second_factor: bool, // whether it finished an MFA challenge
+}
+
+pub struct PasskeyRepository {
+    pool: PgPool,
+}
+
+impl PasskeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Options for `navigator.credentials.create()` to register a new passkey for the user.
+    /// The passkey must be discoverable and verify the user, so that it can sign in on its own.
+    pub async fn registration_options(&self, user: &User) -> Result<Value> {
+        let rp = RelyingParty::from_env()?;
+        let challenge = self
+            .create_challenge(REGISTRATION, Some(&user.id), None)
+            .await?;
+        let exclude_credentials = self.credential_descriptors(&user.id).await?;
+
+        Ok(json!({
+            "challenge": challenge,
+            "rp": { "id": rp.id, "name": rp.name },
+            "user": {
+                "id": encode_base64url(user.id.as_bytes()),
+                "name": user.email,
+                "displayName": user.display_name.as_deref().unwrap_or(&user.username),
+            },
+            "pubKeyCredParams": SUPPORTED_ALGORITHMS
+                .iter()
+                .map(|alg| json!({ "type": "public-key", "alg": alg }))
+                .collect::<Vec<_>>(),
+            "excludeCredentials": exclude_credentials,
+            "authenticatorSelection": {
+                "residentKey": "required",
+                "requireResidentKey": true,
+                "userVerification": "required",
+            },
+            "attestation": "none",
+            "timeout": CHALLENGE_TTL_SECONDS * 1000,
+        }))
+    }
+
+    /// Options for `navigator.credentials.get()`. For a second factor only the user's own
+    /// passkeys are allowed; otherwise the browser offers any discoverable passkey for the site.
+    pub async fn authentication_options(
+        &self,
+        mfa_user_id: Option<&Uuid>,
+        audience: &str,
+    ) -> Result<Value> {
+        let rp = RelyingParty::from_env()?;
+        let challenge = self
+            .create_challenge(AUTHENTICATION, mfa_user_id, Some(audience))
+            .await?;
+        let allow_credentials = match mfa_user_id {
+            Some(user_id) => self.credential_descriptors(user_id).await?,
+            None => Vec::new(),
+        };
+
+        Ok(json!({
+            "challenge": challenge,
+            "rpId": rp.id,
+            "allowCredentials": allow_credentials,
+            "userVerification": if mfa_user_id.is_some() { "preferred" } else { "required" },
+            "timeout": CHALLENGE_TTL_SECONDS * 1000,
+        }))
+    }
+
+    /// Verifies a passkey created from `registration_options` and stores it for the user.
+    /// Without a name it is called "Passkey".
+    pub async fn register_passkey(
+        &self,
+        user_id: &Uuid,
+        name: Option<&str>,
+        attestation: &PasskeyAttestation,
+    ) -> Result<Passkey> {
+        let name = name.map(str::trim).unwrap_or_default();
+
+        let rp = RelyingParty::from_env()?;
+        let client_data = ClientData::parse(
+            &rp,
+            &attestation.response.client_data_json,
+            "webauthn.create",
+        )?;
+        let challenge_user_id = self
+            .take_challenge(&client_data.challenge, REGISTRATION)
+            .await?
+            .0;
+        if challenge_user_id.as_ref() != Some(user_id) {
+            return Err(anyhow!("Challenge was issued to another user"));
+        }
+
+        let credential = verify_registration(&rp, &client_data, attestation)?;
+
+        let passkey = sqlx::query_as::<_, Passkey>(
+            r#"
+            INSERT INTO passkeys
+                (id, user_id, credential_id, public_key, sign_count, name, transports, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+            ON CONFLICT (credential_id) DO NOTHING
+            RETURNING id, name, transports, last_used_at, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(&credential.credential_id)
+        .bind(&credential.public_key)
+        .bind(credential.sign_count as i64)
+        .bind(if name.is_empty() {
+            DEFAULT_PASSKEY_NAME
+        } else {
+            name
+        })
+        .bind(&attestation.response.transports)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("Passkey is already registered"))?;
+
+        Ok(passkey)
+    }
+
+    /// Verifies a passkey assertion made for `authentication_options` and returns who signed in.
+    ///
+    /// When the challenge was issued to finish MFA the passkey must belong to that user;
+    /// otherwise it has to have verified the user (PIN or biometrics), since it stands in for
+    /// both the password and the second factor.
+    pub async fn authenticate(&self, assertion: &PasskeyAssertion) -> Result<PasskeySignin> {
+        let rp = RelyingParty::from_env()?;
+        let client_data =
+            ClientData::parse(&rp, &assertion.response.client_data_json, "webauthn.get")?;
+        let (mfa_user_id, audience) = self
+            .take_challenge(&client_data.challenge, AUTHENTICATION)
+            .await?;
+
+        let row = sqlx::query(
+            "SELECT id, user_id, public_key, sign_count FROM passkeys WHERE credential_id = $1",
+        )
+        .bind(assertion.id.trim_end_matches('='))
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("Unknown passkey"))?;
+
+        let passkey_id: Uuid = row.get("id");
+        let user_id: Uuid = row.get("user_id");
+        if mfa_user_id.is_some_and(|mfa_user_id| mfa_user_id != user_id) {
+            return Err(anyhow!("Passkey belongs to another user"));
+        }
+        if let Some(user_handle) = &assertion.response.user_handle {
+            if decode_base64url(user_handle)? != user_id.as_bytes() {
+                return Err(anyhow!("User handle does not match the passkey"));
+            }
+        }
+
+        let public_key: Vec<u8> = row.get("public_key");
+        let stored_sign_count: i64 = row.get("sign_count");
+        let sign_count = verify_assertion(
+            &rp,
+            &client_data,
+            assertion,
+            &public_key,
+            stored_sign_count as u32,
+            mfa_user_id.is_none(),
+        )?;
+
+        // Only moves the counter on from the value that was checked against
+        let result = sqlx::query(
+            r#"
+            UPDATE passkeys SET sign_count = $1, last_used_at = NOW()
+            WHERE id = $2 AND sign_count = $3
+            "#,
+        )
+        .bind(sign_count as i64)
+        .bind(passkey_id)
+        .bind(stored_sign_count)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Passkey was used concurrently"));
+        }
+
+        Ok(PasskeySignin {
+            user_id,
+            audience: audience.ok_or_else(|| anyhow!("Challenge has no audience"))?,
+            second_factor: mfa_user_id.is_some(),
+        })
+    }
+
+    pub async fn list_passkeys(&self, user_id: &Uuid) -> Result<Vec<Passkey>> {
+        let passkeys = sqlx::query_as::<_, Passkey>(
+            r#"
+            SELECT id, name, transports, last_used_at, created_at
+            FROM passkeys WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(passkeys)
+    }
+
+    /// Removes one of the user's passkeys; returns false if they have no such passkey.
+    pub async fn remove_passkey(&self, user_id: &Uuid, passkey_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM passkeys WHERE id = $1 AND user_id = $2")
+            .bind(passkey_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// The user's passkeys as `PublicKeyCredentialDescriptor`s.
+    async fn credential_descriptors(&self, user_id: &Uuid) -> Result<Vec<Value>> {
+        let rows = sqlx::query("SELECT credential_id, transports FROM passkeys WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                json!({
+                    "type": "public-key",
+                    "id": row.get::<String, _>("credential_id"),
+                    "transports": row.get::<Vec<String>, _>("transports"),
+                })
+            })
+            .collect())
+    }
+
+    /// Issues a single-use challenge for a ceremony, clearing out expired ones on the way.
+    async fn create_challenge(
+        &self,
+        purpose: &str,
+        user_id: Option<&Uuid>,
+        audience: Option<&str>,
+    ) -> Result<String> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let challenge = encode_base64url(&bytes);
+
+        sqlx::query("DELETE FROM webauthn_challenges WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO webauthn_challenges
+                (id, challenge_hash, purpose, user_id, audience, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(hash_token(&challenge))
+        .bind(purpose)
+        .bind(user_id)
+        .bind(audience)
+        .bind(Utc::now() + Duration::seconds(CHALLENGE_TTL_SECONDS))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    /// Uses up a challenge that was issued for `purpose` and returns the user and audience
+    /// it was issued with.
+    async fn take_challenge(
+        &self,
+        challenge: &str,
+        purpose: &str,
+    ) -> Result<(Option<Uuid>, Option<String>)> {
+        let row = sqlx::query(
+            r#"
+            DELETE FROM webauthn_challenges
+            WHERE challenge_hash = $1 AND purpose = $2 AND expires_at > NOW()
+            RETURNING user_id, audience
+            "#,
+        )
+        .bind(hash_token(challenge))
+        .bind(purpose)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow!("Invalid or expired challenge"))?;
+
+        Ok((row.get("user_id"), row.get("audience")))
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use std::env;
+
+// OWASP's recommended Argon2id settings: 19 MiB, 2 passes, 1 lane
+const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+
+/// The algorithm new password hashes are created with, set by `PASSWORD_HASH_ALGORITHM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordAlgorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+/// Hashes and verifies passwords according to the current policy.
+///
+/// New hashes use the configured algorithm; Argon2id hashes are stored as PHC strings
+/// (`$argon2id$v=19$m=...,t=...,p=...$salt$hash`) so their parameters travel with them.
+/// Existing bcrypt hashes keep verifying, and `needs_rehash` tells when a stored hash
+/// should be replaced because the policy has moved on.
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordHasher {
+    pub algorithm: PasswordAlgorithm,
+    /// `ARGON2_MEMORY_KIB`
+    pub argon2_memory_kib: u32,
+    /// `ARGON2_ITERATIONS`
+    pub argon2_iterations: u32,
+    /// `ARGON2_PARALLELISM`
+    pub argon2_parallelism: u32,
+    /// `BCRYPT_COST`, only used when bcrypt is the configured algorithm.
+    pub bcrypt_cost: u32,
+}
+
+impl Default for PasswordHasher {
+    fn default() -> Self {
+        Self {
+            algorithm: PasswordAlgorithm::Argon2id,
+            argon2_memory_kib: DEFAULT_ARGON2_MEMORY_KIB,
+            argon2_iterations: DEFAULT_ARGON2_ITERATIONS,
+            argon2_parallelism: DEFAULT_ARGON2_PARALLELISM,
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+        }
+    }
+}
+
+fn u32_from_env(name: &str, default_value: u32) -> u32 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default_value)
+}
+
+impl PasswordHasher {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+
+        let algorithm = match env::var("PASSWORD_HASH_ALGORITHM") {
+            Ok(value) => match value.to_lowercase().as_str() {
+                "argon2id" => PasswordAlgorithm::Argon2id,
+                "bcrypt" => PasswordAlgorithm::Bcrypt,
+                other => return Err(anyhow!("Unsupported PASSWORD_HASH_ALGORITHM: {}", other)),
+            },
+            Err(_) => defaults.algorithm,
+        };
+
+        Ok(Self {
+            algorithm,
+            argon2_memory_kib: u32_from_env("ARGON2_MEMORY_KIB", defaults.argon2_memory_kib),
+            argon2_iterations: u32_from_env("ARGON2_ITERATIONS", defaults.argon2_iterations),
+            argon2_parallelism: u32_from_env("ARGON2_PARALLELISM", defaults.argon2_parallelism),
+            bcrypt_cost: u32_from_env("BCRYPT_COST", defaults.bcrypt_cost),
+        })
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>> {
+        let params = Params::new(
+            self.argon2_memory_kib,
+            self.argon2_iterations,
+            self.argon2_parallelism,
+            None,
+        )
+        .map_err(|e| anyhow!("Invalid Argon2 parameters: {}", e))?;
+
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String> {
+        match self.algorithm {
+            PasswordAlgorithm::Argon2id => {
+                let mut salt = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut salt);
+                let salt = SaltString::encode_b64(&salt)
+                    .map_err(|e| anyhow!("Failed to encode salt: {}", e))?;
+
+                let hash = self
+                    .argon2()?
+                    .hash_password(password.as_bytes(), &salt)
+                    .map_err(|e| anyhow!("Failed to hash password: {}", e))?;
+                Ok(hash.to_string())
+            }
+            PasswordAlgorithm::Bcrypt => Ok(bcrypt::hash(password, self.bcrypt_cost)?),
+        }
+    }
+
+    /// Checks a password against a stored hash of either algorithm.
+    pub fn verify(&self, password: &str, hash: &str) -> Result<bool> {
+        if hash.starts_with("$argon2") {
+            let parsed =
+                PasswordHash::new(hash).map_err(|e| anyhow!("Invalid password hash: {}", e))?;
+
+            // Verification uses the parameters stored in the hash, not the current ones
+            return Ok(Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok());
+        }
+
+        Ok(bcrypt::verify(password, hash)?)
+    }
+
+    /// Whether a stored hash was made with another algorithm or different parameters
+    /// than the current policy, and should be replaced the next time the password is known.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match self.algorithm {
+            PasswordAlgorithm::Argon2id => {
+                let parsed = match PasswordHash::new(hash) {
+                    Ok(parsed) => parsed,
+                    Err(_) => return true,
+                };
+                if parsed.algorithm != Algorithm::Argon2id.ident()
+                    || parsed.version != Some(Version::V0x13.into())
+                {
+                    return true;
+                }
+
+                match Params::try_from(&parsed) {
+                    Ok(params) => {
+                        params.m_cost() != self.argon2_memory_kib
+                            || params.t_cost() != self.argon2_iterations
+                            || params.p_cost() != self.argon2_parallelism
+                    }
+                    Err(_) => true,
+                }
+            }
+            PasswordAlgorithm::Bcrypt => {
+                // bcrypt hashes look like $2b$12$..., with the cost as the second field
+                let cost = hash
+                    .strip_prefix("$2")
+                    .and_then(|rest| rest.split('$').nth(1))
+                    .and_then(|cost| cost.parse::<u32>().ok());
+                cost != Some(self.bcrypt_cost)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small parameters keep the tests fast; the comparisons don't depend on the sizes
+    fn argon2_hasher() -> PasswordHasher {
+        PasswordHasher {
+            argon2_memory_kib: 256,
+            argon2_iterations: 1,
+            argon2_parallelism: 1,
+            ..PasswordHasher::default()
+        }
+    }
+
+    fn bcrypt_hasher() -> PasswordHasher {
+        PasswordHasher {
+            algorithm: PasswordAlgorithm::Bcrypt,
+            bcrypt_cost: 4,
+            ..argon2_hasher()
+        }
+    }
+
+    #[test]
+    fn needs_rehash_is_false_for_a_hash_made_under_the_current_policy() {
+        let hasher = argon2_hasher();
+        let hash = hasher.hash("password").unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=256,t=1,p=1$"));
+        assert!(!hasher.needs_rehash(&hash));
+
+        let hasher = bcrypt_hasher();
+        assert!(!hasher.needs_rehash(&hasher.hash("password").unwrap()));
+    }
+
+    #[test]
+    fn needs_rehash_is_true_when_argon2_parameters_change() {
+        let hash = argon2_hasher().hash("password").unwrap();
+        let changed = [
+            PasswordHasher {
+                argon2_memory_kib: 512,
+                ..argon2_hasher()
+            },
+            PasswordHasher {
+                argon2_iterations: 2,
+                ..argon2_hasher()
+            },
+            PasswordHasher {
+                argon2_parallelism: 2,
+                ..argon2_hasher()
+            },
+        ];
+        for hasher in changed {
+            assert!(hasher.needs_rehash(&hash), "{:?}", hasher);
+        }
+    }
+
+    #[test]
+    fn needs_rehash_is_true_for_other_argon2_variants() {
+        let params = Params::new(256, 1, 1, None).unwrap();
+        let salt = SaltString::encode_b64(&[7u8; 16]).unwrap();
+        let hash = Argon2::new(Algorithm::Argon2i, Version::V0x13, params)
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string();
+        assert!(argon2_hasher().needs_rehash(&hash));
+    }
+
+    #[test]
+    fn needs_rehash_is_true_when_the_algorithm_changes() {
+        let bcrypt_hash = bcrypt_hasher().hash("password").unwrap();
+        let argon2_hash = argon2_hasher().hash("password").unwrap();
+        assert!(argon2_hasher().needs_rehash(&bcrypt_hash));
+        assert!(bcrypt_hasher().needs_rehash(&argon2_hash));
+    }
+
+    #[test]
+    fn needs_rehash_is_true_when_the_bcrypt_cost_changes() {
+        let hash = bcrypt_hasher().hash("password").unwrap();
+        let hasher = PasswordHasher {
+            bcrypt_cost: 5,
+            ..bcrypt_hasher()
+        };
+        assert!(hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn needs_rehash_is_true_for_unreadable_hashes() {
+        for hash in ["", "not a hash", "$argon2id$v=19$garbage", "$2b$xx$"] {
+            assert!(argon2_hasher().needs_rehash(hash), "{:?}", hash);
+            assert!(bcrypt_hasher().needs_rehash(hash), "{:?}", hash);
+        }
+    }
+
+    #[test]
+    fn verify_accepts_hashes_of_either_algorithm() {
+        for hasher in [argon2_hasher(), bcrypt_hasher()] {
+            let hash = hasher.hash("password").unwrap();
+            assert!(argon2_hasher().verify("password", &hash).unwrap());
+            assert!(!argon2_hasher().verify("Password", &hash).unwrap());
+        }
+    }
+}
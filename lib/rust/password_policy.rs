@@ -0,0 +1,270 @@
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::PasswordViolation;
+
+const DEFAULT_MIN_LENGTH: usize = 8;
+const DEFAULT_MIN_STRENGTH: u8 = 2;
+
+/// SHA-1 hashes of passwords seen in breaches, bundled so the check works offline.
+const BREACHED_PASSWORDS: &str = include_str!("data/breached-passwords.txt");
+
+/// Rules a new password has to pass, configured per deployment.
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordPolicy {
+    /// `PASSWORD_MIN_LENGTH`, counted in characters.
+    pub min_length: usize,
+    /// `PASSWORD_MIN_STRENGTH`, on the 0-4 scale of `estimate_strength`.
+    pub min_strength: u8,
+    /// `PASSWORD_CHECK_BREACHED`, whether to reject passwords on the bundled breach list.
+    pub check_breached: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: DEFAULT_MIN_LENGTH,
+            min_strength: DEFAULT_MIN_STRENGTH,
+            check_breached: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            min_length: env::var("PASSWORD_MIN_LENGTH")
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|length| *length > 0)
+                .unwrap_or(defaults.min_length),
+            min_strength: env::var("PASSWORD_MIN_STRENGTH")
+                .ok()
+                .and_then(|value| value.parse::<u8>().ok())
+                .map(|strength| strength.min(4))
+                .unwrap_or(defaults.min_strength),
+            check_breached: env::var("PASSWORD_CHECK_BREACHED")
+                .map(|value| value != "false" && value != "0")
+                .unwrap_or(defaults.check_breached),
+        }
+    }
+
+    /// Checks a password for the given account and returns every rule it breaks,
+    /// so the client can show them all at once. An empty list means it is accepted.
+    pub fn validate(&self, password: &str, email: &str, username: &str) -> Vec<PasswordViolation> {
+        let mut violations = Vec::new();
+
+        if password.chars().count() < self.min_length {
+            violations.push(violation(
+                "min_length",
+                format!("Password must be at least {} characters", self.min_length),
+            ));
+        }
+
+        let lowercase = password.to_lowercase();
+        let email = email.trim().to_lowercase();
+        let local_part = email.split('@').next().unwrap_or_default();
+        if local_part.chars().count() >= 3 && lowercase.contains(local_part) {
+            violations.push(violation(
+                "contains_email",
+                "Password must not contain your email address".to_string(),
+            ));
+        }
+
+        let username = username.trim().to_lowercase();
+        if username.chars().count() >= 3 && lowercase.contains(&username) {
+            violations.push(violation(
+                "contains_username",
+                "Password must not contain your username".to_string(),
+            ));
+        }
+
+        if self.check_breached && is_breached_password(password) {
+            violations.push(violation(
+                "breached",
+                "This password has appeared in a data breach; choose another".to_string(),
+            ));
+        } else if estimate_strength(password) < self.min_strength {
+            violations.push(violation(
+                "too_weak",
+                "Password is too easy to guess; use a longer mix of words, numbers and symbols"
+                    .to_string(),
+            ));
+        }
+
+        violations
+    }
+}
+
+fn violation(rule: &str, message: String) -> PasswordViolation {
+    PasswordViolation {
+        rule: rule.to_string(),
+        message,
+    }
+}
+
+/// Scores how hard a password is to guess, from 0 (trivial) to 4 (strong).
+///
+/// The estimate is the password's entropy over the character classes it uses, with
+/// repeated characters and runs like `abc` or `321` counting for half.
+pub fn estimate_strength(password: &str) -> u8 {
+    let chars: Vec<char> = password.chars().collect();
+    if chars.is_empty() {
+        return 0;
+    }
+
+    let mut pool = 0u32;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+
+    let mut effective_length = 1.0;
+    for pair in chars.windows(2) {
+        let step = pair[1] as i64 - pair[0] as i64;
+        effective_length += if step.abs() <= 1 { 0.5 } else { 1.0 };
+    }
+
+    let bits = effective_length * (pool.max(1) as f64).log2();
+    match bits {
+        bits if bits < 28.0 => 0,
+        bits if bits < 36.0 => 1,
+        bits if bits < 60.0 => 2,
+        bits if bits < 80.0 => 3,
+        _ => 4,
+    }
+}
+
+/// Whether the password is on the bundled list of common and breached passwords.
+///
+/// The list only holds SHA-1 hashes, the same form the Pwned Passwords service uses.
+pub fn is_breached_password(password: &str) -> bool {
+    static HASHES: OnceLock<HashSet<&'static str>> = OnceLock::new();
+
+    let hashes = HASHES.get_or_init(|| {
+        BREACHED_PASSWORDS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.split(':').next().unwrap_or(line))
+            .collect()
+    });
+
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    hashes.contains(hash.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(violations: &[PasswordViolation]) -> Vec<&str> {
+        violations
+            .iter()
+            .map(|violation| violation.rule.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn estimate_strength_scores_trivial_passwords_zero() {
+        for password in ["", "aaaaaaaa", "abcdefgh", "zyxwvuts", "12345678"] {
+            assert_eq!(estimate_strength(password), 0, "{:?}", password);
+        }
+    }
+
+    #[test]
+    fn estimate_strength_rewards_length_and_variety() {
+        assert_eq!(estimate_strength("password"), 1);
+        assert_eq!(estimate_strength("kT9#vLq2"), 2);
+        assert_eq!(estimate_strength("Tr0ub4dor&3"), 3);
+        assert_eq!(estimate_strength("mellow-Harbor-41"), 4);
+        assert_eq!(estimate_strength("correct horse battery staple"), 4);
+        assert_eq!(estimate_strength("ñandú-Pájaro-9"), 4);
+    }
+
+    #[test]
+    fn validate_accepts_a_strong_password() {
+        let violations =
+            PasswordPolicy::default().validate("mellow-Harbor-41", "jane@example.com", "jane");
+        assert!(violations.is_empty(), "{:?}", rules(&violations));
+    }
+
+    #[test]
+    fn validate_reports_every_broken_rule() {
+        let violations = PasswordPolicy::default().validate("Jane", "jane@example.com", "jane");
+        assert_eq!(
+            rules(&violations),
+            [
+                "min_length",
+                "contains_email",
+                "contains_username",
+                "too_weak"
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_counts_length_in_characters() {
+        let policy = PasswordPolicy {
+            min_length: 8,
+            min_strength: 0,
+            check_breached: false,
+        };
+        assert!(policy.validate("ñandúñan", "a@example.com", "a").is_empty());
+        assert_eq!(
+            rules(&policy.validate("ñandúña", "a@example.com", "a")),
+            ["min_length"]
+        );
+    }
+
+    #[test]
+    fn validate_ignores_short_email_and_username_parts() {
+        let policy = PasswordPolicy::default();
+        assert!(policy
+            .validate("mellow-Harbor-41", "me@example.com", "lo")
+            .is_empty());
+    }
+
+    #[test]
+    fn validate_matches_email_and_username_case_insensitively() {
+        let violations = PasswordPolicy::default().validate(
+            "mellow-HARBOR-41-Jane.Doe",
+            " Jane.Doe@Example.com ",
+            " Harbor ",
+        );
+        assert_eq!(rules(&violations), ["contains_email", "contains_username"]);
+    }
+
+    #[test]
+    fn validate_rejects_breached_passwords_unless_switched_off() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(
+            rules(&policy.validate("password", "a@example.com", "a")),
+            ["breached"]
+        );
+
+        let policy = PasswordPolicy {
+            check_breached: false,
+            ..policy
+        };
+        assert_eq!(
+            rules(&policy.validate("password", "a@example.com", "a")),
+            ["too_weak"]
+        );
+    }
+}
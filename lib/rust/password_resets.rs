@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::auth::{generate_token, hash_password, hash_token, ttl_from_env};
+use crate::revocation::{revoke_all_api_keys_in, revoke_all_user_tokens_in};
+
+const DEFAULT_PASSWORD_RESET_TTL_SECONDS: i64 = 60 * 60;
+
+pub struct PasswordResetRepository {
+    pool: PgPool,
+}
+
+impl PasswordResetRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates a reset token for the user, invalidating any earlier ones that were not used.
+    pub async fn create_reset_token(&self, user_id: &Uuid) -> Result<String> {
+        let reset_token = generate_token();
+        let expires_at = Utc::now()
+            + ttl_from_env(
+                "PASSWORD_RESET_TTL_SECONDS",
+                DEFAULT_PASSWORD_RESET_TTL_SECONDS,
+            );
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO password_reset_tokens (id, user_id, token_hash, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(hash_token(&reset_token))
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(reset_token)
+    }
+
+    /// Returns the user a valid reset token belongs to without using it up, so the new
+    /// password can be checked against the account first.
+    pub async fn find_reset_token_user(&self, reset_token: &str) -> Result<Uuid> {
+        let token_row = sqlx::query(
+            r#"
+            SELECT user_id FROM password_reset_tokens
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            "#,
+        )
+        .bind(hash_token(reset_token))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let row = token_row.ok_or_else(|| anyhow!("Invalid or expired reset token"))?;
+        Ok(row.get("user_id"))
+    }
+
+    /// Uses up a valid reset token, sets the new password, signs the user out everywhere and
+    /// revokes their API keys, all in one transaction. Returns the user, or `None` if the token is invalid or expired.
+    pub async fn reset_password(
+        &self,
+        reset_token: &str,
+        new_password: &str,
+    ) -> Result<Option<Uuid>> {
+        let password_hash = hash_password(new_password)?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let token_row = sqlx::query(
+            r#"
+            UPDATE password_reset_tokens SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+            "#,
+        )
+        .bind(hash_token(reset_token))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let user_id: Uuid = match token_row {
+            Some(row) => row.get("user_id"),
+            None => return Ok(None),
+        };
+
+        sqlx::query("UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2")
+            .bind(&password_hash)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        revoke_all_user_tokens_in(&mut tx, &user_id).await?;
+        revoke_all_api_keys_in(&mut tx, &user_id).await?;
+
+        tx.commit().await?;
+
+        Ok(Some(user_id))
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::organizations::Tenant;
+use crate::{CreateProductRequest, Product};
+
+/// Products of a single organization; every query is filtered by the tenant.
+pub struct ProductRepository {
+    pool: PgPool,
+    tenant: Tenant,
+}
+
+impl ProductRepository {
+    pub fn new(pool: PgPool, tenant: Tenant) -> Self {
+        Self { pool, tenant }
+    }
+
+    pub async fn list_products(&self) -> Result<Vec<Product>> {
+        let products = sqlx::query_as::<_, Product>(
+            r#"
+            SELECT id::text AS id, name, price FROM products
+            WHERE organization_id = $1
+            ORDER BY created_at, name
+            "#,
+        )
+        .bind(self.tenant.organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(products)
+    }
+
+    pub async fn create_product(
+        &self,
+        request: &CreateProductRequest,
+        created_by: &Uuid,
+    ) -> Result<Product> {
+        let product = sqlx::query_as::<_, Product>(
+            r#"
+            INSERT INTO products (id, organization_id, name, price, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, NOW())
+            RETURNING id::text AS id, name, price
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(self.tenant.organization_id)
+        .bind(&request.name)
+        .bind(request.price)
+        .bind(created_by)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(product)
+    }
+}
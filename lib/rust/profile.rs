@@ -0,0 +1,269 @@
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::auth::USER_COLUMNS;
+use crate::{UpdateProfileRequest, User, UserRepository};
+
+const MAX_USERNAME_LENGTH: usize = 100;
+const MAX_DISPLAY_NAME_LENGTH: usize = 100;
+const MAX_AVATAR_URL_LENGTH: usize = 2048;
+
+/// Checks the fields present in a profile update, returning the message for a 400 if one is
+/// unacceptable. Empty optional fields are fine; they clear the value.
+pub fn validate_profile_update(request: &UpdateProfileRequest) -> Result<(), String> {
+    if let Some(username) = &request.username {
+        let username = username.trim();
+        if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+            return Err(format!(
+                "Username must be between 1 and {} characters",
+                MAX_USERNAME_LENGTH
+            ));
+        }
+        if username.chars().any(char::is_whitespace) {
+            return Err("Username must not contain spaces".to_string());
+        }
+    }
+
+    if let Some(display_name) = &request.display_name {
+        if display_name.trim().chars().count() > MAX_DISPLAY_NAME_LENGTH {
+            return Err(format!(
+                "Display name must be at most {} characters",
+                MAX_DISPLAY_NAME_LENGTH
+            ));
+        }
+    }
+
+    if let Some(avatar_url) = request.avatar_url.as_deref().map(str::trim) {
+        if !avatar_url.is_empty() && !is_valid_avatar_url(avatar_url) {
+            return Err("Avatar URL must be an http or https URL".to_string());
+        }
+    }
+
+    if let Some(locale) = request.locale.as_deref().map(str::trim) {
+        if !locale.is_empty() && !is_valid_locale(locale) {
+            return Err("Locale must be a language tag such as \"en\" or \"pt-BR\"".to_string());
+        }
+    }
+
+    if let Some(timezone) = request.timezone.as_deref().map(str::trim) {
+        if !timezone.is_empty() && !is_valid_timezone(timezone) {
+            return Err("Timezone must be an IANA time zone such as \"Europe/Berlin\"".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+fn is_valid_avatar_url(avatar_url: &str) -> bool {
+    avatar_url.len() <= MAX_AVATAR_URL_LENGTH
+        && url::Url::parse(avatar_url)
+            .map(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+            .unwrap_or(false)
+}
+
+/// A loose BCP 47 check: a 2-3 letter language followed by optional subtags.
+fn is_valid_locale(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+    let language = subtags.next().unwrap_or_default();
+
+    locale.len() <= 35
+        && (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Checks the shape of an IANA zone name ("UTC", "America/New_York", "Etc/GMT+5"); there is
+/// no bundled zone database to check that the zone exists.
+fn is_valid_timezone(timezone: &str) -> bool {
+    timezone.len() <= 64
+        && timezone.split('/').all(|part| {
+            part.starts_with(|c: char| c.is_ascii_alphabetic())
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+        })
+}
+
+/// Stores an optional field: left out keeps the current value, empty clears it.
+fn optional_field(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(|value| value.trim().to_string())
+}
+
+impl UserRepository {
+    pub async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(&format!(
+            "SELECT {} FROM users WHERE username = $1",
+            USER_COLUMNS
+        ))
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Applies the fields present in the request, which should have passed
+    /// `validate_profile_update`, and returns the updated user.
+    pub async fn update_profile(
+        &self,
+        user_id: &Uuid,
+        request: &UpdateProfileRequest,
+    ) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(&format!(
+            r#"
+            UPDATE users SET
+                username = COALESCE($2, username),
+                display_name = CASE WHEN $3::TEXT IS NULL THEN display_name ELSE NULLIF($3, '') END,
+                avatar_url = CASE WHEN $4::TEXT IS NULL THEN avatar_url ELSE NULLIF($4, '') END,
+                locale = CASE WHEN $5::TEXT IS NULL THEN locale ELSE NULLIF($5, '') END,
+                timezone = CASE WHEN $6::TEXT IS NULL THEN timezone ELSE NULLIF($6, '') END,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING {}
+            "#,
+            USER_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(request.username.as_deref().map(str::trim))
+        .bind(optional_field(&request.display_name))
+        .bind(optional_field(&request.avatar_url))
+        .bind(optional_field(&request.locale))
+        .bind(optional_field(&request.timezone))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_update() -> UpdateProfileRequest {
+        UpdateProfileRequest {
+            username: None,
+            display_name: None,
+            avatar_url: None,
+            locale: None,
+            timezone: None,
+        }
+    }
+
+    #[test]
+    fn accepts_an_empty_update_and_cleared_fields() {
+        assert_eq!(validate_profile_update(&empty_update()), Ok(()));
+        assert_eq!(
+            validate_profile_update(&UpdateProfileRequest {
+                display_name: Some(String::new()),
+                avatar_url: Some(" ".to_string()),
+                locale: Some(String::new()),
+                timezone: Some(String::new()),
+                ..empty_update()
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn accepts_a_full_valid_update() {
+        let request = UpdateProfileRequest {
+            username: Some(" jane ".to_string()),
+            display_name: Some("Jane Doe".to_string()),
+            avatar_url: Some("https://cdn.example.com/jane.png".to_string()),
+            locale: Some("pt-BR".to_string()),
+            timezone: Some("America/Argentina/Buenos_Aires".to_string()),
+        };
+        assert_eq!(validate_profile_update(&request), Ok(()));
+    }
+
+    #[test]
+    fn checks_usernames() {
+        for username in ["", "   ", "jane doe", &"j".repeat(MAX_USERNAME_LENGTH + 1)] {
+            let request = UpdateProfileRequest {
+                username: Some(username.to_string()),
+                ..empty_update()
+            };
+            assert!(validate_profile_update(&request).is_err(), "{:?}", username);
+        }
+
+        let request = UpdateProfileRequest {
+            username: Some("ñ".repeat(MAX_USERNAME_LENGTH)),
+            ..empty_update()
+        };
+        assert_eq!(validate_profile_update(&request), Ok(()));
+    }
+
+    #[test]
+    fn limits_display_names() {
+        let request = UpdateProfileRequest {
+            display_name: Some("a".repeat(MAX_DISPLAY_NAME_LENGTH + 1)),
+            ..empty_update()
+        };
+        assert!(validate_profile_update(&request).is_err());
+    }
+
+    #[test]
+    fn only_accepts_http_avatar_urls() {
+        let too_long = format!("https://example.com/{}", "a".repeat(MAX_AVATAR_URL_LENGTH));
+        for avatar_url in [
+            "javascript:alert(1)",
+            "data:image/png;base64,AAAA",
+            "ftp://example.com/a.png",
+            "https://",
+            "not a url",
+            too_long.as_str(),
+        ] {
+            let request = UpdateProfileRequest {
+                avatar_url: Some(avatar_url.to_string()),
+                ..empty_update()
+            };
+            assert!(
+                validate_profile_update(&request).is_err(),
+                "{:?}",
+                avatar_url
+            );
+        }
+    }
+
+    #[test]
+    fn checks_locales() {
+        for locale in ["en", "pt-BR", "zh-Hant-TW", "es-419"] {
+            assert!(is_valid_locale(locale), "{:?}", locale);
+        }
+        for locale in [
+            "e",
+            "english",
+            "en_US",
+            "en-",
+            "en--US",
+            "12",
+            "en-toolongsubtag",
+        ] {
+            assert!(!is_valid_locale(locale), "{:?}", locale);
+        }
+    }
+
+    #[test]
+    fn checks_timezones() {
+        for timezone in [
+            "UTC",
+            "Europe/Berlin",
+            "America/Port-au-Prince",
+            "Etc/GMT+5",
+        ] {
+            assert!(is_valid_timezone(timezone), "{:?}", timezone);
+        }
+        for timezone in [
+            "",
+            "Europe/",
+            "/Berlin",
+            "Europe/Berlin Time",
+            "1Europe/Berlin",
+        ] {
+            assert!(!is_valid_timezone(timezone), "{:?}", timezone);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::Result;
+use sqlx::{PgPool, Row};
+use std::env;
+use std::future::Future;
+use vercel_runtime::{Body, Error, Request, Response, StatusCode};
+
+use crate::api_keys::extract_api_key;
+use crate::auth::{extract_bearer_token, hash_token, verify_jwt};
+use crate::{client_ip, create_pool, ApiError};
+
+/// How many requests a caller may make: `limit` per `window_seconds`, refilled continuously.
+///
+/// The limit can be changed per deployment with `RATE_LIMIT_<NAME>`, e.g.
+/// `RATE_LIMIT_AUTH=10/60`, or switched off with `RATE_LIMIT_<NAME>=off`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitPolicy {
+    pub name: &'static str,
+    pub limit: u32,
+    pub window_seconds: u32,
+}
+
+impl RateLimitPolicy {
+    /// General API traffic.
+    pub const DEFAULT: Self = Self {
+        name: "default",
+        limit: 120,
+        window_seconds: 60,
+    };
+
+    /// Unauthenticated endpoints that check credentials or send email.
+    pub const AUTH: Self = Self {
+        name: "auth",
+        limit: 10,
+        window_seconds: 60,
+    };
+
+    /// Emails a caller can have sent to one address, counted per recipient rather than per
+    /// caller by the endpoints that send them.
+    pub const EMAIL: Self = Self {
+        name: "email",
+        limit: 3,
+        window_seconds: 15 * 60,
+    };
+
+    /// The policy after applying `RATE_LIMIT_<NAME>`, or `None` when it is switched off.
+    fn configured(&self) -> Option<Self> {
+        let value = match env::var(format!("RATE_LIMIT_{}", self.name.to_uppercase())) {
+            Ok(value) => value,
+            Err(_) => return Some(*self),
+        };
+
+        if value.trim() == "off" {
+            return None;
+        }
+
+        let parsed = value.split_once('/').and_then(|(limit, window)| {
+            Some((
+                limit.trim().parse::<u32>().ok()?,
+                window.trim().parse::<u32>().ok()?,
+            ))
+        });
+
+        match parsed {
+            Some((limit, window_seconds)) if limit > 0 && window_seconds > 0 => Some(Self {
+                limit,
+                window_seconds,
+                ..*self
+            }),
+            _ => Some(*self),
+        }
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        self.limit as f64 / self.window_seconds as f64
+    }
+}
+
+/// The outcome of taking a token from a bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_seconds: u64,
+    /// Seconds until the next request would be allowed; zero when this one was.
+    pub retry_after_seconds: u64,
+    pub policy: RateLimitPolicy,
+}
+
+impl RateLimitDecision {
+    /// Adds the `RateLimit-*` headers, plus `Retry-After` when the request was refused.
+    pub fn apply_headers(&self, response: &mut Response<Body>) {
+        let headers = response.headers_mut();
+        let values = [
+            ("ratelimit-limit", self.limit.to_string()),
+            ("ratelimit-remaining", self.remaining.to_string()),
+            ("ratelimit-reset", self.reset_seconds.to_string()),
+            (
+                "ratelimit-policy",
+                format!("{};w={}", self.policy.limit, self.policy.window_seconds),
+            ),
+        ];
+
+        for (name, value) in values {
+            if let Ok(value) = value.parse() {
+                headers.insert(name, value);
+            }
+        }
+
+        if !self.allowed {
+            if let Ok(value) = self.retry_after_seconds.to_string().parse() {
+                headers.insert("retry-after", value);
+            }
+        }
+    }
+}
+
+/// Token buckets stored in Postgres, since serverless functions can't keep counters in memory.
+pub struct RateLimiter {
+    pool: PgPool,
+}
+
+impl RateLimiter {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Takes one token from the bucket for `subject` under `policy`.
+    ///
+    /// Returns `None` when the policy has been switched off.
+    pub async fn check(
+        &self,
+        policy: &RateLimitPolicy,
+        subject: &str,
+    ) -> Result<Option<RateLimitDecision>> {
+        let policy = match policy.configured() {
+            Some(policy) => policy,
+            None => return Ok(None),
+        };
+        let capacity = policy.limit as f64;
+        let rate = policy.refill_per_second();
+
+        let row = sqlx::query(
+            r#"
+            INSERT INTO rate_limit_buckets (key, tokens, allowed, updated_at)
+            VALUES ($1, $2 - 1, TRUE, NOW())
+            ON CONFLICT (key) DO UPDATE SET
+                tokens = CASE
+                    WHEN LEAST($2, rate_limit_buckets.tokens
+                        + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at) * $3) >= 1
+                    THEN LEAST($2, rate_limit_buckets.tokens
+                        + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at) * $3) - 1
+                    ELSE LEAST($2, rate_limit_buckets.tokens
+                        + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at) * $3)
+                END,
+                allowed = LEAST($2, rate_limit_buckets.tokens
+                    + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at) * $3) >= 1,
+                updated_at = NOW()
+            RETURNING tokens, allowed
+            "#,
+        )
+        .bind(format!("{}:{}", policy.name, subject))
+        .bind(capacity)
+        .bind(rate)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let tokens: f64 = row.get("tokens");
+        let allowed: bool = row.get("allowed");
+
+        // There is no scheduler in a serverless deployment, so idle buckets
+        // are cleaned up every now and then on the request path.
+        if rand::random::<u8>() < 3 {
+            self.purge_idle().await?;
+        }
+
+        Ok(Some(RateLimitDecision {
+            allowed,
+            limit: policy.limit,
+            remaining: tokens.max(0.0).floor() as u32,
+            reset_seconds: ((capacity - tokens).max(0.0) / rate).ceil() as u64,
+            retry_after_seconds: if allowed {
+                0
+            } else {
+                ((1.0 - tokens).max(0.0) / rate).ceil().max(1.0) as u64
+            },
+            policy,
+        }))
+    }
+
+    /// Deletes buckets that have been idle for a day; they would be full again anyway.
+    pub async fn purge_idle(&self) -> Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM rate_limit_buckets WHERE updated_at < NOW() - INTERVAL '1 day'",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// The subject for `EMAIL`: the recipient's address, hashed so the buckets don't store it.
+pub fn email_rate_limit_subject(email: &str) -> String {
+    format!("to:{}", hash_token(&email.trim().to_lowercase()))
+}
+
+/// Who a request is counted against: the API key it carries, the user of a valid bearer
+/// token, otherwise the client IP. Under `AUTH` it is always the client IP, so a stolen or
+/// freshly minted token can't buy an attacker a bucket of their own.
+///
+/// Neither is checked against the database here; the handler still does that. Keys are
+/// counted by their hash, so a made-up key only ever drains its own bucket.
+pub fn rate_limit_subject(req: &Request, policy: &RateLimitPolicy) -> String {
+    let ip = || format!("ip:{}", client_ip(req));
+
+    if policy.name == RateLimitPolicy::AUTH.name {
+        return ip();
+    }
+
+    if let Some(key) = extract_api_key(req) {
+        return format!("key:{}", hash_token(key));
+    }
+
+    let user_id = req
+        .headers()
+        .get("authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| extract_bearer_token(header).ok())
+        .and_then(|token| verify_jwt(token).ok())
+        .map(|claims| claims.sub);
+
+    match user_id {
+        Some(user_id) => format!("user:{}", user_id),
+        None => ip(),
+    }
+}
+
+/// Runs `handler` behind a rate limit, for use in each function's `main`:
+///
+/// ```ignore
+/// run(|req| rate_limited(req, "auth/me", RateLimitPolicy::DEFAULT, handler)).await
+/// ```
+///
+/// `route` names the endpoint, usually its path under `api/`. Each endpoint has buckets of
+/// its own, so a caller using one of them up doesn't lock them out of the others.
+/// The handler is given the pool the limiter used, so each request connects only once.
+/// Refused requests get a 429 `ApiError`; every response carries the `RateLimit-*` headers.
+/// If the limiter's query fails the request is let through rather than failing.
+pub async fn rate_limited<F, Fut>(
+    req: Request,
+    route: &str,
+    policy: RateLimitPolicy,
+    handler: F,
+) -> Result<Response<Body>, Error>
+where
+    F: FnOnce(Request, PgPool) -> Fut,
+    Fut: Future<Output = Result<Response<Body>, Error>>,
+{
+    dotenvy::dotenv().ok();
+
+    // Connect to database
+    let pool = match create_pool().await {
+        Ok(pool) => pool,
+        Err(_) => {
+            let error = ApiError {
+                message: "Database connection failed".to_string(),
+                code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?);
+        }
+    };
+
+    let subject = format!("{}:{}", route, rate_limit_subject(&req, &policy));
+    let decision = match RateLimiter::new(pool.clone())
+        .check(&policy, &subject)
+        .await
+    {
+        Ok(decision) => decision,
+        Err(e) => {
+            eprintln!("Rate limiter unavailable: {}", e);
+            None
+        }
+    };
+
+    let mut response = match decision {
+        Some(decision) if !decision.allowed => {
+            let error = ApiError {
+                message: "Too many requests".to_string(),
+                code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            };
+            Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("content-type", "application/json")
+                .body(serde_json::to_string(&error)?.into())?
+        }
+        _ => handler(req, pool).await?,
+    };
+
+    if let Some(decision) = decision {
+        decision.apply_headers(&mut response);
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use vercel_runtime::StatusCode;
+
+use crate::{ApiError, Claims};
+
+/// Role given to every new account.
+pub const DEFAULT_ROLE: &str = "member";
+
+pub const PRODUCTS_READ: &str = "products:read";
+pub const PRODUCTS_WRITE: &str = "products:write";
+pub const ROLES_MANAGE: &str = "roles:manage";
+pub const MEMBERS_READ: &str = "members:read";
+pub const MEMBERS_MANAGE: &str = "members:manage";
+
+/// The roles a user holds and the permissions those roles grant, as embedded in access tokens.
+///
+/// Permissions combine the user's global roles with their role in the active organization.
+#[derive(Debug, Default, Clone)]
+pub struct UserAccess {
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+    pub organization_id: Option<Uuid>,
+    pub organization_role: Option<String>,
+}
+
+/// Guard for handlers: succeeds when the token grants `permission`, otherwise returns the
+/// 403 `ApiError` to send back.
+///
+/// Roles are read from the token, so changes take effect at the user's next refresh.
+pub fn require_permission(claims: &Claims, permission: &str) -> Result<(), ApiError> {
+    if claims
+        .permissions
+        .iter()
+        .any(|granted| granted == permission)
+    {
+        return Ok(());
+    }
+
+    Err(ApiError {
+        message: format!("Missing permission: {}", permission),
+        code: StatusCode::FORBIDDEN.as_u16(),
+    })
+}
+
+pub struct RoleRepository {
+    pool: PgPool,
+}
+
+impl RoleRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Loads the user's access, working in `organization_id` if they are still a member of it.
+    pub async fn get_user_access(
+        &self,
+        user_id: &Uuid,
+        organization_id: Option<&Uuid>,
+    ) -> Result<UserAccess> {
+        let roles = sqlx::query(
+            r#"
+            SELECT roles.name FROM user_roles
+            JOIN roles ON roles.id = user_roles.role_id
+            WHERE user_roles.user_id = $1
+            ORDER BY roles.name
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+        let membership = match organization_id {
+            Some(organization_id) => sqlx::query(
+                r#"
+                SELECT organization_members.organization_id, organization_roles.name
+                FROM organization_members
+                JOIN organization_roles ON organization_roles.id = organization_members.role_id
+                WHERE organization_members.organization_id = $1
+                AND organization_members.user_id = $2
+                "#,
+            )
+            .bind(organization_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|row| {
+                (
+                    row.get::<Uuid, _>("organization_id"),
+                    row.get::<String, _>("name"),
+                )
+            }),
+            None => None,
+        };
+
+        let permissions = sqlx::query(
+            r#"
+            SELECT permissions.name FROM user_roles
+            JOIN role_permissions ON role_permissions.role_id = user_roles.role_id
+            JOIN permissions ON permissions.id = role_permissions.permission_id
+            WHERE user_roles.user_id = $1
+            UNION
+            SELECT permissions.name FROM organization_members
+            JOIN organization_role_permissions
+                ON organization_role_permissions.role_id = organization_members.role_id
+            JOIN permissions ON permissions.id = organization_role_permissions.permission_id
+            WHERE organization_members.user_id = $1
+            AND organization_members.organization_id = $2
+            ORDER BY name
+            "#,
+        )
+        .bind(user_id)
+        .bind(
+            membership
+                .as_ref()
+                .map(|(organization_id, _)| *organization_id),
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+        let (organization_id, organization_role) = membership.unzip();
+
+        Ok(UserAccess {
+            roles,
+            permissions,
+            organization_id,
+            organization_role,
+        })
+    }
+
+    pub async fn assign_role(&self, user_id: &Uuid, role: &str) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO user_roles (user_id, role_id, created_at)
+            SELECT $1, id, NOW() FROM roles WHERE name = $2
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 && !self.role_exists(role).await? {
+            return Err(anyhow!("Unknown role: {}", role));
+        }
+
+        Ok(())
+    }
+
+    pub async fn remove_role(&self, user_id: &Uuid, role: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM user_roles
+            WHERE user_id = $1 AND role_id = (SELECT id FROM roles WHERE name = $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn role_exists(&self, role: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT id FROM roles WHERE name = $1")
+            .bind(role)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+}
@@ -0,0 +1,219 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::auth::{generate_token, hash_token, refresh_token_ttl};
+use crate::sessions::SessionRepository;
+
+pub struct RefreshTokenRepository {
+    pool: PgPool,
+}
+
+/// The outcome of a successful rotation: the owner of the presented token and its replacement.
+pub struct RotatedRefreshToken {
+    pub user_id: Uuid,
+    pub session_id: Uuid,
+    pub refresh_token: String,
+    pub audience: Option<String>, // None for tokens issued before audiences were recorded
+    pub organization_id: Option<Uuid>,
+    pub auth_time: Option<usize>, // when the session was signed in to
+}
+
+impl RefreshTokenRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Starts the token family of a new session (see `SessionRepository::create_session`)
+    /// and returns its first refresh token.
+    ///
+    /// The audience and active organization are stored so access tokens minted on refresh
+    /// are for the same app and organization.
+    pub async fn create_refresh_token(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+        audience: &str,
+        organization_id: Option<&Uuid>,
+    ) -> Result<String> {
+        let refresh_token = generate_token();
+        let expires_at = Utc::now() + refresh_token_ttl();
+
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens
+                (id, user_id, family_id, token_hash, audience, organization_id, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(session_id)
+        .bind(hash_token(&refresh_token))
+        .bind(audience)
+        .bind(organization_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(refresh_token)
+    }
+
+    /// Exchanges a refresh token for a new one in the same family.
+    ///
+    /// Every refresh token can be used exactly once. Presenting a token that was already
+    /// rotated means it leaked, so the whole family is revoked and the legitimate holder
+    /// has to sign in again. An account waiting to be deleted can't refresh at all; signing
+    /// in again is what cancels the deletion.
+    pub async fn rotate_refresh_token(&self, refresh_token: &str) -> Result<RotatedRefreshToken> {
+        let mut tx = self.pool.begin().await?;
+
+        let token_row = sqlx::query(
+            r#"
+            SELECT refresh_tokens.id, refresh_tokens.user_id, refresh_tokens.family_id,
+                refresh_tokens.audience, refresh_tokens.organization_id,
+                refresh_tokens.expires_at, refresh_tokens.revoked_at
+            FROM refresh_tokens
+            JOIN users ON users.id = refresh_tokens.user_id
+            WHERE refresh_tokens.token_hash = $1
+            AND users.deleted_at IS NULL
+            FOR UPDATE OF refresh_tokens
+            "#,
+        )
+        .bind(hash_token(refresh_token))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let row = token_row.ok_or_else(|| anyhow!("Invalid refresh token"))?;
+        let token_id: Uuid = row.get("id");
+        let user_id: Uuid = row.get("user_id");
+        let family_id: Uuid = row.get("family_id");
+        let audience: Option<String> = row.get("audience");
+        let organization_id: Option<Uuid> = row.get("organization_id");
+        let expires_at: DateTime<Utc> = row.get("expires_at");
+        let revoked_at: Option<DateTime<Utc>> = row.get("revoked_at");
+
+        if revoked_at.is_some() {
+            sqlx::query(
+                "UPDATE refresh_tokens SET revoked_at = NOW() WHERE family_id = $1 AND revoked_at IS NULL",
+            )
+            .bind(family_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL",
+            )
+            .bind(family_id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            return Err(anyhow!("Refresh token reuse detected"));
+        }
+
+        if expires_at <= Utc::now() {
+            return Err(anyhow!("Refresh token expired"));
+        }
+
+        let new_token = generate_token();
+        let new_token_id = Uuid::new_v4();
+        let new_expires_at = Utc::now() + refresh_token_ttl();
+
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens
+                (id, user_id, family_id, token_hash, audience, organization_id, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+            "#,
+        )
+        .bind(new_token_id)
+        .bind(user_id)
+        .bind(family_id)
+        .bind(hash_token(&new_token))
+        .bind(&audience)
+        .bind(organization_id)
+        .bind(new_expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE refresh_tokens SET revoked_at = NOW(), replaced_by = $1 WHERE id = $2")
+            .bind(new_token_id)
+            .bind(token_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // The session now lasts as long as the new token
+        let signed_in_at: Option<DateTime<Utc>> = sqlx::query_scalar(
+            r#"
+            UPDATE sessions SET last_seen_at = NOW(), expires_at = $1 WHERE id = $2
+            RETURNING created_at
+            "#,
+        )
+        .bind(new_expires_at)
+        .bind(family_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(RotatedRefreshToken {
+            user_id,
+            session_id: family_id,
+            refresh_token: new_token,
+            audience,
+            organization_id,
+            auth_time: signed_in_at.map(|signed_in_at| signed_in_at.timestamp() as usize),
+        })
+    }
+
+    /// Moves the sign-in behind an unused refresh token to another organization, so later
+    /// refreshes keep working in it.
+    pub async fn switch_organization(
+        &self,
+        user_id: &Uuid,
+        refresh_token: &str,
+        organization_id: &Uuid,
+    ) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE refresh_tokens SET organization_id = $1
+            WHERE token_hash = $2 AND user_id = $3 AND revoked_at IS NULL AND expires_at > NOW()
+            "#,
+        )
+        .bind(organization_id)
+        .bind(hash_token(refresh_token))
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Invalid refresh token"));
+        }
+
+        Ok(())
+    }
+
+    /// Revokes every token in the family of the given refresh token, ending that session.
+    pub async fn revoke_refresh_token_family(
+        &self,
+        user_id: &Uuid,
+        refresh_token: &str,
+    ) -> Result<()> {
+        let family_id: Option<Uuid> = sqlx::query_scalar(
+            "SELECT family_id FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2",
+        )
+        .bind(hash_token(refresh_token))
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(family_id) = family_id {
+            SessionRepository::new(self.pool.clone())
+                .revoke_session(user_id, &family_id)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
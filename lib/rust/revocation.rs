@@ -0,0 +1,166 @@
+use anyhow::Result;
+use chrono::DateTime;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+use crate::Claims;
+
+pub struct TokenRevocationRepository {
+    pool: PgPool,
+}
+
+impl TokenRevocationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Revokes a single access token until its natural expiry.
+    pub async fn revoke_token(&self, user_id: &Uuid, claims: &Claims) -> Result<()> {
+        let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_default();
+
+        sqlx::query(
+            r#"
+            INSERT INTO revoked_tokens (jti, user_id, expires_at, revoked_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+        )
+        .bind(&claims.jti)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        // There is no scheduler in a serverless deployment, so expired entries
+        // are cleaned up whenever a new one is written.
+        self.purge_expired().await?;
+
+        Ok(())
+    }
+
+    /// Records a single-use token's `jti` until it expires; returns false if it was already used.
+    pub async fn use_once(&self, jti: &str, user_id: &Uuid, exp: usize) -> Result<bool> {
+        let expires_at = DateTime::from_timestamp(exp as i64, 0).unwrap_or_default();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO revoked_tokens (jti, user_id, expires_at, revoked_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+        )
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        self.purge_expired().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Revokes every access and refresh token the user currently holds.
+    pub async fn revoke_all_user_tokens(&self, user_id: &Uuid) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        revoke_all_user_tokens_in(&mut tx, user_id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Checks a verified token against the revocation list and the user's sign-out-everywhere cutoff.
+    ///
+    /// A token belonging to a session is revoked if the session was ended or started before
+    /// the cutoff; both times come from the database, so they compare at full precision.
+    /// Tokens without a session only carry `iat` in whole seconds and are revoked if they were
+    /// issued in the second of the cutoff or earlier. Tokens minted for an email address the
+    /// user no longer has are treated as revoked too.
+    pub async fn is_revoked(&self, user_id: &Uuid, claims: &Claims) -> Result<bool> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = $1)
+                OR EXISTS (
+                    SELECT 1 FROM users
+                    WHERE id = $2
+                    AND (
+                        email <> $4
+                        OR ($5::uuid IS NULL AND tokens_revoked_before >= to_timestamp($3))
+                    )
+                )
+                OR EXISTS (
+                    SELECT 1 FROM sessions
+                    JOIN users ON users.id = sessions.user_id
+                    WHERE sessions.id = $5
+                    AND (
+                        sessions.revoked_at IS NOT NULL
+                        OR sessions.created_at < users.tokens_revoked_before
+                    )
+                )
+                AS revoked
+            "#,
+        )
+        .bind(&claims.jti)
+        .bind(user_id)
+        .bind(claims.iat as i64)
+        .bind(&claims.email)
+        .bind(claims.session_id())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.get("revoked"))
+    }
+
+    /// Deletes revocation entries for tokens that have expired on their own.
+    pub async fn purge_expired(&self) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Revokes every access and refresh token the user currently holds, as part of a larger
+/// transaction.
+pub(crate) async fn revoke_all_user_tokens_in(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+) -> Result<()> {
+    sqlx::query("UPDATE users SET tokens_revoked_before = NOW() WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        "UPDATE oidc_access_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Revokes all of the user's API keys, as part of a larger transaction.
+pub(crate) async fn revoke_all_api_keys_in(conn: &mut PgConnection, user_id: &Uuid) -> Result<()> {
+    sqlx::query("UPDATE api_keys SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+use vercel_runtime::Request;
+
+use crate::auth::refresh_token_ttl;
+use crate::{client_ip, Claims, ExportedSession, Session};
+
+/// How often using an access token moves its session's `last_seen_at` forward, so verifying
+/// a token doesn't mean a write on every request.
+const LAST_SEEN_INTERVAL_SECONDS: i64 = 60;
+
+impl Claims {
+    /// The session the token belongs to; tokens issued before sessions were recorded have none.
+    pub fn session_id(&self) -> Option<Uuid> {
+        self.sid
+            .as_deref()
+            .and_then(|sid| Uuid::parse_str(sid).ok())
+    }
+}
+
+/// Sign-ins and the devices they were made from. A session lives as long as its refresh
+/// token family, whose `family_id` is the session id, and access tokens carry it as `sid`.
+pub struct SessionRepository {
+    pool: PgPool,
+}
+
+impl SessionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Records a new sign-in from the device that sent the request and returns its id.
+    pub async fn create_session(
+        &self,
+        user_id: &Uuid,
+        audience: &str,
+        req: &Request,
+    ) -> Result<Uuid> {
+        let session_id = Uuid::new_v4();
+        let user_agent = req
+            .headers()
+            .get("user-agent")
+            .and_then(|header| header.to_str().ok());
+
+        sqlx::query(
+            r#"
+            INSERT INTO sessions
+                (id, user_id, audience, user_agent, ip_address, created_at, last_seen_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, NOW(), NOW(), $6)
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .bind(audience)
+        .bind(user_agent)
+        .bind(client_ip(req))
+        .bind(Utc::now() + refresh_token_ttl())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(session_id)
+    }
+
+    /// Notes that an access token of the session was used.
+    pub async fn touch_session(&self, session_id: &Uuid, jti: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE sessions SET last_seen_at = NOW(), access_token_jti = $2
+            WHERE id = $1
+            AND (
+                access_token_jti IS DISTINCT FROM $2
+                OR last_seen_at < NOW() - make_interval(secs => $3)
+            )
+            "#,
+        )
+        .bind(session_id)
+        .bind(jti)
+        .bind(LAST_SEEN_INTERVAL_SECONDS as f64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lists the user's sessions that can still be refreshed, most recently used first.
+    pub async fn list_active_sessions(
+        &self,
+        user_id: &Uuid,
+        current_session_id: Option<&Uuid>,
+    ) -> Result<Vec<Session>> {
+        let sessions = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT id, audience, user_agent, ip_address, created_at, last_seen_at, expires_at,
+                id IS NOT DISTINCT FROM $2 AS current
+            FROM sessions
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY last_seen_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(current_session_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    /// Lists every session the user ever had, for the data export.
+    pub async fn list_user_sessions(&self, user_id: &Uuid) -> Result<Vec<ExportedSession>> {
+        let sessions = sqlx::query_as::<_, ExportedSession>(
+            r#"
+            SELECT id, audience, user_agent, ip_address, created_at, last_seen_at, expires_at,
+                revoked_at
+            FROM sessions
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    /// Ends one of the user's sessions. Its refresh tokens stop working and its access
+    /// tokens are rejected from now on. Returns whether an active session was found.
+    pub async fn revoke_session(&self, user_id: &Uuid, session_id: &Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = NOW() WHERE family_id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Ends every session of the user except the given one and returns how many were ended.
+    pub async fn revoke_other_sessions(
+        &self,
+        user_id: &Uuid,
+        current_session_id: &Uuid,
+    ) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND id <> $2 AND revoked_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(current_session_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND family_id <> $2 AND revoked_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(current_session_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+}
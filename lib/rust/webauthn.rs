@@ -0,0 +1,1154 @@
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::signature::{self, UnparsedPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env;
+
+use crate::{app_url, PasskeyAssertion, PasskeyAttestation};
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+const COSE_ALG_EDDSA: i64 = -8;
+const COSE_ALG_ES256: i64 = -7;
+const COSE_ALG_RS256: i64 = -257;
+
+/// Signature algorithms accepted for passkeys, in order of preference, as COSE identifiers.
+pub const SUPPORTED_ALGORITHMS: [i64; 3] = [COSE_ALG_EDDSA, COSE_ALG_ES256, COSE_ALG_RS256];
+
+/// The site passkeys are registered for. Configured through `WEBAUTHN_RP_ID` (defaults to
+/// the host of `APP_URL`), `WEBAUTHN_RP_NAME` and the comma separated `WEBAUTHN_ORIGINS`
+/// the browser may report (defaults to `APP_URL`).
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origins: Vec<String>,
+}
+
+impl RelyingParty {
+    pub fn from_env() -> Result<Self> {
+        let id = match env::var("WEBAUTHN_RP_ID") {
+            Ok(id) if !id.is_empty() => id,
+            _ => url::Url::parse(&app_url())?
+                .host_str()
+                .ok_or_else(|| anyhow!("APP_URL has no host to use as WEBAUTHN_RP_ID"))?
+                .to_string(),
+        };
+
+        let origins: Vec<String> = env::var("WEBAUTHN_ORIGINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/').to_string())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+
+        Ok(Self {
+            name: env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| id.clone()),
+            origins: if origins.is_empty() {
+                vec![app_url()]
+            } else {
+                origins
+            },
+            id,
+        })
+    }
+}
+
+pub fn encode_base64url(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes base64url as browsers send it, tolerating padding.
+pub fn decode_base64url(value: &str) -> Result<Vec<u8>> {
+    Ok(URL_SAFE_NO_PAD.decode(value.trim_end_matches('='))?)
+}
+
+#[derive(Deserialize)]
+struct ClientDataJson {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(default, rename = "crossOrigin")]
+    cross_origin: bool,
+}
+
+/// The `clientDataJSON` of a ceremony, checked for its type and origin. The challenge is
+/// left to the caller to look up among the ones it issued.
+pub struct ClientData {
+    pub challenge: String,
+    hash: Vec<u8>,
+}
+
+impl ClientData {
+    /// Parses the client data of a `webauthn.create` or `webauthn.get` ceremony.
+    pub fn parse(rp: &RelyingParty, client_data_json: &str, expected_type: &str) -> Result<Self> {
+        let raw = decode_base64url(client_data_json)?;
+        let client_data: ClientDataJson = serde_json::from_slice(&raw)?;
+
+        if client_data.kind != expected_type {
+            return Err(anyhow!("Unexpected ceremony type: {}", client_data.kind));
+        }
+        if !rp.origins.contains(&client_data.origin) || client_data.cross_origin {
+            return Err(anyhow!("Unexpected origin: {}", client_data.origin));
+        }
+
+        Ok(Self {
+            challenge: client_data.challenge,
+            hash: Sha256::digest(&raw).to_vec(),
+        })
+    }
+}
+
+struct AuthenticatorData {
+    user_verified: bool,
+    sign_count: u32,
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>, // credential id and COSE public key
+}
+
+impl AuthenticatorData {
+    /// Parses authenticator data for this relying party, which the user must have been present for.
+    fn parse(rp: &RelyingParty, bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 37 {
+            return Err(anyhow!("Authenticator data is too short"));
+        }
+        if bytes[..32] != Sha256::digest(rp.id.as_bytes())[..] {
+            return Err(anyhow!("Authenticator data is for another relying party"));
+        }
+
+        let flags = bytes[32];
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err(anyhow!("User was not present"));
+        }
+        let sign_count = u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]);
+
+        let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            // AAGUID (16 bytes), credential id length (2 bytes), credential id, COSE key
+            let rest = bytes
+                .get(37 + 16..)
+                .ok_or_else(|| anyhow!("Attested credential data is truncated"))?;
+            if rest.len() < 2 {
+                return Err(anyhow!("Attested credential data is truncated"));
+            }
+            let id_length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            let credential_id = rest
+                .get(2..2 + id_length)
+                .ok_or_else(|| anyhow!("Credential id is truncated"))?
+                .to_vec();
+            let key_bytes = &rest[2 + id_length..];
+            let (_, key_length) = cbor::decode(key_bytes)?;
+
+            Some((credential_id, key_bytes[..key_length].to_vec()))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            user_verified: flags & FLAG_USER_VERIFIED != 0,
+            sign_count,
+            attested_credential,
+        })
+    }
+}
+
+/// A credential public key in COSE form, limited to the algorithms in `SUPPORTED_ALGORITHMS`.
+struct CoseKey {
+    alg: i64,
+    public_key: Vec<u8>, // in the form ring expects for `alg`
+}
+
+impl CoseKey {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let (key, _) = cbor::decode(bytes)?;
+        let alg = key
+            .get_int(3)
+            .and_then(|alg| alg.as_i64())
+            .ok_or_else(|| anyhow!("COSE key has no algorithm"))?;
+        let bytes_param = |label: i64| {
+            key.get_int(label)
+                .and_then(|value| value.as_bytes())
+                .ok_or_else(|| anyhow!("COSE key is missing parameter {}", label))
+        };
+
+        let public_key = match alg {
+            // OKP key on Ed25519: the public key is x
+            COSE_ALG_EDDSA => bytes_param(-2)?.to_vec(),
+            // EC2 key on P-256: an uncompressed point from x and y
+            COSE_ALG_ES256 => [&[0x04][..], bytes_param(-2)?, bytes_param(-3)?].concat(),
+            // RSA key: a DER RSAPublicKey from n and e
+            COSE_ALG_RS256 => rsa_public_key_der(bytes_param(-1)?, bytes_param(-2)?),
+            other => return Err(anyhow!("Unsupported passkey algorithm: {}", other)),
+        };
+
+        Ok(Self { alg, public_key })
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+        let algorithm: &dyn signature::VerificationAlgorithm = match self.alg {
+            COSE_ALG_EDDSA => &signature::ED25519,
+            COSE_ALG_ES256 => &signature::ECDSA_P256_SHA256_ASN1,
+            _ => &signature::RSA_PKCS1_2048_8192_SHA256,
+        };
+
+        UnparsedPublicKey::new(algorithm, &self.public_key)
+            .verify(message, signature)
+            .map_err(|_| anyhow!("Invalid signature"))
+    }
+}
+
+/// DER encoding of an RSAPublicKey (a SEQUENCE of the modulus and exponent INTEGERs).
+fn rsa_public_key_der(n: &[u8], e: &[u8]) -> Vec<u8> {
+    fn der_length(length: usize) -> Vec<u8> {
+        match length {
+            0..=0x7f => vec![length as u8],
+            0x80..=0xff => vec![0x81, length as u8],
+            _ => vec![0x82, (length >> 8) as u8, length as u8],
+        }
+    }
+    fn der_integer(value: &[u8]) -> Vec<u8> {
+        let value = match value.iter().position(|byte| *byte != 0) {
+            Some(start) => &value[start..],
+            None => &[0][..],
+        };
+        // A leading zero keeps the INTEGER positive
+        let padded = [
+            if value[0] & 0x80 != 0 {
+                &[0][..]
+            } else {
+                &[][..]
+            },
+            value,
+        ]
+        .concat();
+        [&[0x02][..], &der_length(padded.len()), &padded].concat()
+    }
+
+    let body = [der_integer(n), der_integer(e)].concat();
+    [&[0x30][..], &der_length(body.len()), &body].concat()
+}
+
+/// A passkey whose registration checked out, ready to be stored.
+pub struct RegisteredCredential {
+    pub credential_id: String, // base64url
+    pub public_key: Vec<u8>,   // COSE key
+    pub sign_count: u32,
+}
+
+/// Verifies the attestation of a new passkey: the authenticator data must be for this relying
+/// party, with the user present and verified, and the attestation statement must be `none`
+/// or `packed` self-attestation. Attestation certificates are not requested, since nothing
+/// here depends on the make of the authenticator.
+pub fn verify_registration(
+    rp: &RelyingParty,
+    client_data: &ClientData,
+    attestation: &PasskeyAttestation,
+) -> Result<RegisteredCredential> {
+    let (object, _) = cbor::decode(&decode_base64url(&attestation.response.attestation_object)?)?;
+    let format = object
+        .get_text("fmt")
+        .and_then(|format| format.as_text())
+        .ok_or_else(|| anyhow!("Attestation has no format"))?;
+    let statement = object
+        .get_text("attStmt")
+        .ok_or_else(|| anyhow!("Attestation has no statement"))?;
+    let auth_data = object
+        .get_text("authData")
+        .and_then(|auth_data| auth_data.as_bytes())
+        .ok_or_else(|| anyhow!("Attestation has no authenticator data"))?;
+
+    let parsed = AuthenticatorData::parse(rp, auth_data)?;
+    if !parsed.user_verified {
+        return Err(anyhow!("User was not verified"));
+    }
+    let (credential_id, public_key) = parsed
+        .attested_credential
+        .ok_or_else(|| anyhow!("Attestation has no credential"))?;
+    if encode_base64url(&credential_id) != attestation.id.trim_end_matches('=') {
+        return Err(anyhow!("Credential id does not match the attestation"));
+    }
+    let key = CoseKey::parse(&public_key)?;
+
+    match format {
+        "none" => {
+            if !statement.is_empty_map() {
+                return Err(anyhow!(
+                    "Attestation statement of format none must be empty"
+                ));
+            }
+        }
+        "packed" => {
+            if statement.get_text("x5c").is_some() {
+                return Err(anyhow!("Attestation certificates are not supported"));
+            }
+            let alg = statement.get_text("alg").and_then(|alg| alg.as_i64());
+            let signature = statement
+                .get_text("sig")
+                .and_then(|sig| sig.as_bytes())
+                .ok_or_else(|| anyhow!("Packed attestation has no signature"))?;
+            if alg != Some(key.alg) {
+                return Err(anyhow!(
+                    "Packed attestation algorithm does not match the key"
+                ));
+            }
+            key.verify(&[auth_data, &client_data.hash].concat(), signature)?;
+        }
+        other => return Err(anyhow!("Unsupported attestation format: {}", other)),
+    }
+
+    Ok(RegisteredCredential {
+        credential_id: encode_base64url(&credential_id),
+        public_key,
+        sign_count: parsed.sign_count,
+    })
+}
+
+/// Verifies an assertion made with a stored passkey and returns its new signature counter.
+///
+/// A counter that didn't move forward means the credential may have been cloned, unless
+/// the authenticator doesn't keep one (both zero), as is usual for synced passkeys.
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    client_data: &ClientData,
+    assertion: &PasskeyAssertion,
+    public_key: &[u8],
+    stored_sign_count: u32,
+    require_user_verification: bool,
+) -> Result<u32> {
+    let auth_data = decode_base64url(&assertion.response.authenticator_data)?;
+    let parsed = AuthenticatorData::parse(rp, &auth_data)?;
+    if require_user_verification && !parsed.user_verified {
+        return Err(anyhow!("User was not verified"));
+    }
+
+    CoseKey::parse(public_key)?.verify(
+        &[&auth_data[..], &client_data.hash].concat(),
+        &decode_base64url(&assertion.response.signature)?,
+    )?;
+
+    if (parsed.sign_count != 0 || stored_sign_count != 0) && parsed.sign_count <= stored_sign_count
+    {
+        return Err(anyhow!("Signature counter did not increase"));
+    }
+
+    Ok(parsed.sign_count)
+}
+
+/// The subset of CBOR (RFC 8949) that WebAuthn uses: integers, byte and text strings, arrays,
+/// maps and simple values, all with definite lengths.
+mod cbor {
+    use anyhow::{anyhow, Result};
+
+    const MAX_DEPTH: usize = 16;
+
+    #[derive(Debug, PartialEq)]
+    pub enum Value {
+        Integer(i128),
+        Bytes(Vec<u8>),
+        Text(String),
+        Array(Vec<Value>),
+        Map(Vec<(Value, Value)>),
+        Bool(bool),
+        Null,
+    }
+
+    impl Value {
+        pub fn as_i64(&self) -> Option<i64> {
+            match self {
+                Value::Integer(value) => i64::try_from(*value).ok(),
+                _ => None,
+            }
+        }
+
+        pub fn as_bytes(&self) -> Option<&[u8]> {
+            match self {
+                Value::Bytes(bytes) => Some(bytes),
+                _ => None,
+            }
+        }
+
+        pub fn as_text(&self) -> Option<&str> {
+            match self {
+                Value::Text(text) => Some(text),
+                _ => None,
+            }
+        }
+
+        pub fn is_empty_map(&self) -> bool {
+            matches!(self, Value::Map(entries) if entries.is_empty())
+        }
+
+        fn get(&self, key: &Value) -> Option<&Value> {
+            match self {
+                Value::Map(entries) => entries
+                    .iter()
+                    .find(|(entry_key, _)| entry_key == key)
+                    .map(|(_, value)| value),
+                _ => None,
+            }
+        }
+
+        pub fn get_text(&self, key: &str) -> Option<&Value> {
+            self.get(&Value::Text(key.to_string()))
+        }
+
+        pub fn get_int(&self, key: i64) -> Option<&Value> {
+            self.get(&Value::Integer(key as i128))
+        }
+    }
+
+    /// Decodes the first item in `bytes` and returns it with the number of bytes it took.
+    pub fn decode(bytes: &[u8]) -> Result<(Value, usize)> {
+        let mut decoder = Decoder { bytes, position: 0 };
+        let value = decoder.value(0)?;
+        Ok((value, decoder.position))
+    }
+
+    struct Decoder<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl Decoder<'_> {
+        fn take(&mut self, length: usize) -> Result<&[u8]> {
+            let end = self
+                .position
+                .checked_add(length)
+                .filter(|end| *end <= self.bytes.len())
+                .ok_or_else(|| anyhow!("CBOR input is truncated"))?;
+            let slice = &self.bytes[self.position..end];
+            self.position = end;
+            Ok(slice)
+        }
+
+        fn argument(&mut self, info: u8) -> Result<u64> {
+            let length = match info {
+                0..=23 => return Ok(info as u64),
+                24 => 1,
+                25 => 2,
+                26 => 4,
+                27 => 8,
+                _ => return Err(anyhow!("Indefinite lengths are not supported")),
+            };
+            Ok(self
+                .take(length)?
+                .iter()
+                .fold(0u64, |value, byte| (value << 8) | *byte as u64))
+        }
+
+        /// A length that cannot exceed what is left of the input.
+        fn length(&mut self, info: u8) -> Result<usize> {
+            let length = self.argument(info)?;
+            if length > (self.bytes.len() - self.position) as u64 {
+                return Err(anyhow!("CBOR length exceeds the input"));
+            }
+            Ok(length as usize)
+        }
+
+        fn value(&mut self, depth: usize) -> Result<Value> {
+            if depth > MAX_DEPTH {
+                return Err(anyhow!("CBOR nesting is too deep"));
+            }
+
+            let initial = self.take(1)?[0];
+            let (major, info) = (initial >> 5, initial & 0x1f);
+
+            match major {
+                0 => Ok(Value::Integer(self.argument(info)? as i128)),
+                1 => Ok(Value::Integer(-1 - self.argument(info)? as i128)),
+                2 => {
+                    let length = self.length(info)?;
+                    Ok(Value::Bytes(self.take(length)?.to_vec()))
+                }
+                3 => {
+                    let length = self.length(info)?;
+                    Ok(Value::Text(String::from_utf8(self.take(length)?.to_vec())?))
+                }
+                4 => {
+                    let count = self.length(info)?;
+                    (0..count)
+                        .map(|_| self.value(depth + 1))
+                        .collect::<Result<_>>()
+                        .map(Value::Array)
+                }
+                5 => {
+                    let count = self.length(info)?;
+                    (0..count)
+                        .map(|_| Ok((self.value(depth + 1)?, self.value(depth + 1)?)))
+                        .collect::<Result<_>>()
+                        .map(Value::Map)
+                }
+                // Tags carry no meaning WebAuthn needs, so only the tagged item is kept
+                6 => {
+                    self.argument(info)?;
+                    self.value(depth + 1)
+                }
+                _ => match info {
+                    20 => Ok(Value::Bool(false)),
+                    21 => Ok(Value::Bool(true)),
+                    22 => Ok(Value::Null),
+                    _ => Err(anyhow!("Unsupported CBOR simple value")),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cbor::Value;
+    use super::*;
+    use crate::{PasskeyAssertionResponse, PasskeyAttestationResponse};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    fn rp() -> RelyingParty {
+        RelyingParty {
+            id: "example.com".to_string(),
+            name: "Example".to_string(),
+            origins: vec!["https://example.com".to_string()],
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Value> {
+        cbor::decode(bytes).map(|(value, _)| value)
+    }
+
+    /// An ES256 COSE key: {1: 2, 3: -7, -1: 1, -2: x, -3: y}.
+    fn es256_cose_key() -> Vec<u8> {
+        [
+            &[0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21, 0x58, 0x20][..],
+            &[0x11; 32],
+            &[0x22, 0x58, 0x20],
+            &[0x22; 32],
+        ]
+        .concat()
+    }
+
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32, rest: &[u8]) -> Vec<u8> {
+        [
+            &Sha256::digest(rp_id.as_bytes())[..],
+            &[flags],
+            &sign_count.to_be_bytes(),
+            rest,
+        ]
+        .concat()
+    }
+
+    /// Attested credential data: an AAGUID, the credential id and its COSE key.
+    fn attested_credential(credential_id: &[u8], cose_key: &[u8]) -> Vec<u8> {
+        [
+            &[0xaa; 16][..],
+            &(credential_id.len() as u16).to_be_bytes(),
+            credential_id,
+            cose_key,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn cbor_decodes_integers_of_every_width() {
+        let cases: [(&[u8], i128); 9] = [
+            (&[0x00], 0),
+            (&[0x17], 23),
+            (&[0x18, 0x18], 24),
+            (&[0x19, 0x01, 0x00], 256),
+            (&[0x1a, 0x00, 0x01, 0x00, 0x00], 65536),
+            (
+                &[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+                u64::MAX as i128,
+            ),
+            (&[0x20], -1),
+            (&[0x26], -7),
+            (&[0x39, 0x01, 0x00], -257),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(
+                decode(bytes).unwrap(),
+                Value::Integer(expected),
+                "{:x?}",
+                bytes
+            );
+        }
+
+        let smallest = decode(&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).unwrap();
+        assert_eq!(smallest, Value::Integer(-1 - u64::MAX as i128));
+        assert_eq!(smallest.as_i64(), None);
+    }
+
+    #[test]
+    fn cbor_decodes_strings_collections_and_simple_values() {
+        assert_eq!(
+            decode(&[0x43, 0x01, 0x02, 0x03]).unwrap(),
+            Value::Bytes(vec![1, 2, 3])
+        );
+        assert_eq!(
+            decode(&[0x63, b'f', b'm', b't']).unwrap(),
+            Value::Text("fmt".to_string())
+        );
+        assert_eq!(
+            decode(&[0x82, 0x01, 0xf5]).unwrap(),
+            Value::Array(vec![Value::Integer(1), Value::Bool(true)])
+        );
+        assert_eq!(decode(&[0xf4]).unwrap(), Value::Bool(false));
+        assert_eq!(decode(&[0xf6]).unwrap(), Value::Null);
+        assert!(decode(&[0xa0]).unwrap().is_empty_map());
+        // A tag is skipped and the tagged item kept
+        assert_eq!(decode(&[0xc2, 0x41, 0x01]).unwrap(), Value::Bytes(vec![1]));
+
+        let map = decode(&[
+            0xa2, 0x63, b'f', b'm', b't', 0x64, b'n', b'o', b'n', b'e', 0x03, 0x26,
+        ])
+        .unwrap();
+        assert_eq!(map.get_text("fmt").and_then(Value::as_text), Some("none"));
+        assert_eq!(map.get_int(3).and_then(Value::as_i64), Some(-7));
+        assert_eq!(map.get_int(1), None);
+        assert_eq!(map.get_text("missing"), None);
+    }
+
+    #[test]
+    fn cbor_reports_the_length_of_the_first_item() {
+        let (value, length) = cbor::decode(&[0x42, 0x01, 0x02, 0xff, 0xff]).unwrap();
+
+        assert_eq!(value, Value::Bytes(vec![1, 2]));
+        assert_eq!(length, 3);
+    }
+
+    #[test]
+    fn cbor_rejects_truncated_input() {
+        let cases: [&[u8]; 9] = [
+            &[],
+            &[0x18],
+            &[0x19, 0x01],
+            &[0x1b, 0x00, 0x00, 0x00],
+            &[0x42, 0x01],
+            &[0x63, b'a', b'b'],
+            &[0x82, 0x01],
+            &[0xa1, 0x01],
+            &[0xc2],
+        ];
+        for bytes in cases {
+            assert!(decode(bytes).is_err(), "{:x?}", bytes);
+        }
+    }
+
+    #[test]
+    fn cbor_rejects_malformed_input() {
+        let cases: [&[u8]; 8] = [
+            // Indefinite lengths
+            &[0x5f, 0x41, 0x01, 0xff],
+            &[0x9f, 0x01, 0xff],
+            &[0xbf, 0x01, 0x02, 0xff],
+            // Reserved additional information
+            &[0x1c],
+            // Text that isn't UTF-8
+            &[0x62, 0xff, 0xfe],
+            // Simple values other than false, true and null, and floats
+            &[0xf7],
+            &[0xf9, 0x3c, 0x00],
+            &[0xff],
+        ];
+        for bytes in cases {
+            assert!(decode(bytes).is_err(), "{:x?}", bytes);
+        }
+    }
+
+    #[test]
+    fn cbor_rejects_lengths_beyond_the_input_without_allocating() {
+        let huge = [0xff; 8];
+        for major in [0x5b, 0x7b, 0x9b, 0xbb] {
+            let bytes = [&[major][..], &huge].concat();
+            assert!(decode(&bytes).is_err(), "{:x?}", bytes);
+        }
+    }
+
+    #[test]
+    fn cbor_limits_nesting() {
+        let nested = |depth: usize| [vec![0x81; depth], vec![0x00]].concat();
+
+        assert!(decode(&nested(16)).is_ok());
+        assert!(decode(&nested(17)).is_err());
+        assert!(decode(&vec![0x81; 10_000]).is_err());
+    }
+
+    #[test]
+    fn rsa_public_key_der_encodes_a_sequence_of_two_integers() {
+        assert_eq!(
+            rsa_public_key_der(&[0x00, 0x00, 0x45], &[0x01, 0x00, 0x01]),
+            [0x30, 0x08, 0x02, 0x01, 0x45, 0x02, 0x03, 0x01, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn rsa_public_key_der_keeps_integers_positive() {
+        assert_eq!(
+            rsa_public_key_der(&[0xc5], &[0x03]),
+            [0x30, 0x07, 0x02, 0x02, 0x00, 0xc5, 0x02, 0x01, 0x03]
+        );
+    }
+
+    #[test]
+    fn rsa_public_key_der_encodes_zero_and_empty_values_as_zero() {
+        assert_eq!(
+            rsa_public_key_der(&[], &[0x00, 0x00]),
+            [0x30, 0x06, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn rsa_public_key_der_uses_long_form_lengths() {
+        // A 1024-bit modulus needs one length byte, a 2048-bit one two
+        let der = rsa_public_key_der(&[0x80; 128], &[0x01, 0x00, 0x01]);
+        assert_eq!(der[..2], [0x30, 0x81]);
+        assert_eq!(der[2] as usize, der.len() - 3);
+        assert_eq!(der[3..6], [0x02, 0x81, 0x81]);
+
+        let der = rsa_public_key_der(&[0xff; 256], &[0x01, 0x00, 0x01]);
+        assert_eq!(der[..4], [0x30, 0x82, 0x01, 0x0a]);
+        assert_eq!(der.len(), 4 + 0x010a);
+        assert_eq!(der[4..9], [0x02, 0x82, 0x01, 0x01, 0x00]);
+        assert_eq!(der[der.len() - 5..], [0x02, 0x03, 0x01, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn authenticator_data_reads_flags_and_counter() {
+        let bytes = authenticator_data(
+            "example.com",
+            FLAG_USER_PRESENT | FLAG_USER_VERIFIED,
+            42,
+            &[],
+        );
+        let data = AuthenticatorData::parse(&rp(), &bytes).unwrap();
+
+        assert!(data.user_verified);
+        assert_eq!(data.sign_count, 42);
+        assert!(data.attested_credential.is_none());
+
+        let bytes = authenticator_data("example.com", FLAG_USER_PRESENT, 0, &[]);
+        assert!(
+            !AuthenticatorData::parse(&rp(), &bytes)
+                .unwrap()
+                .user_verified
+        );
+    }
+
+    #[test]
+    fn authenticator_data_reads_the_attested_credential() {
+        let cose_key = es256_cose_key();
+        // Extension data after the key is left alone
+        let rest = [attested_credential(b"credential", &cose_key), vec![0xa0]].concat();
+        let bytes = authenticator_data(
+            "example.com",
+            FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA,
+            0,
+            &rest,
+        );
+
+        let (credential_id, public_key) = AuthenticatorData::parse(&rp(), &bytes)
+            .unwrap()
+            .attested_credential
+            .unwrap();
+        assert_eq!(credential_id, b"credential");
+        assert_eq!(public_key, cose_key);
+
+        let key = CoseKey::parse(&public_key).unwrap();
+        assert_eq!(key.alg, COSE_ALG_ES256);
+        assert_eq!(
+            key.public_key,
+            [&[0x04][..], &[0x11; 32], &[0x22; 32]].concat()
+        );
+    }
+
+    #[test]
+    fn authenticator_data_rejects_another_relying_party_and_absent_users() {
+        let bytes = authenticator_data("evil.example", FLAG_USER_PRESENT, 0, &[]);
+        assert!(AuthenticatorData::parse(&rp(), &bytes).is_err());
+
+        let bytes = authenticator_data("example.com", FLAG_USER_VERIFIED, 0, &[]);
+        assert!(AuthenticatorData::parse(&rp(), &bytes).is_err());
+    }
+
+    #[test]
+    fn authenticator_data_rejects_truncated_input() {
+        let bytes = authenticator_data("example.com", FLAG_USER_PRESENT, 0, &[]);
+        for length in [0, 1, 32, 33, 36] {
+            assert!(
+                AuthenticatorData::parse(&rp(), &bytes[..length]).is_err(),
+                "{}",
+                length
+            );
+        }
+
+        let flags = FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA;
+        let credential = attested_credential(b"credential", &es256_cose_key());
+        // Cut inside the AAGUID, the id length, the credential id and the key, or before the key
+        for length in [0, 10, 17, 20, 28, credential.len() - 1] {
+            let bytes = authenticator_data("example.com", flags, 0, &credential[..length]);
+            assert!(
+                AuthenticatorData::parse(&rp(), &bytes).is_err(),
+                "{}",
+                length
+            );
+        }
+
+        // A credential id length that runs past the end
+        let rest = [&[0xaa; 16][..], &[0xff, 0xff], b"short"].concat();
+        let bytes = authenticator_data("example.com", flags, 0, &rest);
+        assert!(AuthenticatorData::parse(&rp(), &bytes).is_err());
+    }
+
+    #[test]
+    fn authenticator_data_rejects_a_malformed_key() {
+        let flags = FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA;
+        for key in [&[0xff][..], &[0x5f, 0x41, 0x01, 0xff], &[0xa1, 0x01]] {
+            let bytes = authenticator_data(
+                "example.com",
+                flags,
+                0,
+                &attested_credential(b"credential", key),
+            );
+            assert!(
+                AuthenticatorData::parse(&rp(), &bytes).is_err(),
+                "{:x?}",
+                key
+            );
+        }
+    }
+
+    const CHALLENGE: &str = "challenge";
+
+    fn p256_key() -> EcdsaKeyPair {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap()
+    }
+
+    /// The ES256 COSE key for a P-256 key pair, from the x and y of its uncompressed point.
+    fn cose_key(key_pair: &EcdsaKeyPair) -> Vec<u8> {
+        let point = key_pair.public_key().as_ref();
+        [
+            &[0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21, 0x58, 0x20][..],
+            &point[1..33],
+            &[0x22, 0x58, 0x20],
+            &point[33..],
+        ]
+        .concat()
+    }
+
+    fn client_data_for(kind: &str, challenge: &str) -> (String, ClientData) {
+        let json = serde_json::json!({
+            "type": kind,
+            "challenge": challenge,
+            "origin": "https://example.com",
+        });
+        let encoded = encode_base64url(json.to_string().as_bytes());
+        let parsed = ClientData::parse(&rp(), &encoded, kind).unwrap();
+        (encoded, parsed)
+    }
+
+    /// Signs the authenticator data followed by the client data hash, as an authenticator does.
+    fn sign(key_pair: &EcdsaKeyPair, auth_data: &[u8], client_data: &ClientData) -> Vec<u8> {
+        key_pair
+            .sign(
+                &SystemRandom::new(),
+                &[auth_data, &client_data.hash].concat(),
+            )
+            .unwrap()
+            .as_ref()
+            .to_vec()
+    }
+
+    fn assertion_for(
+        client_data_json: &str,
+        auth_data: &[u8],
+        signature: &[u8],
+    ) -> PasskeyAssertion {
+        PasskeyAssertion {
+            id: encode_base64url(b"credential"),
+            response: PasskeyAssertionResponse {
+                client_data_json: client_data_json.to_string(),
+                authenticator_data: encode_base64url(auth_data),
+                signature: encode_base64url(signature),
+                user_handle: None,
+            },
+        }
+    }
+
+    /// The CBOR head of a byte or text string, for the lengths used here.
+    fn cbor_string(major: u8, bytes: &[u8]) -> Vec<u8> {
+        let head = match bytes.len() {
+            length @ 0..=23 => vec![major | length as u8],
+            length @ 24..=255 => vec![major | 24, length as u8],
+            length => [&[major | 25][..], &(length as u16).to_be_bytes()].concat(),
+        };
+        [head, bytes.to_vec()].concat()
+    }
+
+    fn cbor_text(text: &str) -> Vec<u8> {
+        cbor_string(0x60, text.as_bytes())
+    }
+
+    fn cbor_bytes(bytes: &[u8]) -> Vec<u8> {
+        cbor_string(0x40, bytes)
+    }
+
+    /// A packed attestation statement: {"alg": alg, "sig": signature}.
+    fn packed_statement(alg: u8, signature: &[u8]) -> Vec<u8> {
+        [
+            &[0xa2][..],
+            &cbor_text("alg"),
+            &[alg],
+            &cbor_text("sig"),
+            &cbor_bytes(signature),
+        ]
+        .concat()
+    }
+
+    fn attestation_for(
+        client_data_json: &str,
+        format: &str,
+        statement: &[u8],
+        auth_data: &[u8],
+    ) -> PasskeyAttestation {
+        let object = [
+            &[0xa3][..],
+            &cbor_text("fmt"),
+            &cbor_text(format),
+            &cbor_text("attStmt"),
+            statement,
+            &cbor_text("authData"),
+            &cbor_bytes(auth_data),
+        ]
+        .concat();
+
+        PasskeyAttestation {
+            id: encode_base64url(b"credential"),
+            response: PasskeyAttestationResponse {
+                client_data_json: client_data_json.to_string(),
+                attestation_object: encode_base64url(&object),
+                transports: Vec::new(),
+            },
+        }
+    }
+
+    fn registration_data(key_pair: &EcdsaKeyPair, flags: u8) -> Vec<u8> {
+        authenticator_data(
+            "example.com",
+            flags | FLAG_ATTESTED_CREDENTIAL_DATA,
+            0,
+            &attested_credential(b"credential", &cose_key(key_pair)),
+        )
+    }
+
+    const VERIFIED: u8 = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+
+    #[test]
+    fn verify_assertion_accepts_a_signature_from_the_stored_key() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.get", CHALLENGE);
+        let auth_data = authenticator_data("example.com", VERIFIED, 8, &[]);
+        let assertion = assertion_for(
+            &json,
+            &auth_data,
+            &sign(&key_pair, &auth_data, &client_data),
+        );
+
+        let sign_count = verify_assertion(
+            &rp(),
+            &client_data,
+            &assertion,
+            &cose_key(&key_pair),
+            7,
+            true,
+        )
+        .unwrap();
+        assert_eq!(sign_count, 8);
+    }
+
+    #[test]
+    fn verify_assertion_rejects_a_signature_from_another_key() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.get", CHALLENGE);
+        let auth_data = authenticator_data("example.com", VERIFIED, 0, &[]);
+        let assertion = assertion_for(
+            &json,
+            &auth_data,
+            &sign(&key_pair, &auth_data, &client_data),
+        );
+
+        let other_key = cose_key(&p256_key());
+        assert!(verify_assertion(&rp(), &client_data, &assertion, &other_key, 0, true).is_err());
+    }
+
+    #[test]
+    fn verify_assertion_rejects_a_signature_over_other_data() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.get", CHALLENGE);
+        let auth_data = authenticator_data("example.com", VERIFIED, 0, &[]);
+        let signature = sign(&key_pair, &auth_data, &client_data);
+        let key = cose_key(&key_pair);
+
+        // The counter in the signed data was changed afterwards
+        let tampered = authenticator_data("example.com", VERIFIED, 9, &[]);
+        let assertion = assertion_for(&json, &tampered, &signature);
+        assert!(verify_assertion(&rp(), &client_data, &assertion, &key, 0, true).is_err());
+
+        // The signature is for a different challenge
+        let (_, other_client_data) = client_data_for("webauthn.get", "other-challenge");
+        let assertion = assertion_for(&json, &auth_data, &signature);
+        assert!(verify_assertion(&rp(), &other_client_data, &assertion, &key, 0, true).is_err());
+    }
+
+    #[test]
+    fn verify_assertion_rejects_a_counter_that_did_not_increase() {
+        let key_pair = p256_key();
+        let key = cose_key(&key_pair);
+        let (json, client_data) = client_data_for("webauthn.get", CHALLENGE);
+
+        for (sign_count, stored) in [(5, 5), (4, 5), (0, 5)] {
+            let auth_data = authenticator_data("example.com", VERIFIED, sign_count, &[]);
+            let assertion = assertion_for(
+                &json,
+                &auth_data,
+                &sign(&key_pair, &auth_data, &client_data),
+            );
+            assert!(
+                verify_assertion(&rp(), &client_data, &assertion, &key, stored, true).is_err(),
+                "{} after {}",
+                sign_count,
+                stored
+            );
+        }
+
+        // Authenticators without a counter always report zero
+        let auth_data = authenticator_data("example.com", VERIFIED, 0, &[]);
+        let assertion = assertion_for(
+            &json,
+            &auth_data,
+            &sign(&key_pair, &auth_data, &client_data),
+        );
+        assert_eq!(
+            verify_assertion(&rp(), &client_data, &assertion, &key, 0, true).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn verify_assertion_requires_user_verification_when_asked() {
+        let key_pair = p256_key();
+        let key = cose_key(&key_pair);
+        let (json, client_data) = client_data_for("webauthn.get", CHALLENGE);
+        let auth_data = authenticator_data("example.com", FLAG_USER_PRESENT, 0, &[]);
+        let assertion = assertion_for(
+            &json,
+            &auth_data,
+            &sign(&key_pair, &auth_data, &client_data),
+        );
+
+        assert!(verify_assertion(&rp(), &client_data, &assertion, &key, 0, true).is_err());
+        assert!(verify_assertion(&rp(), &client_data, &assertion, &key, 0, false).is_ok());
+    }
+
+    #[test]
+    fn verify_registration_accepts_packed_self_attestation() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.create", CHALLENGE);
+        let auth_data = registration_data(&key_pair, VERIFIED);
+        let statement = packed_statement(0x26, &sign(&key_pair, &auth_data, &client_data));
+        let attestation = attestation_for(&json, "packed", &statement, &auth_data);
+
+        let credential = verify_registration(&rp(), &client_data, &attestation).unwrap();
+        assert_eq!(credential.credential_id, encode_base64url(b"credential"));
+        assert_eq!(credential.public_key, cose_key(&key_pair));
+        assert_eq!(credential.sign_count, 0);
+    }
+
+    #[test]
+    fn verify_registration_accepts_no_attestation() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.create", CHALLENGE);
+        let auth_data = registration_data(&key_pair, VERIFIED);
+        let attestation = attestation_for(&json, "none", &[0xa0], &auth_data);
+
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_ok());
+
+        let statement = packed_statement(0x26, &[0x00]);
+        let attestation = attestation_for(&json, "none", &statement, &auth_data);
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_err());
+    }
+
+    #[test]
+    fn verify_registration_rejects_a_bad_packed_signature() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.create", CHALLENGE);
+        let auth_data = registration_data(&key_pair, VERIFIED);
+
+        // Signed by a different key than the one being registered
+        let statement = packed_statement(0x26, &sign(&p256_key(), &auth_data, &client_data));
+        let attestation = attestation_for(&json, "packed", &statement, &auth_data);
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_err());
+
+        // Claiming an algorithm other than the key's
+        let statement = packed_statement(0x27, &sign(&key_pair, &auth_data, &client_data));
+        let attestation = attestation_for(&json, "packed", &statement, &auth_data);
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_err());
+    }
+
+    #[test]
+    fn verify_registration_rejects_attestation_certificates() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.create", CHALLENGE);
+        let auth_data = registration_data(&key_pair, VERIFIED);
+        let signature = sign(&key_pair, &auth_data, &client_data);
+        let statement = [
+            &[0xa3][..],
+            &cbor_text("alg"),
+            &[0x26],
+            &cbor_text("sig"),
+            &cbor_bytes(&signature),
+            &cbor_text("x5c"),
+            &[0x81],
+            &cbor_bytes(b"certificate"),
+        ]
+        .concat();
+        let attestation = attestation_for(&json, "packed", &statement, &auth_data);
+
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_err());
+    }
+
+    #[test]
+    fn verify_registration_requires_user_verification() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.create", CHALLENGE);
+        let auth_data = registration_data(&key_pair, FLAG_USER_PRESENT);
+        let statement = packed_statement(0x26, &sign(&key_pair, &auth_data, &client_data));
+        let attestation = attestation_for(&json, "packed", &statement, &auth_data);
+
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_err());
+    }
+
+    #[test]
+    fn verify_registration_rejects_a_mismatched_credential_id() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.create", CHALLENGE);
+        let auth_data = registration_data(&key_pair, VERIFIED);
+        let statement = packed_statement(0x26, &sign(&key_pair, &auth_data, &client_data));
+        let mut attestation = attestation_for(&json, "packed", &statement, &auth_data);
+        attestation.id = encode_base64url(b"another-credential");
+
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_err());
+    }
+
+    #[test]
+    fn verify_registration_rejects_other_formats() {
+        let key_pair = p256_key();
+        let (json, client_data) = client_data_for("webauthn.create", CHALLENGE);
+        let auth_data = registration_data(&key_pair, VERIFIED);
+        let statement = packed_statement(0x26, &sign(&key_pair, &auth_data, &client_data));
+        let attestation = attestation_for(&json, "fido-u2f", &statement, &auth_data);
+
+        assert!(verify_registration(&rp(), &client_data, &attestation).is_err());
+    }
+}
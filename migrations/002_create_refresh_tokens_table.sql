@@ -0,0 +1,24 @@
+-- Create refresh tokens table
+CREATE TABLE
+    IF NOT EXISTS refresh_tokens (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        -- Every token issued by rotating the same original token shares a family
+        family_id UUID NOT NULL,
+        token_hash TEXT UNIQUE NOT NULL,
+        replaced_by UUID REFERENCES refresh_tokens (id) ON DELETE SET NULL,
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            revoked_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+-- Create indexes for better performance
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user_id ON refresh_tokens (user_id);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_family_id ON refresh_tokens (family_id);
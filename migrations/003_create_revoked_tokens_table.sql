@@ -0,0 +1,21 @@
+-- Create revoked tokens table
+CREATE TABLE
+    IF NOT EXISTS revoked_tokens (
+        jti TEXT PRIMARY KEY,
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        -- Rows are only needed until the token would have expired anyway
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            revoked_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_revoked_tokens_expires_at ON revoked_tokens (expires_at);
+
+-- Access tokens issued before this instant are rejected ("sign out everywhere")
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS tokens_revoked_before TIMESTAMP
+WITH
+    TIME ZONE;
@@ -0,0 +1,23 @@
+-- TOTP two-factor authentication
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS totp_secret TEXT,
+ADD COLUMN IF NOT EXISTS totp_enabled_at TIMESTAMP
+WITH
+    TIME ZONE,
+ADD COLUMN IF NOT EXISTS totp_last_used_step BIGINT;
+
+-- Create MFA recovery codes table
+CREATE TABLE
+    IF NOT EXISTS mfa_recovery_codes (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        code_hash TEXT NOT NULL,
+        used_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_mfa_recovery_codes_user_id ON mfa_recovery_codes (user_id);
@@ -0,0 +1,4 @@
+-- Remember which app a refresh token family was issued for, so refreshed access
+-- tokens keep the same audience
+ALTER TABLE refresh_tokens
+ADD COLUMN IF NOT EXISTS audience TEXT;
@@ -0,0 +1,85 @@
+-- Create roles table
+CREATE TABLE
+    IF NOT EXISTS roles (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        name VARCHAR(100) UNIQUE NOT NULL,
+        description TEXT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+-- Create permissions table; names follow the "resource:action" convention
+CREATE TABLE
+    IF NOT EXISTS permissions (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        name VARCHAR(100) UNIQUE NOT NULL,
+        description TEXT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE TABLE
+    IF NOT EXISTS role_permissions (
+        role_id UUID NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+        permission_id UUID NOT NULL REFERENCES permissions (id) ON DELETE CASCADE,
+        PRIMARY KEY (role_id, permission_id)
+    );
+
+CREATE TABLE
+    IF NOT EXISTS user_roles (
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        role_id UUID NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW (),
+            PRIMARY KEY (user_id, role_id)
+    );
+
+CREATE INDEX IF NOT EXISTS idx_user_roles_role_id ON user_roles (role_id);
+
+-- Seed the built-in roles and permissions
+INSERT INTO
+    roles (name, description)
+VALUES
+    ('admin', 'Full access, including role management'),
+    ('member', 'Default role for new accounts')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO
+    permissions (name, description)
+VALUES
+    ('products:read', 'List products'),
+    ('products:write', 'Create products'),
+    ('roles:manage', 'Grant and revoke user roles')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO
+    role_permissions (role_id, permission_id)
+SELECT
+    roles.id,
+    permissions.id
+FROM
+    roles
+    CROSS JOIN permissions
+WHERE
+    roles.name = 'admin'
+    OR (
+        roles.name = 'member'
+        AND permissions.name = 'products:read'
+    )
+ON CONFLICT DO NOTHING;
+
+-- Existing accounts become members
+INSERT INTO
+    user_roles (user_id, role_id)
+SELECT
+    users.id,
+    roles.id
+FROM
+    users
+    CROSS JOIN roles
+WHERE
+    roles.name = 'member'
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,13 @@
+-- Create products table
+CREATE TABLE
+    IF NOT EXISTS products (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        name VARCHAR(255) NOT NULL,
+        price DOUBLE PRECISION NOT NULL,
+        created_by UUID REFERENCES users (id) ON DELETE SET NULL,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_products_created_at ON products (created_at);
@@ -0,0 +1,109 @@
+-- Create organizations table
+CREATE TABLE
+    IF NOT EXISTS organizations (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        name VARCHAR(255) NOT NULL,
+        created_by UUID REFERENCES users (id) ON DELETE SET NULL,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW (),
+            updated_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+-- Roles held within an organization. They are kept apart from the global roles in
+-- user_roles so that an organization role never grants anything outside its organization.
+CREATE TABLE
+    IF NOT EXISTS organization_roles (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        name VARCHAR(50) UNIQUE NOT NULL,
+        description TEXT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE TABLE
+    IF NOT EXISTS organization_role_permissions (
+        role_id UUID NOT NULL REFERENCES organization_roles (id) ON DELETE CASCADE,
+        permission_id UUID NOT NULL REFERENCES permissions (id) ON DELETE CASCADE,
+        PRIMARY KEY (role_id, permission_id)
+    );
+
+-- Memberships carry a per-organization role
+CREATE TABLE
+    IF NOT EXISTS organization_members (
+        organization_id UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        role_id UUID NOT NULL REFERENCES organization_roles (id),
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW (),
+            PRIMARY KEY (organization_id, user_id)
+    );
+
+CREATE INDEX IF NOT EXISTS idx_organization_members_user_id ON organization_members (user_id);
+
+-- The organization a sign-in is working in, kept across refreshes
+ALTER TABLE refresh_tokens
+ADD COLUMN IF NOT EXISTS organization_id UUID REFERENCES organizations (id) ON DELETE SET NULL;
+
+-- Products belong to an organization. Rows created before organizations existed have
+-- none and are no longer visible through the API.
+ALTER TABLE products
+ADD COLUMN IF NOT EXISTS organization_id UUID REFERENCES organizations (id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS idx_products_organization_id ON products (organization_id);
+
+-- Organization roles and permissions
+INSERT INTO
+    organization_roles (name, description)
+VALUES
+    ('owner', 'Full control of an organization'),
+    ('member', 'Default role for invited members')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO
+    permissions (name, description)
+VALUES
+    ('members:read', 'List organization members'),
+    ('members:manage', 'Add and remove organization members')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO
+    organization_role_permissions (role_id, permission_id)
+SELECT
+    organization_roles.id,
+    permissions.id
+FROM
+    organization_roles
+    CROSS JOIN permissions
+WHERE
+    (
+        organization_roles.name = 'owner'
+        AND permissions.name IN (
+            'products:read',
+            'products:write',
+            'members:read',
+            'members:manage'
+        )
+    )
+    OR (
+        organization_roles.name = 'member'
+        AND permissions.name IN ('products:read', 'members:read')
+    )
+ON CONFLICT DO NOTHING;
+
+-- Admins hold every permission, including the new ones
+INSERT INTO
+    role_permissions (role_id, permission_id)
+SELECT
+    roles.id,
+    permissions.id
+FROM
+    roles
+    CROSS JOIN permissions
+WHERE
+    roles.name = 'admin'
+ON CONFLICT DO NOTHING;
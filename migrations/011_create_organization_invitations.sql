@@ -0,0 +1,24 @@
+-- Create organization invitations table
+CREATE TABLE
+    IF NOT EXISTS organization_invitations (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        organization_id UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+        email VARCHAR(255) NOT NULL,
+        role_id UUID NOT NULL REFERENCES organization_roles (id),
+        token_hash TEXT UNIQUE NOT NULL,
+        invited_by UUID REFERENCES users (id) ON DELETE SET NULL,
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            accepted_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            revoked_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_organization_invitations_organization_id ON organization_invitations (organization_id);
@@ -0,0 +1,14 @@
+-- Failed signin attempts, keyed by "account:<email>" or "ip:<address>"
+CREATE TABLE
+    IF NOT EXISTS login_attempts (
+        key TEXT PRIMARY KEY,
+        failed_count INTEGER NOT NULL DEFAULT 0,
+        last_failed_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW (),
+            locked_until TIMESTAMP
+        WITH
+            TIME ZONE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_login_attempts_last_failed_at ON login_attempts (last_failed_at);
@@ -0,0 +1,32 @@
+-- Email address changes, applied only once the new address is confirmed
+CREATE TABLE
+    IF NOT EXISTS email_change_requests (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        old_email VARCHAR(255) NOT NULL,
+        new_email VARCHAR(255) NOT NULL,
+        token_hash TEXT UNIQUE NOT NULL,
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            confirmed_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            -- Superseded by a newer request before being confirmed
+            cancelled_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            -- Sent to the old address once the change is applied
+            undo_token_hash TEXT UNIQUE,
+            undo_expires_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            undone_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_email_change_requests_user_id ON email_change_requests (user_id);
@@ -0,0 +1,6 @@
+-- Editable profile fields, all optional
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS display_name VARCHAR(100),
+ADD COLUMN IF NOT EXISTS avatar_url TEXT,
+ADD COLUMN IF NOT EXISTS locale VARCHAR(35),
+ADD COLUMN IF NOT EXISTS timezone VARCHAR(64);
@@ -0,0 +1,28 @@
+-- Accounts scheduled for deletion stay restorable until purge_after, then are removed
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP
+WITH
+    TIME ZONE,
+ADD COLUMN IF NOT EXISTS purge_after TIMESTAMP
+WITH
+    TIME ZONE;
+
+CREATE INDEX IF NOT EXISTS idx_users_purge_after ON users (purge_after)
+WHERE
+    purge_after IS NOT NULL;
+
+-- Security-relevant account activity, included in the user's data export
+CREATE TABLE
+    IF NOT EXISTS audit_events (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        event TEXT NOT NULL,
+        ip_address TEXT,
+        user_agent TEXT,
+        metadata JSONB NOT NULL DEFAULT '{}',
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_audit_events_user_id ON audit_events (user_id, created_at);
@@ -0,0 +1,54 @@
+-- One row per sign-in, shared by every refresh token in its family (family_id = sessions.id)
+CREATE TABLE
+    IF NOT EXISTS sessions (
+        id UUID PRIMARY KEY,
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        audience TEXT,
+        user_agent TEXT,
+        ip_address TEXT,
+        access_token_jti TEXT, -- latest access token used in the session
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW (),
+            last_seen_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW (),
+            expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            revoked_at TIMESTAMP
+        WITH
+            TIME ZONE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions (user_id, last_seen_at);
+
+-- Existing refresh token families become sessions without device details
+INSERT INTO
+    sessions (
+        id,
+        user_id,
+        audience,
+        created_at,
+        last_seen_at,
+        expires_at,
+        revoked_at
+    )
+SELECT
+    family_id,
+    user_id,
+    MAX(audience),
+    MIN(created_at),
+    MAX(created_at),
+    MAX(expires_at),
+    CASE
+        WHEN BOOL_AND(revoked_at IS NOT NULL) THEN MAX(revoked_at)
+    END
+FROM
+    refresh_tokens
+GROUP BY
+    family_id,
+    user_id ON CONFLICT (id) DO NOTHING;
+
+ALTER TABLE refresh_tokens
+ADD CONSTRAINT fk_refresh_tokens_session FOREIGN KEY (family_id) REFERENCES sessions (id) ON DELETE CASCADE;
@@ -0,0 +1,27 @@
+-- Personal API keys for machine clients; only a hash of the secret is stored
+CREATE TABLE
+    IF NOT EXISTS api_keys (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        -- Organization the key works in, like the active organization of a sign-in
+        organization_id UUID REFERENCES organizations (id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        -- First characters of the key, shown so users can tell their keys apart
+        prefix TEXT NOT NULL,
+        key_hash TEXT UNIQUE NOT NULL,
+        scopes TEXT[] NOT NULL DEFAULT '{}',
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            last_used_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            revoked_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_user_id ON api_keys (user_id);
@@ -0,0 +1,38 @@
+-- Pending "Sign in with ..." attempts, looked up by the state parameter on the way back
+CREATE TABLE
+    IF NOT EXISTS oidc_login_states (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        state_hash TEXT UNIQUE NOT NULL,
+        provider TEXT NOT NULL,
+        nonce TEXT NOT NULL,
+        code_verifier TEXT NOT NULL, -- PKCE secret, needed to redeem the authorization code
+        redirect_uri TEXT NOT NULL,
+        audience TEXT NOT NULL,
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_oidc_login_states_expires_at ON oidc_login_states (expires_at);
+
+-- Accounts at external identity providers, each linked to one user
+CREATE TABLE
+    IF NOT EXISTS user_identities (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        provider TEXT NOT NULL,
+        subject TEXT NOT NULL, -- the provider's stable user id, the ID token's sub
+        email TEXT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW (),
+            last_signin_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW (),
+            UNIQUE (provider, subject)
+    );
+
+CREATE INDEX IF NOT EXISTS idx_user_identities_user_id ON user_identities (user_id);
@@ -0,0 +1,61 @@
+-- Apps that sign users in with their accounts here, acting as OpenID Connect relying parties
+CREATE TABLE
+    IF NOT EXISTS oidc_clients (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        client_id TEXT UNIQUE NOT NULL,
+        name TEXT NOT NULL,
+        -- NULL for public clients (single page and native apps), which rely on PKCE alone
+        client_secret_hash TEXT,
+        redirect_uris TEXT[] NOT NULL,
+        revoked_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+-- Codes handed to a client's redirect URI, redeemed once at the token endpoint
+CREATE TABLE
+    IF NOT EXISTS oidc_authorization_codes (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        code_hash TEXT UNIQUE NOT NULL,
+        client_id TEXT NOT NULL REFERENCES oidc_clients (client_id) ON DELETE CASCADE,
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        redirect_uri TEXT NOT NULL,
+        scopes TEXT[] NOT NULL,
+        nonce TEXT,
+        code_challenge TEXT NOT NULL, -- PKCE S256 challenge
+        auth_time TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_oidc_authorization_codes_expires_at ON oidc_authorization_codes (expires_at);
+
+-- Opaque access tokens issued to clients, only good for the userinfo endpoint
+CREATE TABLE
+    IF NOT EXISTS oidc_access_tokens (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        token_hash TEXT UNIQUE NOT NULL,
+        client_id TEXT NOT NULL REFERENCES oidc_clients (client_id) ON DELETE CASCADE,
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        scopes TEXT[] NOT NULL,
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            revoked_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_oidc_access_tokens_user_id ON oidc_access_tokens (user_id);
@@ -0,0 +1,22 @@
+-- Passwordless sign-in links; only hashes of the link token and the device nonce are stored
+CREATE TABLE
+    IF NOT EXISTS magic_links (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        email TEXT NOT NULL, -- the address the link was sent to
+        token_hash TEXT UNIQUE NOT NULL,
+        -- The nonce handed to the device that asked for the link, which must present it
+        device_nonce_hash TEXT NOT NULL,
+        audience TEXT NOT NULL,
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            used_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_magic_links_user_id ON magic_links (user_id);
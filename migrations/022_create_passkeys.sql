@@ -0,0 +1,38 @@
+-- WebAuthn credentials (passkeys) users registered for signing in
+CREATE TABLE
+    IF NOT EXISTS passkeys (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        credential_id TEXT UNIQUE NOT NULL, -- base64url, as the browser reports it
+        public_key BYTEA NOT NULL, -- COSE_Key from the attested credential data
+        sign_count BIGINT NOT NULL DEFAULT 0,
+        name TEXT NOT NULL,
+        transports TEXT[] NOT NULL DEFAULT '{}',
+        last_used_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_passkeys_user_id ON passkeys (user_id);
+
+-- Challenges of registration and authentication ceremonies in progress, each used once
+CREATE TABLE
+    IF NOT EXISTS webauthn_challenges (
+        id UUID PRIMARY KEY DEFAULT uuid_generate_v4 (),
+        challenge_hash TEXT UNIQUE NOT NULL,
+        purpose TEXT NOT NULL, -- 'registration' or 'authentication'
+        -- Registering user, or the user finishing MFA; NULL for a passkey-first sign-in
+        user_id UUID REFERENCES users (id) ON DELETE CASCADE,
+        audience TEXT,
+        expires_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW ()
+    );
+
+CREATE INDEX IF NOT EXISTS idx_webauthn_challenges_expires_at ON webauthn_challenges (expires_at);
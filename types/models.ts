@@ -2,14 +2,130 @@
 
 export interface Product { id: string, name: string, price: number, }
 
-export interface User { id: string, email: string, username: string, created_at: string, updated_at: string, }
+export interface CreateProductRequest { name: string, price: number, }
 
-export interface CreateUserRequest { email: string, username: string, password: string, }
+export interface User { id: string, email: string, username: string, display_name: string | null, avatar_url: string | null, locale: string | null, timezone: string | null, email_verified_at: string | null, created_at: string, updated_at: string, }
 
-export interface LoginRequest { email: string, password: string, }
+export interface UpdateProfileRequest { username: string | null, display_name: string | null, avatar_url: string | null, locale: string | null, timezone: string | null, }
 
-export interface AuthResponse { user: User, token: string, }
+export interface DeleteAccountRequest { password: string | null, }
+
+export interface DeleteAccountResponse { message: string, purge_after: string, }
+
+export interface ExportedSession { id: string, audience: string | null, user_agent: string | null, ip_address: string | null, created_at: string, last_seen_at: string, expires_at: string, revoked_at: string | null, }
+
+export interface AuditEvent { id: string, event: string, ip_address: string | null, user_agent: string | null, metadata: Record<string, unknown>, created_at: string, }
+
+export interface AccountExport { exported_at: string, user: User, mfa_enabled: boolean, roles: Array<string>, organizations: Array<Organization>, sessions: Array<ExportedSession>, passkeys: Array<Passkey>, audit_events: Array<AuditEvent>, }
+
+export interface CreateUserRequest { email: string, username: string, password: string, audience: string | null, invite_token: string | null, }
+
+export interface LoginRequest { email: string, password: string, audience: string | null, }
+
+export interface AuthResponse { user: User, token: string, refresh_token: string, expires_in: number, }
+
+export interface RefreshRequest { refresh_token: string, }
+
+export interface SignoutRequest { refresh_token: string | null, all_sessions: boolean | null, }
+
+export interface Session { id: string, audience: string | null, user_agent: string | null, ip_address: string | null, created_at: string, last_seen_at: string, expires_at: string, current: boolean, }
+
+export interface RevokeSessionRequest { session_id: string | null, all_others: boolean | null, }
+
+export interface ApiKey { id: string, name: string, prefix: string, scopes: Array<string>, organization_id: string | null, expires_at: string | null, last_used_at: string | null, created_at: string, }
+
+export interface CreateApiKeyRequest { name: string, scopes: Array<string>, expires_in_days: number | null, }
+
+export interface CreateApiKeyResponse { api_key: ApiKey, key: string, }
+
+export interface RevokeApiKeyRequest { api_key_id: string, }
+
+export interface MfaChallengeResponse { mfa_required: boolean, mfa_token: string, expires_in: number, methods: Array<string>, }
+
+export interface MfaVerifyRequest { mfa_token: string, code: string, }
+
+export interface MfaCodeRequest { code: string, }
+
+export interface TotpSetupResponse { secret: string, otpauth_uri: string, }
+
+export interface RecoveryCodesResponse { recovery_codes: Array<string>, }
+
+export interface Passkey { id: string, name: string, transports: Array<string>, last_used_at: string | null, created_at: string, }
+
+export interface PasskeyAttestation { id: string, response: PasskeyAttestationResponse, }
+
+export interface PasskeyAttestationResponse { clientDataJSON: string, attestationObject: string, transports: Array<string>, }
+
+export interface PasskeyAssertion { id: string, response: PasskeyAssertionResponse, }
+
+export interface PasskeyAssertionResponse { clientDataJSON: string, authenticatorData: string, signature: string, userHandle: string | null, }
+
+export interface RegisterPasskeyRequest { name: string | null, credential: PasskeyAttestation, }
+
+export interface RemovePasskeyRequest { passkey_id: string, }
+
+export interface PasskeyAuthenticationOptionsRequest { audience: string | null, mfa_token: string | null, }
+
+export interface PasskeyAuthenticationRequest { credential: PasskeyAssertion, }
+
+export interface OidcAuthorizeRequest { provider: string, audience: string | null, }
+
+export interface OidcAuthorizeResponse { authorization_url: string, }
+
+export interface OidcCallbackRequest { state: string, code: string, }
+
+export interface MagicLinkRequest { email: string, audience: string | null, }
+
+export interface MagicLinkResponse { message: string, device_nonce: string, }
+
+export interface MagicLinkVerifyRequest { token: string, device_nonce: string, }
+
+export interface OidcUserInfo { sub: string, email?: string, email_verified?: boolean, name?: string, preferred_username?: string, picture?: string, locale?: string, zoneinfo?: string, }
+
+export interface OidcTokenResponse { access_token: string, token_type: string, expires_in: number, id_token: string, scope: string, }
+
+export interface OAuthErrorResponse { error: string, error_description: string, }
+
+export interface ForgotPasswordRequest { email: string, }
+
+export interface ResetPasswordRequest { token: string, new_password: string, }
+
+export interface ChangePasswordRequest { current_password: string, new_password: string, }
+
+export interface ChangeEmailRequest { new_email: string, current_password: string, }
+
+export interface ConfirmEmailChangeRequest { token: string, }
+
+export interface UndoEmailChangeRequest { token: string, }
+
+export interface VerifyEmailRequest { token: string, }
+
+export interface ResendVerificationRequest { email: string, }
+
+export interface Organization { id: string, name: string, role: string, created_at: string, }
+
+export interface OrganizationMember { user_id: string, email: string, username: string, role: string, joined_at: string, }
+
+export interface CreateOrganizationRequest { name: string, }
+
+export interface SwitchOrganizationRequest { organization_id: string, refresh_token: string, }
+
+export interface Invitation { id: string, organization_id: string, email: string, role: string, expires_at: string, created_at: string, }
+
+export interface CreateInvitationRequest { email: string, role: string | null, }
+
+export interface RevokeInvitationRequest { invitation_id: string, }
+
+export interface AcceptInvitationRequest { token: string, }
+
+export interface UserRoleRequest { user_id: string, role: string, }
+
+export interface MessageResponse { message: string, }
 
 export interface ApiError { message: string, code: number, }
 
-export interface Claims { sub: string, email: string, exp: number, iat: number, }
\ No newline at end of file
+export interface PasswordViolation { rule: string, message: string, }
+
+export interface PasswordPolicyError { message: string, code: number, violations: Array<PasswordViolation>, }
+
+export interface Claims { sub: string, email: string, email_verified: boolean, iss: string, aud: string, exp: number, nbf: number, iat: number, jti: string, sid: string | null, auth_time: number | null, roles: Array<string>, permissions: Array<string>, org: string | null, org_role: string | null, }
\ No newline at end of file